@@ -0,0 +1,1264 @@
+use crate::approvals::{self, Approvals};
+use crate::audit;
+use crate::calc;
+use crate::conversation::ConversationState;
+use crate::count;
+use crate::jobs::Jobs;
+use crate::kube;
+use crate::lsp::LanguageServers;
+use crate::outline;
+use crate::preview;
+use crate::reload::ConfigWatcher;
+use crate::style::{self, epaint, paint, Style};
+use crate::sysinfo;
+use crate::testrun;
+use crate::{perform_unfiltered_request, Settings};
+use dialoguer::Select;
+use serde_json::Value;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command as ProcessCommand, ExitStatus};
+
+pub struct AgentOptions {
+    pub read_only: bool,
+    pub sandbox: String, //"none", "docker", "podman" or "bwrap"
+    pub target: Option<Target>,
+}
+
+// A remote machine commands run on instead of this one, from `--target ssh://user@host:port`
+pub struct Target {
+    pub destination: String, //user@host, as ssh takes it
+    pub port: Option<u16>,
+}
+
+impl Target {
+    pub fn parse(url: &str) -> Result<Target, String> {
+        let rest = url
+            .strip_prefix("ssh://")
+            .ok_or_else(|| format!("Unsupported target '{}', expected ssh://[user@]host[:port]", url))?;
+        let rest = rest.trim_end_matches('/');
+        let (destination, port) = match rest.rsplit_once(':') {
+            Some((destination, port)) => {
+                let port = port.parse().map_err(|_| format!("Invalid port in target '{}'", url))?;
+                (destination, Some(port))
+            }
+            None => (rest, None),
+        };
+        if destination.is_empty() || destination.ends_with('@') {
+            return Err(format!("Target '{}' has no host", url));
+        }
+        Ok(Target {
+            destination: destination.to_string(),
+            port,
+        })
+    }
+}
+
+// " on user@host" for messages about commands, empty when they run here
+fn location(options: &AgentOptions) -> String {
+    options
+        .target
+        .as_ref()
+        .map(|target| format!(" on {}", target.destination))
+        .unwrap_or_default()
+}
+
+// Programs allowed in read-only mode. Each only inspects state (given the flags checked below).
+const READ_ONLY_PROGRAMS: &[&str] = &[
+    "ls", "cat", "head", "tail", "grep", "egrep", "fgrep", "rg", "find", "fd", "wc", "stat",
+    "file", "du", "df", "ps", "pgrep", "uname", "whoami", "id", "pwd", "echo", "printenv",
+    "which", "type", "tree", "sort", "uniq", "cut", "tr", "date", "uptime", "free", "lsblk",
+    "lscpu", "hostname", "jq", "diff", "cmp", "md5sum", "sha1sum", "sha256sum", "readlink",
+    "realpath", "basename", "dirname", "nl", "column", "strings", "journalctl", "dmesg",
+];
+
+// Subcommands of git that never write
+const READ_ONLY_GIT: &[&str] = &[
+    "status", "log", "diff", "show", "blame", "ls-files", "rev-parse", "describe", "shortlog",
+    "grep", "branch", "tag", "remote",
+];
+
+// Flags that make an otherwise harmless program write, execute or change state
+const MUTATING_FLAGS: &[(&str, &str)] = &[
+    ("find", "-delete"),
+    ("find", "-exec"),
+    ("find", "-ok"),
+    ("find", "-fprint"),
+    ("find", "-fls"),
+    ("sort", "-o"),
+    ("sort", "--output"),
+    ("rg", "--pre"),
+    ("tree", "-o"),
+    ("journalctl", "--vacuum"),
+    ("journalctl", "--rotate"),
+    ("journalctl", "--flush"),
+    ("journalctl", "--sync"),
+    ("journalctl", "--relinquish-var"),
+    ("journalctl", "--smart-relinquish-var"),
+    ("journalctl", "--setup-keys"),
+    ("journalctl", "--update-catalog"),
+    ("dmesg", "-c"),
+    ("dmesg", "-C"),
+    ("dmesg", "--clear"),
+    ("dmesg", "--read-clear"),
+    ("dmesg", "-D"),
+    ("dmesg", "-E"),
+    ("dmesg", "-n"),
+    ("dmesg", "--console-off"),
+    ("dmesg", "--console-on"),
+    ("dmesg", "--console-level"),
+    ("file", "-C"),
+    ("file", "--compile"),
+    ("date", "-s"),
+    ("date", "--set"),
+    ("git", "--output"),
+    ("git", "-O"),
+    ("git", "--open-files-in-pager"),
+];
+
+// The only forms of git branch/tag/remote that don't write: their listing flags, then those
+// taking a value (`--contains=<commit>` or the next word). Branch and tag patterns are only
+// allowed after `--list`, elsewhere a name creates something.
+const GIT_LISTING_FLAGS: &[(&str, &[&str], &[&str])] = &[
+    (
+        "branch",
+        &[
+            "-l", "--list", "-a", "--all", "-r", "--remotes", "-v", "-vv", "--verbose", "-i",
+            "--ignore-case", "--show-current", "--color", "--no-color", "--column", "--no-column",
+            "--omit-empty",
+        ],
+        &["--contains", "--no-contains", "--merged", "--no-merged", "--points-at", "--sort", "--format"],
+    ),
+    (
+        "tag",
+        &[
+            "-l", "--list", "-n", "-i", "--ignore-case", "--color", "--no-color", "--column",
+            "--no-column", "--omit-empty",
+        ],
+        &["--contains", "--no-contains", "--merged", "--no-merged", "--points-at", "--sort", "--format"],
+    ),
+    ("remote", &["-v", "--verbose"], &[]),
+];
+
+fn check_git_listing(subcommand: &str, arguments: &[&str]) -> Result<(), String> {
+    let Some((_, flags, valued)) = GIT_LISTING_FLAGS.iter().find(|(name, _, _)| *name == subcommand) else {
+        return Ok(());
+    };
+    let letters: Vec<char> = flags
+        .iter()
+        .filter_map(|flag| flag.strip_prefix('-').filter(|letter| letter.len() == 1))
+        .filter_map(|letter| letter.chars().next())
+        .collect();
+    let listing = arguments.iter().any(|argument| {
+        *argument == "--list" || (argument.starts_with('-') && !argument.starts_with("--") && argument.contains('l'))
+    });
+
+    let mut arguments = arguments.iter();
+    while let Some(argument) = arguments.next() {
+        let name = argument.split('=').next().unwrap_or_default();
+        let allowed = if valued.contains(&name) {
+            if !argument.contains('=') {
+                arguments.next();
+            }
+            true
+        } else if flags.contains(&name) {
+            true
+        } else if let Some(cluster) = argument.strip_prefix('-').filter(|cluster| !cluster.starts_with('-')) {
+            // `-av`, or `-n5` for tag annotations
+            (subcommand == "tag" && cluster.strip_prefix('n').is_some_and(|n| n.chars().all(|c| c.is_ascii_digit())))
+                || (!cluster.is_empty() && cluster.chars().all(|c| letters.contains(&c)))
+        } else {
+            !argument.starts_with('-') && listing && subcommand != "remote"
+        };
+        if !allowed {
+            return Err(format!("'git {} {}' may modify the repository", subcommand, argument));
+        }
+    }
+    Ok(())
+}
+
+// Arguments that aren't flags, skipping the values of the `valued` flags given apart
+fn positionals<'a>(arguments: &[&'a str], valued: &[&str]) -> Vec<&'a str> {
+    let mut found = vec![];
+    let mut arguments = arguments.iter();
+    while let Some(argument) = arguments.next() {
+        if valued.contains(argument) {
+            arguments.next();
+        } else if !argument.starts_with('-') {
+            found.push(*argument);
+        }
+    }
+    found
+}
+
+// Whether any argument sets `flag`, however it is spelled: short flags inside a cluster
+// (`-uo` holds `-o`), long flags with `=value` or abbreviated the way getopt allows (`--out`).
+// Single-dash long flags like find's `-delete` only match from the start of the word.
+fn has_flag(arguments: &[&str], flag: &str) -> bool {
+    arguments.iter().any(|argument| {
+        if let Some(name) = flag.strip_prefix("--") {
+            let Some(given) = argument.strip_prefix("--") else {
+                return false;
+            };
+            let given = given.split('=').next().unwrap_or_default();
+            !given.is_empty() && (name.starts_with(given) || given.starts_with(name))
+        } else if flag.len() == 2 {
+            argument.starts_with('-')
+                && !argument.starts_with("--")
+                && argument[1..].contains(&flag[1..])
+        } else {
+            argument.starts_with(flag)
+        }
+    })
+}
+
+// Checks that every stage of a pipeline runs an allowlisted program with harmless arguments
+fn check_read_only(command: &str) -> Result<(), String> {
+    // Discarding output is fine, writing it anywhere else is not
+    let stripped = command
+        .replace("2>&1", "")
+        .replace("2>/dev/null", "")
+        .replace(">/dev/null", "");
+    for forbidden in [">", "`", "$(", "<(", "&", "\n"] {
+        let found = if forbidden == "&" {
+            stripped.replace("&&", "").contains('&')
+        } else {
+            stripped.contains(forbidden)
+        };
+        if found {
+            return Err(format!("'{}' is not allowed in read-only mode", forbidden));
+        }
+    }
+
+    let segments = stripped
+        .split("&&")
+        .flat_map(|s| s.split("||"))
+        .flat_map(|s| s.split(';'))
+        .flat_map(|s| s.split('|'));
+
+    for segment in segments {
+        let words: Vec<&str> = segment.split_whitespace().collect();
+        let program = match words.first() {
+            Some(program) => *program,
+            None => continue,
+        };
+
+        if program == "git" {
+            let subcommand = words.get(1).copied().unwrap_or("");
+            if !READ_ONLY_GIT.contains(&subcommand) {
+                return Err(format!("'git {}' may modify the repository", subcommand));
+            }
+            check_git_listing(subcommand, &words[2..])?;
+        } else if !READ_ONLY_PROGRAMS.contains(&program) {
+            return Err(format!("'{}' is not on the read-only allowlist", program));
+        }
+        for (flagged_program, flag) in MUTATING_FLAGS {
+            if program == *flagged_program && has_flag(&words[1..], flag) {
+                return Err(format!("'{} {}' may modify the system", program, flag));
+            }
+        }
+        // `uniq in out` writes its output to the second file
+        if program == "uniq" && positionals(&words[1..], &["-f", "-s", "-w"]).len() > 1 {
+            return Err("'uniq' with an output file may modify the system".to_string());
+        }
+        // `hostname <name>` renames the machine
+        if program == "hostname" && words.iter().skip(1).any(|w| !w.starts_with('-')) {
+            return Err("'hostname' with arguments may modify the system".to_string());
+        }
+    }
+
+    Ok(())
+}
+
+// `~` and `~/...` come from HOME, `~name/...` from that user's passwd entry. Anything else, a
+// user without an entry included, is left as it is.
+fn expand_tilde(path: &str) -> PathBuf {
+    let Some(rest) = path.strip_prefix('~') else {
+        return PathBuf::from(path);
+    };
+    let (user, rest) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+    let home = if user.is_empty() { env::var("HOME").ok() } else { home_of(user) };
+    match home {
+        Some(home) => PathBuf::from(format!("{}{}", home, rest)),
+        None => PathBuf::from(path),
+    }
+}
+
+fn home_of(user: &str) -> Option<String> {
+    let passwd = fs::read_to_string("/etc/passwd").ok()?;
+    passwd.lines().find_map(|line| {
+        let fields: Vec<&str> = line.split(':').collect();
+        (fields.len() > 5 && fields[0] == user).then(|| fields[5].to_string())
+    })
+}
+
+// Resolves symlinks and `..`. Paths that don't exist yet resolve through their closest
+// existing ancestor, so `touch /etc/new` is caught too.
+fn resolve(path: &Path) -> PathBuf {
+    let absolute = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        env::current_dir().unwrap_or_default().join(path)
+    };
+
+    let mut existing = absolute.as_path();
+    let mut missing = vec![];
+    while existing.canonicalize().is_err() {
+        match (existing.parent(), existing.file_name()) {
+            (Some(parent), Some(name)) => {
+                missing.push(name.to_os_string());
+                existing = parent;
+            }
+            _ => return absolute,
+        }
+    }
+
+    let mut resolved = existing.canonicalize().unwrap_or_else(|_| existing.to_path_buf());
+    for name in missing.iter().rev() {
+        resolved.push(name);
+    }
+    resolved
+}
+
+// `*` matches any run of characters, everything else is literal
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let parts: Vec<&str> = pattern.split('*').collect();
+    if parts.len() == 1 {
+        return pattern == text;
+    }
+
+    let mut rest = match text.strip_prefix(parts[0]) {
+        Some(rest) => rest,
+        None => return false,
+    };
+    for part in &parts[1..parts.len() - 1] {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(parts[parts.len() - 1])
+}
+
+pub fn is_protected(path: &Path, protected_paths: &[String]) -> Option<String> {
+    let resolved = resolve(path);
+    let resolved_text = resolved.display().to_string();
+
+    protected_paths.iter().find_map(|protected| {
+        let hit = if protected.contains('*') {
+            glob_match(&expand_tilde(protected).display().to_string(), &resolved_text)
+        } else {
+            resolved.starts_with(resolve(&expand_tilde(protected)))
+        };
+        hit.then(|| protected.clone())
+    })
+}
+
+// The simple commands of a shell command line (split at | ; & ( ) and backticks), as the words
+// sh would pass on: quotes are removed and join what they touch (`/e''tc` is `/etc`), and
+// `$VAR`, `${VAR}` and a leading `~` or `~name` are expanded from the environment. Redirection operators
+// end a word, so `cat</etc/passwd` yields the path too.
+fn shell_words(command: &str) -> Vec<Vec<String>> {
+    let mut segments: Vec<Vec<String>> = vec![vec![]];
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quote: Option<char> = None;
+    let mut chars = command.chars().peekable();
+
+    let finish = |segments: &mut Vec<Vec<String>>, word: &mut String, in_word: &mut bool| {
+        if *in_word {
+            let expanded = match word.starts_with('~') {
+                true => expand_tilde(word).display().to_string(),
+                false => word.clone(),
+            };
+            segments.last_mut().unwrap().push(expanded);
+        }
+        word.clear();
+        *in_word = false;
+    };
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some('\''), '\'') => quote = None,
+            (Some('\''), _) => word.push(c),
+            (Some('"'), '"') => quote = None,
+            (_, '\\') => {
+                if let Some(escaped) = chars.next() {
+                    word.push(escaped);
+                }
+                in_word = true;
+            }
+            (_, '$') => {
+                let braced = chars.next_if_eq(&'{').is_some();
+                let mut name = String::new();
+                while let Some(c) = chars.next_if(|c| c.is_ascii_alphanumeric() || *c == '_') {
+                    name.push(c);
+                }
+                if braced {
+                    // `${VAR:-default}` and the like expand to VAR here, the rest is dropped
+                    for c in chars.by_ref() {
+                        if c == '}' {
+                            break;
+                        }
+                    }
+                }
+                if name.is_empty() && !braced {
+                    word.push('$');
+                } else {
+                    word.push_str(&env::var(&name).unwrap_or_default());
+                }
+                in_word = true;
+            }
+            (Some(_), _) => word.push(c),
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                in_word = true;
+            }
+            (None, c) if c.is_whitespace() || "<>".contains(c) => finish(&mut segments, &mut word, &mut in_word),
+            (None, c) if "|;&()`".contains(c) => {
+                finish(&mut segments, &mut word, &mut in_word);
+                segments.push(vec![]);
+            }
+            (None, _) => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+    finish(&mut segments, &mut word, &mut in_word);
+    segments.retain(|words| !words.is_empty());
+    segments
+}
+
+// Refuses commands that mention a protected path, whatever the user would approve. This reads
+// the command the way sh would, but it is best effort, not a guarantee: a path assembled at
+// run time (`D=/etc; cat $D/passwd`, `cat $(echo /etc)/passwd`) is not seen.
+fn check_protected_paths(command: &str, protected_paths: &[String]) -> Result<(), String> {
+    if protected_paths.is_empty() {
+        return Ok(());
+    }
+    let project = env::current_dir().unwrap_or_default();
+    check_protected_words(command, &project, protected_paths)
+}
+
+// Relative paths are resolved against the directory the earlier `cd`s, `pushd`s and `popd`s left
+// the command in.
+fn check_protected_words(command: &str, dir: &Path, protected_paths: &[String]) -> Result<(), String> {
+    let mut dir = dir.to_path_buf();
+    let mut previous: Vec<PathBuf> = vec![];
+    for words in shell_words(command) {
+        if matches!(words[0].as_str(), "cd" | "pushd" | "popd") {
+            let target = words.iter().skip(1).find(|word| !word.starts_with('-') || *word == "-");
+            let destination = match (words[0].as_str(), target.map(String::as_str)) {
+                ("popd", _) | (_, Some("-")) => previous.pop().unwrap_or_else(|| dir.clone()),
+                (_, Some(target)) => dir.join(target),
+                (_, None) => PathBuf::from(env::var("HOME").unwrap_or_default()),
+            };
+            if let Some(protected) = is_protected(&destination, protected_paths) {
+                return Err(format!("'{}' enters the protected path {}", words.join(" "), protected));
+            }
+            if words[0] != "popd" {
+                previous.push(dir);
+            }
+            dir = resolve(&destination);
+            continue;
+        }
+
+        for word in &words {
+            // A quoted script, as in `sh -c '...'`, is checked like a command of its own
+            if word.contains(|c: char| c.is_whitespace() || ";|&".contains(c)) {
+                check_protected_words(word, &dir, protected_paths)?;
+            }
+            for candidate in word.split(|c: char| "=,:".contains(c)).filter(|w| !w.is_empty()) {
+                // `--file=~name/...` is expanded by the shell too, an unknown `~name` can't be
+                // told apart from a home directory resolved some other way (LDAP, NIS)
+                let candidate = expand_tilde(candidate).display().to_string();
+                if candidate.starts_with('~') {
+                    return Err(format!("'{}' names a home directory that can't be resolved", candidate));
+                }
+                let candidate = candidate.as_str();
+                let looks_like_path =
+                    candidate.contains('/') || candidate.starts_with('.') || dir.join(candidate).exists();
+                if !looks_like_path {
+                    continue;
+                }
+                if let Some(protected) = is_protected(&dir.join(candidate), protected_paths) {
+                    return Err(format!("'{}' is inside the protected path {}", candidate, protected));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// Wraps the command in the configured sandbox, with the working directory mounted, or sends it
+// over ssh to the target
+fn build_shell_command(command: &str, options: &AgentOptions, settings: &Settings) -> ProcessCommand {
+    wrap_command(command, None, options, settings)
+}
+
+// A background job also needs a way to stop everything it started. Killing the local process
+// group reaches sh, bwrap and their children, but not what runs in a container or over ssh: the
+// container gets a name to remove it by, and the remote command a terminal, which hangs up its
+// processes when the connection closes.
+fn build_job_command(name: &str, command: &str, options: &AgentOptions, settings: &Settings) -> (ProcessCommand, Option<ProcessCommand>) {
+    let container = format!(
+        "ask-{}-{}",
+        std::process::id(),
+        name.chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '-' }).collect::<String>()
+    );
+    let process = wrap_command(command, Some(&container), options, settings);
+    let cleanup = match options.sandbox.as_str() {
+        engine @ ("docker" | "podman") if options.target.is_none() => {
+            let mut cleanup = ProcessCommand::new(engine);
+            cleanup.args(["rm", "-f", &container]);
+            Some(cleanup)
+        }
+        _ => None,
+    };
+    (process, cleanup)
+}
+
+fn wrap_command(command: &str, container: Option<&str>, options: &AgentOptions, settings: &Settings) -> ProcessCommand {
+    if let Some(target) = &options.target {
+        // BatchMode: a password prompt would hang the agent, keys or an agent have to do
+        let mut process = ProcessCommand::new("ssh");
+        process.arg(if container.is_some() { "-tt" } else { "-T" }).args(["-o", "BatchMode=yes"]);
+        if let Some(port) = target.port {
+            process.args(["-p", &port.to_string()]);
+        }
+        process.arg(&target.destination).arg("--").arg(command);
+        return process;
+    }
+
+    let workspace = env::current_dir()
+        .map(|dir| dir.display().to_string())
+        .unwrap_or_else(|_| ".".to_string());
+
+    match options.sandbox.as_str() {
+        engine @ ("docker" | "podman") => {
+            let mut process = ProcessCommand::new(engine);
+            process
+                .args(["run", "--rm", "-i"])
+                .args(["-v", &format!("{}:/workspace", workspace)])
+                .args(["-w", "/workspace"]);
+            if let Some(container) = container {
+                // --init forwards the stop signal to the whole tree inside
+                process.args(["--init", "--name", container]);
+            }
+            if !settings.sandbox_network {
+                process.args(["--network", "none"]);
+            }
+            process.arg(&settings.sandbox_image).args(["sh", "-c", command]);
+            process
+        }
+        "bwrap" => {
+            // Read-only host, writable workspace and a private /tmp
+            let mut process = ProcessCommand::new("bwrap");
+            process
+                .args(["--ro-bind", "/", "/"])
+                .args(["--dev", "/dev", "--proc", "/proc", "--tmpfs", "/tmp"])
+                .args(["--bind", &workspace, &workspace])
+                .args(["--chdir", &workspace])
+                .arg("--die-with-parent");
+            if !settings.sandbox_network {
+                process.arg("--unshare-net");
+            }
+            process.args(["sh", "-c", command]);
+            process
+        }
+        _ => {
+            let mut process = ProcessCommand::new("sh");
+            process.arg("-c").arg(command);
+            process
+        }
+    }
+}
+
+// What the model asked for in its last reply
+enum Directive {
+    Command(String),
+    Background { name: String, command: String },
+    Poll(String),
+    Stop(String),
+    Tests(String),
+    Outline(String),
+    Lsp(String),
+    Kube(String),
+    Count(String),
+    SystemInfo,
+    Calc(String),
+}
+
+const DIRECTIVE_HELP: &str = "Format your response as: COMMAND: <command> followed by an explanation. For a command spanning several lines (a heredoc, a script) put COMMAND: alone on its line, followed by the command in a ```sh block. To start a long-running process (dev server, watcher) in the background use BACKGROUND: <name> <command>, then POLL: <name> to read its new output and STOP: <name> to terminate it. To run the project's test suite and get a structured summary use TESTS: <optional test filter>. To list the functions, types and impl blocks of a source file with their line ranges use OUTLINE: <file>. To ask the language server use LSP: definition <file>:<line>:<column>, LSP: references <file>:<line>:<column> or LSP: diagnostics <file>. For lines of code per language, file counts and the largest files of a directory use COUNT: <directory>. To inspect the Kubernetes cluster without approval use KUBECTL: get <kind> [name], KUBECTL: describe <kind> <name>, KUBECTL: logs <pod> [--previous] [-c <container>], KUBECTL: top pods or KUBECTL: events, optionally with -n <namespace>. SYSINFO: describes the OS, shell, package managers, installed toolchains, CPU and memory in one go. For exact arithmetic, unit conversion or date math use CALC: <expression> (e.g. CALC: 3.5 * 2^10, CALC: 42 km to mi, CALC: 2024-03-01 + 90 days). Or say DONE if the task is complete.";
+
+// The subset of DIRECTIVE_HELP that runs without approval, offered to --serve clients
+pub const UNATTENDED_HELP: &str = "You have tools on the machine you are answering from. To use one, reply with only its line and the result will be sent back: OUTLINE: <file> lists the functions, types and impl blocks of a source file, LSP: definition <file>:<line>:<column>, LSP: references <file>:<line>:<column> and LSP: diagnostics <file> ask the language server, COUNT: <directory> reports lines of code per language and the largest files, SYSINFO: describes the OS and installed toolchains, CALC: <expression> does exact arithmetic, unit conversion and date math. Otherwise answer normally.";
+
+// Finds the earliest directive in the reply. Each takes the rest of its line as arguments.
+fn parse_directive(response: &str) -> Option<Directive> {
+    let keywords = ["COMMAND:", "BACKGROUND:", "POLL:", "STOP:", "TESTS:", "OUTLINE:", "LSP:", "KUBECTL:", "COUNT:", "SYSINFO:", "CALC:"];
+    let (start, keyword) = keywords
+        .iter()
+        .filter_map(|keyword| response.find(keyword).map(|index| (index, *keyword)))
+        .min_by_key(|(index, _)| *index)?;
+
+    let line = response[start..].lines().next().unwrap();
+    let argument = line.trim_start_matches(keyword).trim().to_string();
+
+    match keyword {
+        // Or COMMAND: alone, followed by a ``` block holding a multi-line command
+        "COMMAND:" => {
+            let after = &response[start + line.len()..];
+            let block = if argument.starts_with("```") {
+                fence_body(after.lines())
+            } else if argument.is_empty() {
+                let mut lines = after.lines().skip_while(|line| line.trim().is_empty());
+                lines.next().filter(|line| line.trim_start().starts_with("```")).and_then(|_| fence_body(lines))
+            } else {
+                None
+            };
+            Some(Directive::Command(block.unwrap_or(argument)))
+        }
+        "BACKGROUND:" => {
+            let (name, command) = argument.split_once(char::is_whitespace)?;
+            Some(Directive::Background {
+                name: name.to_string(),
+                command: command.trim().to_string(),
+            })
+        }
+        "POLL:" => Some(Directive::Poll(argument)),
+        "STOP:" => Some(Directive::Stop(argument)),
+        "TESTS:" => Some(Directive::Tests(argument)),
+        "OUTLINE:" => Some(Directive::Outline(argument)),
+        "LSP:" => Some(Directive::Lsp(argument)),
+        "KUBECTL:" => Some(Directive::Kube(argument)),
+        "COUNT:" => Some(Directive::Count(argument)),
+        "SYSINFO:" => Some(Directive::SystemInfo),
+        _ => Some(Directive::Calc(argument)),
+    }
+}
+
+// The lines up to the closing ``` of a fence
+fn fence_body<'a>(lines: impl Iterator<Item = &'a str>) -> Option<String> {
+    let body: Vec<&str> = lines.take_while(|line| !line.trim_start().starts_with("```")).collect();
+    (!body.is_empty()).then(|| body.join("\n"))
+}
+
+// Reason a command must not run, checked before the user is even asked
+fn refusal(command: &str, options: &AgentOptions, settings: &Settings) -> Option<String> {
+    if options.read_only {
+        if let Err(reason) = check_read_only(command) {
+            return Some(format!("{}. This session is read-only. Suggest a command that only inspects the system.", reason));
+        }
+    }
+    // Protected paths name files on this machine, a remote target's are its own business
+    if options.target.is_some() {
+        return None;
+    }
+    if let Err(reason) = check_protected_paths(command, &settings.protected_paths) {
+        return Some(format!("{}. Protected paths may not be touched. Find another way that avoids them.", reason));
+    }
+    None
+}
+
+// Asks the user to approve a command, unless an earlier "always allow" covers it. Returns the
+// command to run, which the user may have edited first, or None when rejected.
+fn approve(command: &str, options: &AgentOptions, approvals: &mut Approvals, settings: &Settings) -> Option<String> {
+    if approvals.is_approved(command) {
+        println!("{} {}", paint(Style::Prompt, "Auto-approved:"), paint(Style::Command, command));
+        return Some(command.to_string());
+    }
+
+    let prefix = approvals::command_prefix(command);
+    let mut choices = vec!["Run once".to_string()];
+    if let Some(prefix) = &prefix {
+        choices.push(format!("Always allow commands starting with '{}'", prefix));
+    }
+    choices.push("Always allow exactly this command".to_string());
+    choices.push("Edit, then run".to_string());
+    choices.push("Reject".to_string());
+
+    // Heredocs and long scripts are unreadable inline, they get a numbered preview instead
+    let line_count = command.lines().count();
+    let shown = if line_count > preview::PREVIEW_LINES {
+        println!("\n{}", paint(Style::Prompt, "Proposed command:"));
+        preview::show(command, settings);
+        format!("the {} lines above", line_count)
+    } else {
+        command.to_string()
+    };
+
+    loop {
+        let choice = Select::with_theme(&*style::dialog_theme())
+            .with_prompt(format!(
+                "\n\n{} {}",
+                epaint(Style::Prompt, &format!("Run command{}:", location(options))),
+                epaint(Style::Command, &shown)
+            ))
+            .default(choices.len() - 1)
+            .items(&choices)
+            .interact()
+            .unwrap_or(choices.len() - 1);
+
+        return match (choice, &prefix) {
+            (0, _) => Some(command.to_string()),
+            (1, Some(prefix)) => {
+                approvals.allow_prefix(prefix.clone());
+                Some(command.to_string())
+            }
+            (1, None) | (2, Some(_)) => {
+                approvals.allow_exact(command.to_string());
+                Some(command.to_string())
+            }
+            (choice, _) if choice == choices.len() - 2 => match edit_command(command) {
+                Some(edited) => Some(edited),
+                None => continue, //Editor closed without saving, ask again
+            },
+            _ => None,
+        };
+    }
+}
+
+// Opens the command in $VISUAL/$EDITOR, or a line prompt when there is no editor to open
+fn edit_command(command: &str) -> Option<String> {
+    let edited = if std::env::var_os("VISUAL").is_some() || std::env::var_os("EDITOR").is_some() {
+        dialoguer::Editor::new().extension(".sh").edit(command).ok().flatten()
+    } else {
+        dialoguer::Input::<String>::with_theme(&*style::dialog_theme())
+            .with_prompt("Command")
+            .with_initial_text(command)
+            .interact_text()
+            .ok()
+    }?;
+    let edited = edited.trim();
+    (!edited.is_empty()).then(|| edited.to_string())
+}
+
+// Refusal check and approval for a command. An edited command is checked again, since the
+// user may have typed into a protected path too.
+fn review(command: &str, options: &AgentOptions, approvals: &mut Approvals, settings: &Settings) -> Result<String, Value> {
+    if let Some(reason) = refusal(command, options, settings) {
+        println!("{}", paint(Style::Warning, &format!("Refused: {}", reason)));
+        audit(command, options, settings, &format!("refused: {}", reason));
+        return Err(Value::String(format!("Command refused: {}", reason)));
+    }
+    let Some(approved) = approve(command, options, approvals, settings) else {
+        audit(command, options, settings, "rejected");
+        return Err(ask_rejection_feedback());
+    };
+    if approved != command {
+        if let Some(reason) = refusal(&approved, options, settings) {
+            println!("{}", paint(Style::Warning, &format!("Refused: {}", reason)));
+            audit(&approved, options, settings, &format!("refused: {}", reason));
+            return Err(Value::String(format!("Command refused: {}", reason)));
+        }
+    }
+    Ok(approved)
+}
+
+// Every command the agent proposes ends up in the audit log, whether it ran or not
+fn audit(command: &str, options: &AgentOptions, settings: &Settings, outcome: &str) {
+    if settings.audit_log {
+        let target = options.target.as_ref().map(|target| target.destination.as_str());
+        audit::record(command, target, outcome);
+    }
+}
+
+fn exit_outcome(status: ExitStatus) -> String {
+    match status.code() {
+        Some(code) => format!("exit {}", code),
+        None => "killed by a signal".to_string(),
+    }
+}
+
+// Tells the model what actually ran when the user edited its command
+fn edited_note(proposed: &str, command: &str) -> String {
+    if proposed == command {
+        String::new()
+    } else {
+        format!("The user edited the command before running it. It ran as:\n{}\n\n", command)
+    }
+}
+
+fn ask_rejection_feedback() -> Value {
+    let comment = dialoguer::Input::<String>::with_theme(&*style::dialog_theme())
+        .with_prompt("Comment on the provided code")
+        .interact()
+        .unwrap_or_default();
+
+    Value::String(
+        format!("Command was rejected by user.\nFEEDBACK: {}\n\nPlease suggest an alternative.", comment).to_string(),
+    )
+}
+
+// Runs a directive and returns what should be reported back to the model
+fn execute_directive(
+    directive: Directive,
+    options: &AgentOptions,
+    approvals: &mut Approvals,
+    jobs: &mut Jobs,
+    language_servers: &mut LanguageServers,
+    settings: &Settings,
+) -> Value {
+    match directive {
+        Directive::Command(proposed) => {
+            // Refused commands never reach the approval prompt
+            let command = match review(&proposed, options, approvals, settings) {
+                Ok(command) => command,
+                Err(result) => return result,
+            };
+
+            // Execute command and capture output
+            match build_shell_command(&command, options, settings).output() {
+                Ok(output) => {
+                    audit(&command, options, settings, &exit_outcome(output.status));
+                    let stdout = String::from_utf8_lossy(&output.stdout);
+                    let stderr = String::from_utf8_lossy(&output.stderr);
+                    let result = format!(
+                        "{}Command output{}:\nstdout:\n{}\nstderr:\n{}",
+                        edited_note(&proposed, &command),
+                        location(options),
+                        stdout,
+                        stderr
+                    );
+                    println!("{}", result);
+                    Value::String(result)
+                }
+                Err(e) => {
+                    audit(&command, options, settings, &format!("failed: {}", e));
+                    println!("{}", paint(Style::Error, &format!("Failed to execute command: {}", e)));
+                    Value::String(format!("Command failed: {}", e))
+                }
+            }
+        }
+        Directive::Background { name, command: proposed } => {
+            let command = match review(&proposed, options, approvals, settings) {
+                Ok(command) => command,
+                Err(result) => return result,
+            };
+
+            let (process, cleanup) = build_job_command(&name, &command, options, settings);
+            let started = jobs.start(&name, process, cleanup);
+            let outcome = match &started {
+                Ok(_) => format!("started in the background as '{}'", name),
+                Err(e) => format!("failed: {}", e),
+            };
+            audit(&command, options, settings, &outcome);
+            let result = match started {
+                Ok(_) => format!(
+                    "{}Started background process '{}'{}. Use POLL: {} to read its output.",
+                    edited_note(&proposed, &command),
+                    name,
+                    location(options),
+                    name
+                ),
+                Err(e) => format!("Could not start background process: {}", e),
+            };
+            println!("{}", result);
+            Value::String(result)
+        }
+        Directive::Poll(name) => {
+            let result = jobs.poll(&name).unwrap_or_else(|e| {
+                format!("Poll failed: {}. Running processes: {:?}", e, jobs.names())
+            });
+            println!("{}", result);
+            Value::String(result)
+        }
+        Directive::Stop(name) => {
+            let result = jobs.stop(&name).unwrap_or_else(|e| format!("Stop failed: {}", e));
+            println!("{}", result);
+            Value::String(result)
+        }
+        Directive::Tests(_) if options.target.is_some() => Value::String(format!(
+            "TESTS: only works on this machine, not{}. Use COMMAND: to run the tests there.",
+            location(options)
+        )),
+        Directive::Tests(filter) => {
+            let mut project = match testrun::detect_project(&filter) {
+                Some(project) => project,
+                None => {
+                    return Value::String(
+                        "No supported test setup found (cargo, npm, go, pytest). Use COMMAND: instead.".to_string(),
+                    )
+                }
+            };
+            let proposed = project.command.clone();
+            project.command = match review(&proposed, options, approvals, settings) {
+                Ok(command) => command,
+                Err(result) => return result,
+            };
+
+            match build_shell_command(&project.command, options, settings).output() {
+                Ok(output) => {
+                    audit(&project.command, options, settings, &exit_outcome(output.status));
+                    let summary = testrun::summarize(&project, &output);
+                    let result = format!(
+                        "{}Test results:\n{}",
+                        edited_note(&proposed, &project.command),
+                        serde_json::to_string_pretty(&summary).unwrap()
+                    );
+                    println!("{}", result);
+                    Value::String(result)
+                }
+                Err(e) => {
+                    audit(&project.command, options, settings, &format!("failed: {}", e));
+                    Value::String(format!("Could not run tests: {}", e))
+                }
+            }
+        }
+        directive => match execute_unattended(directive, language_servers, settings) {
+            Ok(result) => result,
+            Err(_) => unreachable!("every directive needing approval is handled above"),
+        },
+    }
+}
+
+// Directives that only read (files, the language server, the machine), so they run without
+// any approval. Hands everything else back.
+fn execute_unattended(
+    directive: Directive,
+    language_servers: &mut LanguageServers,
+    settings: &Settings,
+) -> Result<Value, Directive> {
+    Ok(match directive {
+        // Only reads the file, so it needs no approval, but protected paths stay off limits
+        Directive::Outline(file) => {
+            if let Err(reason) = check_protected_paths(&file, &settings.protected_paths) {
+                println!("{}", paint(Style::Warning, &format!("Refused: {}", reason)));
+                return Ok(Value::String(format!("Outline refused: {}", reason)));
+            }
+            let result = outline::outline(Path::new(&file)).unwrap_or_else(|e| format!("Outline failed: {}", e));
+            println!("{}", result);
+            Value::String(result)
+        }
+        Directive::Lsp(query) => {
+            if let Err(reason) = check_protected_paths(&query, &settings.protected_paths) {
+                println!("{}", paint(Style::Warning, &format!("Refused: {}", reason)));
+                return Ok(Value::String(format!("LSP query refused: {}", reason)));
+            }
+            let result = language_servers
+                .run(&query, &settings.lsp_servers)
+                .unwrap_or_else(|e| format!("LSP query failed: {}", e));
+            println!("{}", result);
+            Value::String(result)
+        }
+        // Protected paths are skipped rather than refused, so the rest of the tree still counts
+        Directive::Count(dir) => {
+            let dir = if dir.is_empty() { ".".to_string() } else { dir };
+            let result = count::count(&expand_tilde(&dir), |path| is_protected(path, &settings.protected_paths).is_some())
+                .unwrap_or_else(|e| format!("Count failed: {}", e));
+            println!("{}", result);
+            Value::String(result)
+        }
+        // kubectl restricted to reads, in the namespaces allowed by the settings
+        Directive::Kube(query) => {
+            let result = kube::run(&query, settings).unwrap_or_else(|e| format!("KUBECTL refused or failed: {}", e));
+            println!("{}", result);
+            Value::String(result)
+        }
+        // A fixed set of version and /proc lookups, nothing the model controls
+        Directive::SystemInfo => {
+            let result = format!("System information:\n{}", sysinfo::describe());
+            println!("{}", result);
+            Value::String(result)
+        }
+        Directive::Calc(expression) => {
+            let result = match calc::evaluate(&expression) {
+                Ok(value) => format!("{} => {}", expression, value),
+                Err(e) => format!("Could not evaluate '{}': {}", expression, e),
+            };
+            println!("{}", result);
+            Value::String(result)
+        }
+        directive => return Err(directive),
+    })
+}
+
+// Runs the reply's directive for clients of --serve, when it is one that needs no approval
+pub fn run_unattended(response: &str, language_servers: &mut LanguageServers, settings: &Settings) -> Option<String> {
+    let directive = parse_directive(response)?;
+    execute_unattended(directive, language_servers, settings)
+        .ok()
+        .and_then(|result| result.as_str().map(str::to_string))
+}
+
+pub fn handle_recursive_mode(
+    conversation_state: &mut ConversationState,
+    transcript_path: &Path,
+    user_input: String,
+    options: &AgentOptions,
+    settings: &Settings,
+) {
+    let read_only_notice = if options.read_only {
+        " You are in READ-ONLY mode: only suggest commands that inspect the system (listing, reading, searching). Never modify files, install software or change any state; such commands will be refused."
+    } else {
+        ""
+    };
+    let target_notice = match &options.target {
+        Some(target) => format!(" COMMAND: and BACKGROUND: run on the remote machine {} over ssh, while OUTLINE: and LSP: read files on the local machine.", target.destination),
+        None => String::new(),
+    };
+    let input = Value::String(format!("You are entering 'recursive agent mode' with the following instruction: {}. Suggest the next command to run. {}{}{}", user_input, DIRECTIVE_HELP, read_only_notice, target_notice));
+    let mut current_settings = settings.clone();
+    perform_unfiltered_request(input, conversation_state, transcript_path, &current_settings);
+    // Approvals saved for this project say nothing about what may run on another machine
+    let mut approvals = Approvals::load(settings.persist_approvals && options.target.is_none());
+    let mut jobs = Jobs::default();
+    let mut language_servers = match options.read_only {
+        true => LanguageServers::without_project_code(),
+        false => LanguageServers::default(),
+    };
+    let mut config_watcher = ConfigWatcher::new(settings);
+
+    loop {
+        // Edits to ask.json (theme, protected paths...) apply from the next turn on
+        config_watcher.check(&mut current_settings);
+        let settings = &current_settings;
+
+        // Get last AI message to check if it's already a command
+        let mut last_message = conversation_state.messages.last().unwrap();
+        let mut response = last_message.content.as_str().unwrap_or("");
+
+        // Check if task is complete
+        if response.contains("DONE") {
+            println!("{}", paint(Style::Success, "Task completed!"));
+            break;
+        }
+
+        // If the last message wasn't a command suggestion, steer the LLM towards it;
+        if parse_directive(response).is_none() {
+            let input = Value::String(format!("Remember the original task: {}. {}", user_input, DIRECTIVE_HELP));
+            perform_unfiltered_request(input, conversation_state, transcript_path, settings);
+
+            // Update response with new AI message
+            last_message = conversation_state.messages.last().unwrap();
+            response = last_message.content.as_str().unwrap_or("");
+
+            // If response is updated, we need to check for completion again
+            if response.contains("DONE") {
+                println!("{}", paint(Style::Success, "Task completed!"));
+                break;
+            }
+        }
+
+        // Variables set with --var work in directives too, e.g. COMMAND: curl {{API_BASE}}/health
+        if let Some(directive) = parse_directive(&conversation_state.expand(response)) {
+            let result = execute_directive(
+                directive,
+                options,
+                &mut approvals,
+                &mut jobs,
+                &mut language_servers,
+                settings,
+            );
+            perform_unfiltered_request(result, conversation_state, transcript_path, settings);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_only_allows_inspection() {
+        for command in [
+            "ls -la src",
+            "sort -u names.txt | uniq -c",
+            "git log --oneline -n 5",
+            "git diff --stat HEAD~1",
+            "git grep -n TODO",
+            "find . -name '*.rs'",
+            "grep -rn foo src 2>/dev/null",
+        ] {
+            assert!(check_read_only(command).is_ok(), "{}", command);
+        }
+    }
+
+    #[test]
+    fn read_only_catches_flags_in_clusters() {
+        for command in [
+            "sort -uo out.txt names.txt",
+            "sort -o out.txt",
+            "tree -ao out.txt",
+            "dmesg -Hc",
+        ] {
+            assert!(check_read_only(command).is_err(), "{}", command);
+        }
+    }
+
+    #[test]
+    fn read_only_catches_long_flags_with_values_and_abbreviations() {
+        for command in [
+            "sort --output=out.txt names.txt",
+            "sort --out=out.txt names.txt",
+            "git diff --output=patch.txt",
+            "git log --output=log.txt",
+            "journalctl --vacuum-size=1M",
+        ] {
+            assert!(check_read_only(command).is_err(), "{}", command);
+        }
+    }
+
+    #[test]
+    fn read_only_refuses_git_pagers() {
+        for command in [
+            "git grep -Ovim TODO",
+            "git grep -nO vim TODO",
+            "git grep --open-files-in-pager=vim TODO",
+            "git grep --open TODO",
+        ] {
+            assert!(check_read_only(command).is_err(), "{}", command);
+        }
+    }
+
+    #[test]
+    fn read_only_refuses_single_dash_find_actions() {
+        for command in ["find . -delete", "find . -exec rm {} ;", "find . -fprint out.txt"] {
+            assert!(check_read_only(command).is_err(), "{}", command);
+        }
+    }
+
+    #[test]
+    fn read_only_refuses_uniq_output_files() {
+        assert!(check_read_only("uniq -c in.txt").is_ok());
+        assert!(check_read_only("uniq -f 1 in.txt").is_ok());
+        assert!(check_read_only("uniq in.txt out.txt").is_err());
+        assert!(check_read_only("uniq -f 1 in.txt out.txt").is_err());
+    }
+
+    #[test]
+    fn read_only_refuses_dmesg_console_control() {
+        assert!(check_read_only("dmesg -T").is_ok());
+        for command in ["dmesg --read-clear", "dmesg -D", "dmesg -E", "dmesg -n 1", "dmesg --console-off"] {
+            assert!(check_read_only(command).is_err(), "{}", command);
+        }
+    }
+
+    #[test]
+    fn read_only_refuses_journal_maintenance() {
+        assert!(check_read_only("journalctl -u nginx --since today").is_ok());
+        for command in [
+            "journalctl --sync",
+            "journalctl --relinquish-var",
+            "journalctl --setup-keys",
+            "journalctl --update-catalog",
+        ] {
+            assert!(check_read_only(command).is_err(), "{}", command);
+        }
+    }
+
+    #[test]
+    fn read_only_refuses_compiling_magic_files() {
+        assert!(check_read_only("file -b src/main.rs").is_ok());
+        assert!(check_read_only("file -C -m x").is_err());
+        assert!(check_read_only("file --compile -m x").is_err());
+    }
+
+    #[test]
+    fn read_only_allows_only_listing_git_branches_tags_and_remotes() {
+        for command in [
+            "git branch",
+            "git branch -av",
+            "git branch --contains HEAD",
+            "git branch --list 'feat*'",
+            "git tag -n5 --sort=-creatordate",
+            "git remote -v",
+        ] {
+            assert!(check_read_only(command).is_ok(), "{}", command);
+        }
+        for command in [
+            "git branch --unset-upstream",
+            "git branch --set-upstream-to=origin/x",
+            "git branch new-feature",
+            "git branch -D old",
+            "git tag v1.0",
+            "git remote add origin url",
+        ] {
+            assert!(check_read_only(command).is_err(), "{}", command);
+        }
+    }
+
+    fn protected() -> Vec<String> {
+        vec!["~/.ssh".to_string(), "/etc".to_string()]
+    }
+
+    #[test]
+    fn protected_paths_allow_project_files() {
+        for command in [
+            "cat src/main.rs",
+            "cd src && cat main.rs",
+            "echo '$HOME/.ssh'",
+            "ls -la",
+        ] {
+            assert!(check_protected_paths(command, &protected()).is_ok(), "{}", command);
+        }
+    }
+
+    #[test]
+    fn protected_paths_expand_home() {
+        for command in [
+            "cat ~/.ssh/id_rsa",
+            "cat $HOME/.ssh/id_rsa",
+            "cat ${HOME}/.ssh/id_rsa",
+            "cat \"$HOME\"/.ssh/id_rsa",
+        ] {
+            assert!(check_protected_paths(command, &protected()).is_err(), "{}", command);
+        }
+    }
+
+    #[test]
+    fn protected_paths_join_quoted_words() {
+        for command in [
+            "cat /e''tc/passwd",
+            "cat \"/etc\"/passwd",
+            "cat /e\\tc/passwd",
+            "cat</etc/passwd",
+            "sh -c 'cat /etc/passwd'",
+            "grep root --file=/etc/passwd",
+        ] {
+            assert!(check_protected_paths(command, &protected()).is_err(), "{}", command);
+        }
+    }
+
+    #[test]
+    fn protected_paths_follow_cd_out_of_the_project() {
+        for command in ["cd /tmp", "cd /var/log && grep error syslog", "cd .. ; ls", "pushd /tmp && popd && ls"] {
+            assert!(check_protected_paths(command, &protected()).is_ok(), "{}", command);
+        }
+        for command in [
+            "cd / && cat etc/shadow",
+            "cd /etc",
+            "cd && cat .ssh/id_rsa",
+            "cd ~/.ssh; cat id_rsa",
+            "cd /tmp && cd - && cd / && cat etc/passwd",
+            "sh -c 'cd /; cat etc/shadow'",
+        ] {
+            assert!(check_protected_paths(command, &protected()).is_err(), "{}", command);
+        }
+    }
+
+    #[test]
+    fn protected_paths_expand_other_users_homes() {
+        let root_ssh = vec![format!("{}/.ssh", home_of("root").unwrap())];
+        assert!(check_protected_paths("cat ~root/.ssh/id_rsa", &root_ssh).is_err());
+        assert!(check_protected_paths("scp -i ~root/.ssh/id_rsa x y", &root_ssh).is_err());
+        assert!(check_protected_paths("cat ~no_such_user/.ssh/id_rsa", &protected()).is_err());
+        assert!(check_protected_paths("git log HEAD~2", &protected()).is_ok());
+    }
+
+    #[test]
+    fn protected_paths_leave_remote_targets_alone() {
+        let settings = Settings {
+            protected_paths: protected(),
+            ..Default::default()
+        };
+        let options = AgentOptions {
+            read_only: false,
+            sandbox: "none".to_string(),
+            target: Some(Target::parse("ssh://admin@web1").unwrap()),
+        };
+        assert!(refusal("cat /etc/nginx/nginx.conf", &options, &settings).is_none());
+    }
+}
@@ -0,0 +1,132 @@
+use crate::data_dir;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+// Tools whose second word selects what actually happens (`git status` vs `git push`)
+const MULTI_COMMAND_PROGRAMS: &[&str] = &[
+    "git", "cargo", "npm", "yarn", "pnpm", "docker", "podman", "kubectl", "systemctl", "go",
+    "pip", "apt", "brew", "make",
+];
+
+// Shell syntax that could smuggle a second command past a prefix match
+const CHAINING_TOKENS: &[&str] = &[";", "&", "|", ">", "<", "`", "$(", "\n"];
+
+// "Always allow" decisions for agent commands. Kept for the run, and optionally persisted per
+// project (the git root, or the current directory outside of a repository). The file lives in
+// the user's data directory, not in the project, so a cloned repository can't bring approvals
+// of its own.
+#[derive(Serialize, Deserialize, Default)]
+pub struct Approvals {
+    #[serde(default)]
+    prefixes: Vec<String>,
+    #[serde(default)]
+    exact: Vec<String>,
+    #[serde(skip)]
+    path: Option<PathBuf>,
+}
+
+impl Approvals {
+    pub fn load(persist: bool) -> Approvals {
+        if !persist {
+            return Approvals::default();
+        }
+
+        let path = approvals_path(&project_root());
+        let mut approvals: Approvals = fs::read_to_string(&path)
+            .ok()
+            .and_then(|data| serde_json::from_str(&data).ok())
+            .unwrap_or_default();
+        approvals.path = Some(path);
+        approvals
+    }
+
+    pub fn is_approved(&self, command: &str) -> bool {
+        if self.exact.iter().any(|exact| exact == command) {
+            return true;
+        }
+        // Prefix approvals only cover plain commands
+        if CHAINING_TOKENS.iter().any(|token| command.contains(token)) {
+            return false;
+        }
+        let words: Vec<&str> = command.split_whitespace().collect();
+        self.prefixes.iter().any(|prefix| {
+            let prefix_words: Vec<&str> = prefix.split_whitespace().collect();
+            words.starts_with(&prefix_words)
+        })
+    }
+
+    pub fn allow_prefix(&mut self, prefix: String) {
+        if !self.prefixes.contains(&prefix) {
+            self.prefixes.push(prefix);
+        }
+        self.save();
+    }
+
+    pub fn allow_exact(&mut self, command: String) {
+        if !self.exact.contains(&command) {
+            self.exact.push(command);
+        }
+        self.save();
+    }
+
+    fn save(&self) {
+        if let Some(path) = &self.path {
+            if let Some(folder) = path.parent() {
+                let _ = fs::create_dir_all(folder);
+            }
+            let data = serde_json::to_string_pretty(self).unwrap();
+            if let Err(e) = fs::write(path, data) {
+                eprintln!("Failed to save approvals to {}: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+// The part of a command an "always allow" decision covers, e.g. `ls` or `git status`.
+// None when the command chains others, since a prefix can't vouch for them.
+pub fn command_prefix(command: &str) -> Option<String> {
+    if CHAINING_TOKENS.iter().any(|token| command.contains(token)) {
+        return None;
+    }
+
+    let words: Vec<&str> = command.split_whitespace().collect();
+    let program = *words.first()?;
+    match words.get(1) {
+        Some(sub) if MULTI_COMMAND_PROGRAMS.contains(&program) && !sub.starts_with('-') => {
+            Some(format!("{} {}", program, sub))
+        }
+        _ => Some(program.to_string()),
+    }
+}
+
+// $XDG_DATA_HOME/ask/approvals/<project path, percent-encoded>.json
+fn approvals_path(project: &Path) -> PathBuf {
+    let name: String = project
+        .display()
+        .to_string()
+        .bytes()
+        .map(|byte| {
+            if byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_' {
+                (byte as char).to_string()
+            } else {
+                format!("%{:02X}", byte)
+            }
+        })
+        .collect();
+    data_dir().join("approvals").join(format!("{}.json", name))
+}
+
+fn project_root() -> PathBuf {
+    Command::new("git")
+        .args(["rev-parse", "--show-toplevel"])
+        .stderr(Stdio::null())
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| PathBuf::from(String::from_utf8_lossy(&output.stdout).trim()))
+        .or_else(|| env::current_dir().ok())
+        .unwrap_or_default()
+}
@@ -0,0 +1,211 @@
+use crate::agent::is_protected;
+use crate::conversation::ConversationState;
+use crate::count::LANGUAGES;
+use crate::message_text;
+use crate::style::{self, epaint, Style};
+use crate::Settings;
+use atty::Stream;
+use std::fs;
+use std::path::{Component, Path};
+
+struct Artifact {
+    name: String,
+    code: String,
+}
+
+// `--artifacts`: offers to write every fenced code block the assistant sent to a file, named
+// after the block's info string, a path comment on its first line or the prose just above it.
+// When a name comes up again the latest version wins, since that is usually the revised one.
+pub fn write_artifacts(conversation_state: &ConversationState, settings: &Settings) {
+    let mut artifacts: Vec<Artifact> = vec![];
+    let mut blocks = 0;
+    for message in conversation_state
+        .messages
+        .iter()
+        .filter(|m| m.role == "assistant")
+    {
+        for artifact in extract(message_text(&message.content), &mut blocks) {
+            artifacts.retain(|earlier| earlier.name != artifact.name);
+            artifacts.push(artifact);
+        }
+    }
+    if artifacts.is_empty() {
+        println!("No code blocks in this conversation.");
+        return;
+    }
+
+    // Without a terminal to confirm on, only list what would be written
+    if !atty::is(Stream::Stdin) || !atty::is(Stream::Stdout) {
+        for artifact in &artifacts {
+            println!(
+                "{} ({} lines)",
+                artifact.name,
+                artifact.code.lines().count()
+            );
+        }
+        return;
+    }
+
+    let mut written = 0;
+    for artifact in &artifacts {
+        let lines = artifact.code.lines().count();
+        let prompt = format!(
+            "Write {} line{} to (empty to skip)",
+            lines,
+            if lines == 1 { "" } else { "s" }
+        );
+        let name = dialoguer::Input::<String>::with_theme(&*style::dialog_theme())
+            .with_prompt(epaint(Style::Prompt, &prompt))
+            .with_initial_text(&artifact.name)
+            .allow_empty(true)
+            .interact_text()
+            .unwrap_or_default();
+        let name = name.trim();
+        if name.is_empty() {
+            continue;
+        }
+
+        let path = Path::new(name);
+        if let Some(protected) = is_protected(path, &settings.protected_paths) {
+            let message = format!("{} is under protected path {}, skipped", name, protected);
+            eprintln!("{}", epaint(Style::Error, &message));
+            continue;
+        }
+        if path.exists() {
+            let overwrite = dialoguer::Confirm::with_theme(&*style::dialog_theme())
+                .with_prompt(epaint(
+                    Style::Warning,
+                    &format!("{} exists. Overwrite?", name),
+                ))
+                .default(false)
+                .interact()
+                .unwrap_or(false);
+            if !overwrite {
+                continue;
+            }
+        }
+
+        let mut code = artifact.code.clone();
+        code.push('\n');
+        let result = match path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            Some(parent) => fs::create_dir_all(parent).and_then(|_| fs::write(path, code)),
+            None => fs::write(path, code),
+        };
+        match result {
+            Ok(()) => written += 1,
+            Err(e) => eprintln!(
+                "{}",
+                epaint(Style::Error, &format!("Could not write {}: {}", name, e))
+            ),
+        }
+    }
+    println!("Wrote {} of {} files.", written, artifacts.len());
+}
+
+// `blocks` counts the blocks seen so far, numbering the ones without a name
+fn extract(text: &str, blocks: &mut usize) -> Vec<Artifact> {
+    let mut artifacts = vec![];
+    let mut prose = "";
+    let mut current: Option<(&str, Vec<&str>)> = None;
+    for line in text.lines() {
+        let trimmed = line.trim_start();
+        match current.take() {
+            None => match trimmed.strip_prefix("```") {
+                Some(info) => current = Some((info.trim(), vec![])),
+                None if !trimmed.is_empty() => prose = trimmed,
+                None => {}
+            },
+            Some((info, lines)) if trimmed.starts_with("```") => {
+                *blocks += 1;
+                let code = lines.join("\n");
+                let name = name_from_info(info)
+                    .or_else(|| lines.first().and_then(|first| name_from_comment(first)))
+                    .or_else(|| name_from_prose(prose))
+                    .unwrap_or_else(|| format!("artifact_{}.{}", blocks, extension(info)));
+                artifacts.push(Artifact { name, code });
+                prose = "";
+            }
+            Some((info, mut lines)) => {
+                lines.push(line);
+                current = Some((info, lines));
+            }
+        }
+    }
+    artifacts
+}
+
+// ```rust src/main.rs, ```rust:src/main.rs, ```python title="app.py" or ```Makefile
+fn name_from_info(info: &str) -> Option<String> {
+    let mut words = info.split_whitespace();
+    let first = words.next()?;
+    let candidates = std::iter::once(first.split_once(':').map_or(first, |(_, path)| path))
+        .chain(words.map(|word| word.split_once('=').map_or(word, |(_, value)| value)));
+    candidates
+        .map(|word| word.trim_matches(['"', '\'']))
+        .find(|word| looks_like_path(word))
+        .map(str::to_string)
+}
+
+// `// src/lib.rs`, `# file: app.py`, `<!-- index.html -->`
+fn name_from_comment(line: &str) -> Option<String> {
+    let text = line
+        .trim()
+        .trim_start_matches(['/', '#', '-', '*', '<', '!', ';'])
+        .trim_end_matches(['>', '-', '*', '/'])
+        .trim();
+    let text = text
+        .strip_prefix("file:")
+        .or_else(|| text.strip_prefix("File:"))
+        .or_else(|| text.strip_prefix("filename:"))
+        .unwrap_or(text)
+        .trim();
+    looks_like_path(text).then(|| text.to_string())
+}
+
+// "Create `src/config.rs`:" or "**app.py**". Unmarked words need a known extension or a
+// directory, so "e.g." doesn't become a file name.
+fn name_from_prose(line: &str) -> Option<String> {
+    line.split_whitespace()
+        .filter_map(|word| {
+            let marked = word.starts_with(['`', '*', '"']);
+            let word = word.trim_matches(|c: char| "`*_:,.()'\"".contains(c));
+            let known = Path::new(word)
+                .extension()
+                .and_then(|extension| extension.to_str())
+                .is_some_and(|extension| LANGUAGES.iter().any(|(known, _)| *known == extension));
+            (looks_like_path(word) && (marked || known || word.contains('/'))).then_some(word)
+        })
+        .next_back()
+        .map(str::to_string)
+}
+
+// A relative file name with an extension, which also keeps names out of parent directories
+fn looks_like_path(word: &str) -> bool {
+    let path = Path::new(word);
+    let has_extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| extension.chars().all(|c| c.is_ascii_alphanumeric()));
+    let is_plain = path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)));
+    (has_extension || matches!(word, "Makefile" | "Dockerfile"))
+        && is_plain
+        && !word.contains(char::is_whitespace)
+        && !word.contains("://")
+}
+
+fn extension(info: &str) -> String {
+    let language = info.split_whitespace().next().unwrap_or("").to_lowercase();
+    if LANGUAGES.iter().any(|(known, _)| *known == language) {
+        return language;
+    }
+    LANGUAGES
+        .iter()
+        .find(|(_, name)| name.to_lowercase() == language)
+        .map(|(known, _)| known.to_string())
+        .unwrap_or_else(|| "txt".to_string())
+}
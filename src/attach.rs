@@ -0,0 +1,202 @@
+use crate::outline;
+use atty::Stream;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+// Attachments from this many files on show a progress count
+const PROGRESS_FILES: usize = 16;
+
+struct File<'a> {
+    path: &'a str,
+    text: String,
+    outline: Option<String>,
+}
+
+// Reads the --file attachments into one prompt block. Files that don't fit their share of
+// `budget` (in characters) are excerpted, and the excerpt says what was left out.
+pub fn attach(paths: &[&String], budget: usize) -> Result<String, String> {
+    // Each share is at least about budget / files, so smaller files never need their outline.
+    // Reading and outlining are the slow parts and run on every core.
+    let smallest_share = budget / paths.len().max(1);
+    let progress = paths.len() >= PROGRESS_FILES && atty::is(Stream::Stderr);
+    let mut files = parallel_map(paths, progress, |path| {
+        let text =
+            fs::read_to_string(path).map_err(|e| format!("Could not read {}: {}", path, e))?;
+        let outline = (text.chars().count() > smallest_share)
+            .then(|| outline::outline(Path::new(path.as_str())).ok())
+            .flatten();
+        Ok(File {
+            path: path.as_str(),
+            text,
+            outline,
+        })
+    })
+    .into_iter()
+    .collect::<Result<Vec<File>, String>>()?;
+
+    // Smallest first, so whatever the small files don't use goes to the big ones
+    let mut order: Vec<usize> = (0..files.len()).collect();
+    order.sort_by_key(|&i| files[i].text.len());
+    let mut remaining = budget;
+    for (position, &i) in order.iter().enumerate() {
+        let share = remaining / (order.len() - position);
+        let file = &mut files[i];
+        if file.text.chars().count() > share {
+            let outline = file
+                .outline
+                .take()
+                .or_else(|| outline::outline(Path::new(file.path)).ok());
+            file.text = excerpt(&file.text, outline.as_deref(), share);
+        }
+        remaining = remaining.saturating_sub(file.text.chars().count());
+    }
+
+    Ok(files
+        .iter()
+        .map(|file| {
+            let language = Path::new(file.path)
+                .extension()
+                .and_then(|e| e.to_str())
+                .unwrap_or("");
+            format!(
+                "File: {}\n```{}\n{}\n```",
+                file.path,
+                language,
+                file.text.trim_end()
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n\n"))
+}
+
+// Lines from the start of `lines` until `budget` characters are used
+pub fn take_within<'a>(lines: impl Iterator<Item = &'a str>, budget: usize) -> Vec<&'a str> {
+    let mut used = 0;
+    lines
+        .take_while(|line| {
+            used += line.chars().count() + 1;
+            used <= budget
+        })
+        .collect()
+}
+
+// Runs `f` over `items` on every core, keeping their order. With `progress`, counts the
+// finished items on stderr.
+fn parallel_map<T: Sync, R: Send>(
+    items: &[T],
+    progress: bool,
+    f: impl Fn(&T) -> R + Sync,
+) -> Vec<R> {
+    let threads = thread::available_parallelism()
+        .map_or(1, |n| n.get())
+        .min(items.len())
+        .max(1);
+    let next = AtomicUsize::new(0);
+    let finished = AtomicUsize::new(0);
+    let mut results: Vec<(usize, R)> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut results = vec![];
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(item) = items.get(index) else {
+                            return results;
+                        };
+                        results.push((index, f(item)));
+                        let done = finished.fetch_add(1, Ordering::Relaxed) + 1;
+                        if progress {
+                            eprint!("\rReading files {}/{}", done, items.len());
+                        }
+                    }
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap())
+            .collect()
+    });
+    if progress {
+        eprint!("\r\x1b[2K");
+    }
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+fn excerpt(text: &str, outline: Option<&str>, budget: usize) -> String {
+    let lines: Vec<&str> = text.lines().collect();
+
+    // Code gets its outline first, so the model knows what exists beyond the excerpt
+    if let Some(outline) = outline {
+        if outline.chars().count() < budget / 2 {
+            let head = take_within(lines.iter().copied(), budget - outline.chars().count());
+            return format!(
+                "[Excerpt: {} of {} lines shown. Ask for other parts if you need them.]\n{}\n\n{}\n[... {} more lines ...]",
+                head.len(),
+                lines.len(),
+                outline,
+                head.join("\n"),
+                lines.len() - head.len()
+            );
+        }
+    }
+
+    let head = take_within(lines.iter().copied(), budget / 2);
+    if head.is_empty() {
+        // One huge line (minified code, a JSON dump...)
+        return format!(
+            "[Excerpt: the first {} of {} characters. Ask for other parts if you need them.]\n{}",
+            budget,
+            text.chars().count(),
+            text.chars().take(budget).collect::<String>()
+        );
+    }
+    let mut tail = take_within(lines[head.len()..].iter().rev().copied(), budget / 2);
+    tail.reverse();
+    let omitted = lines.len() - head.len() - tail.len();
+    format!(
+        "[Excerpt: the first {} and last {} of {} lines. Ask for other parts if you need them.]\n{}\n[... {} lines omitted ...]\n{}",
+        head.len(),
+        tail.len(),
+        lines.len(),
+        head.join("\n"),
+        omitted,
+        tail.join("\n")
+    )
+}
+
+// `--diff`: the unified diff between two files (or directories), so only the changes are sent
+pub fn diff(old: &str, new: &str, budget: usize) -> Result<String, String> {
+    for path in [old, new] {
+        if !Path::new(path).exists() {
+            return Err(format!("Could not read {}: no such file or directory", path));
+        }
+    }
+    let output = Command::new("diff")
+        .args(["-u", "-r", "-N", old, new])
+        .output()
+        .map_err(|e| format!("Could not run diff: {}", e))?;
+    // diff exits with 1 when the inputs differ, 2 on trouble
+    match output.status.code() {
+        Some(0) => return Err(format!("{} and {} are identical", old, new)),
+        Some(1) => {}
+        _ => return Err(format!("diff failed: {}", String::from_utf8_lossy(&output.stderr).trim())),
+    }
+
+    let text = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<&str> = text.lines().collect();
+    let shown = take_within(lines.iter().copied(), budget);
+    let mut block = format!("Diff of {} -> {}:\n```diff\n{}\n```", old, new, shown.join("\n"));
+    if shown.len() < lines.len() {
+        block.push_str(&format!(
+            "\n[... the last {} of {} diff lines were left out ...]",
+            lines.len() - shown.len(),
+            lines.len()
+        ));
+    }
+    Ok(block)
+}
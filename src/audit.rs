@@ -0,0 +1,68 @@
+use crate::data_dir;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// One line of agent_audit.jsonl: a command the agent proposed, and what became of it
+#[derive(Serialize, Deserialize)]
+pub struct Entry {
+    pub timestamp: u64,
+    pub cwd: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>, //user@host with --target
+    pub command: String,
+    pub outcome: String, //"exit 0", "refused: ...", "rejected"...
+}
+
+fn audit_path() -> PathBuf {
+    data_dir().join("agent_audit.jsonl")
+}
+
+// Appended under an exclusive lock, like the usage ledger
+pub fn record(command: &str, target: Option<&str>, outcome: &str) {
+    let entry = Entry {
+        timestamp: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0),
+        cwd: std::env::current_dir()
+            .map(|dir| dir.display().to_string())
+            .unwrap_or_default(),
+        target: target.map(str::to_string),
+        command: command.to_string(),
+        outcome: outcome.to_string(),
+    };
+
+    let path = audit_path();
+    if let Some(parent) = path.parent() {
+        let _ = fs::create_dir_all(parent);
+    }
+    let appended = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut file| {
+            file.lock()?;
+            writeln!(file, "{}", serde_json::to_string(&entry).unwrap())
+        });
+    if let Err(e) = appended {
+        eprintln!("Could not write to {}: {}", path.display(), e);
+    }
+}
+
+// Oldest first
+pub fn entries() -> Vec<Entry> {
+    let Ok(file) = File::open(audit_path()) else {
+        return vec![];
+    };
+    if file.lock_shared().is_err() {
+        return vec![];
+    }
+    fs::read_to_string(audit_path())
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
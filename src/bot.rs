@@ -0,0 +1,321 @@
+use crate::conversation::{self, Message};
+use crate::serve::{
+    error_body, is_local_host, is_local_origin, read_request, write_response, Request,
+};
+use crate::style::{epaint, Style};
+use crate::{http_client, new_conversation, send_request, Settings};
+use openssl::hash::MessageDigest;
+use openssl::memcmp;
+use openssl::pkey::PKey;
+use openssl::sign::Signer;
+use serde_json::{json, Value};
+use std::env;
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// Connections are handled one at a time, so a client that stops sending can't hold the bot
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+// Slack's own limit: older deliveries are refused, so a captured one can't be replayed
+const MAX_SIGNATURE_AGE: u64 = 5 * 60;
+
+// What a webhook delivery asks of the bot
+enum Incoming {
+    Challenge(String), //Slack's one-time URL verification
+    Message { channel: String, text: String },
+    Ignored,
+}
+
+// Accepts Slack Events API payloads as well as plain {"channel", "text"} objects
+fn parse_incoming(body: &Value) -> Incoming {
+    if body["type"] == "url_verification" {
+        return Incoming::Challenge(body["challenge"].as_str().unwrap_or_default().to_string());
+    }
+    let event = if body["type"] == "event_callback" {
+        let event = &body["event"];
+        // Our own replies (and edits, joins...) come back as events too
+        if event.get("bot_id").is_some() || event.get("subtype").is_some() {
+            return Incoming::Ignored;
+        }
+        event
+    } else {
+        body
+    };
+
+    match (event["channel"].as_str(), event["text"].as_str()) {
+        (Some(channel), Some(text)) if !text.trim().is_empty() => Incoming::Message {
+            channel: channel.to_string(),
+            text: text.to_string(),
+        },
+        _ => Incoming::Ignored,
+    }
+}
+
+// Slack signs each delivery with an HMAC-SHA256 of "v0:<timestamp>:<body>"
+fn sign(secret: &str, timestamp: &str, body: &[u8]) -> Option<String> {
+    let key = PKey::hmac(secret.as_bytes()).ok()?;
+    let mut signer = Signer::new(MessageDigest::sha256(), &key).ok()?;
+    signer
+        .update(format!("v0:{}:", timestamp).as_bytes())
+        .ok()?;
+    signer.update(body).ok()?;
+    let digest = signer.sign_to_vec().ok()?;
+    let hex: String = digest.iter().map(|byte| format!("{:02x}", byte)).collect();
+    Some(format!("v0={}", hex))
+}
+
+fn verify_signature(request: &Request, secret: &str, now: u64) -> Result<(), &'static str> {
+    let timestamp = request
+        .header("X-Slack-Request-Timestamp")
+        .ok_or("Missing X-Slack-Request-Timestamp")?;
+    let signature = request
+        .header("X-Slack-Signature")
+        .ok_or("Missing X-Slack-Signature")?;
+    let sent: u64 = timestamp
+        .parse()
+        .map_err(|_| "Invalid X-Slack-Request-Timestamp")?;
+    if now.abs_diff(sent) > MAX_SIGNATURE_AGE {
+        return Err("The request timestamp is too old");
+    }
+
+    let expected = sign(secret, timestamp, &request.body).ok_or("Could not check the signature")?;
+    if signature.len() != expected.len() || !memcmp::eq(signature.as_bytes(), expected.as_bytes()) {
+        return Err("Invalid signature");
+    }
+    Ok(())
+}
+
+// Channel names are percent-encoded, so distinct channels never share a transcript
+fn transcript_path(channel: &str, settings: &Settings) -> PathBuf {
+    let channel: String = channel
+        .bytes()
+        .map(|byte| {
+            if byte.is_ascii_alphanumeric() || byte == b'-' {
+                (byte as char).to_string()
+            } else {
+                format!("%{:02X}", byte)
+            }
+        })
+        .collect();
+    env::temp_dir().join(format!("{}bot-{}", settings.transcript_name, channel))
+}
+
+// Continues the channel's conversation. "/reset" starts it over.
+fn reply(channel: &str, text: &str, settings: &Settings) -> String {
+    let path = transcript_path(channel, settings);
+    if text.trim() == "/reset" {
+        let _ = conversation::remove(&path);
+        return "Conversation cleared.".to_string();
+    }
+
+    let mut state = conversation::load(&path).unwrap_or_else(|| new_conversation(settings));
+    state
+        .messages
+        .push(Message::new("user", Value::String(text.to_string())));
+    let Some(response) = send_request(&mut state, settings) else {
+        return "Sorry, the request to the model failed.".to_string();
+    };
+
+    let answer = response.text().to_string();
+    state.messages.push(response.message);
+    conversation::save(&mut state, &path);
+    answer
+}
+
+fn post_reply(url: &str, channel: &str, text: &str) {
+    let result = http_client()
+        .post(url)
+        .json(&json!({ "channel": channel, "text": text }))
+        .send()
+        .and_then(|response| response.error_for_status());
+    if let Err(e) = result {
+        eprintln!(
+            "{}",
+            epaint(Style::Error, &format!("Could not deliver the reply: {}", e))
+        );
+    }
+}
+
+fn handle(stream: TcpStream, port: u16, settings: &Settings) {
+    let _ = stream.set_read_timeout(Some(READ_TIMEOUT));
+    let request = match read_request(&stream) {
+        // Signed deliveries may come through a tunnel or proxy under any host name, the rest
+        // have to be local like --serve's
+        Ok(request)
+            if !is_local_origin(request.origin.as_deref(), port)
+                || (settings.bot_signing_secret.is_empty()
+                    && !is_local_host(&request.host, port)) =>
+        {
+            write_response(
+                &stream,
+                "403 Forbidden",
+                "application/json",
+                &error_body("Only local requests are answered"),
+            );
+            return;
+        }
+        Ok(request) if request.method == "POST" => request,
+        Ok(_) => {
+            write_response(
+                &stream,
+                "405 Method Not Allowed",
+                "application/json",
+                &error_body("POST a message"),
+            );
+            return;
+        }
+        Err(e) => {
+            write_response(
+                &stream,
+                e.status,
+                "application/json",
+                &error_body(&e.message),
+            );
+            return;
+        }
+    };
+    if !settings.bot_signing_secret.is_empty() {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or_default();
+        if let Err(e) = verify_signature(&request, &settings.bot_signing_secret, now) {
+            write_response(
+                &stream,
+                "401 Unauthorized",
+                "application/json",
+                &error_body(e),
+            );
+            return;
+        }
+    }
+
+    let body: Value = match serde_json::from_slice(&request.body) {
+        Ok(body) => body,
+        Err(e) => {
+            write_response(
+                &stream,
+                "400 Bad Request",
+                "application/json",
+                &error_body(&format!("Invalid JSON: {}", e)),
+            );
+            return;
+        }
+    };
+
+    let (channel, text) = match parse_incoming(&body) {
+        Incoming::Challenge(challenge) => {
+            write_response(
+                &stream,
+                "200 OK",
+                "application/json",
+                &json!({ "challenge": challenge }).to_string(),
+            );
+            return;
+        }
+        Incoming::Ignored => {
+            write_response(&stream, "200 OK", "application/json", "{}");
+            return;
+        }
+        Incoming::Message { channel, text } => (channel, text),
+    };
+    eprintln!("[{}] {}", channel, text);
+
+    if settings.bot_webhook_url.is_empty() {
+        let answer = reply(&channel, &text, settings);
+        let response = json!({ "channel": channel, "text": answer });
+        write_response(&stream, "200 OK", "application/json", &response.to_string());
+    } else {
+        // Acknowledge right away, chat services give up on slow webhooks
+        write_response(&stream, "200 OK", "application/json", "{}");
+        drop(stream);
+        post_reply(
+            &settings.bot_webhook_url,
+            &channel,
+            &reply(&channel, &text, settings),
+        );
+    }
+}
+
+// A small chat bot: every channel posting to the webhook gets its own conversation
+pub fn run_bot(port: u16, settings: &Settings) {
+    let listener = match TcpListener::bind(("127.0.0.1", port)) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!(
+                "{}",
+                epaint(
+                    Style::Error,
+                    &format!("Could not listen on port {}: {}", port, e)
+                )
+            );
+            std::process::exit(1);
+        }
+    };
+    eprintln!(
+        "Bot listening at http://127.0.0.1:{} (Ctrl-C to stop)",
+        port
+    );
+
+    let settings = Settings {
+        show_spinner: false,
+        ..settings.clone()
+    };
+    // One message at a time keeps each channel's transcript consistent
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => handle(stream, port, &settings),
+            Err(e) => eprintln!(
+                "{}",
+                epaint(Style::Warning, &format!("Connection failed: {}", e))
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn delivery(timestamp: &str, signature: &str, body: &str) -> Request {
+        Request {
+            method: "POST".to_string(),
+            path: "/".to_string(),
+            host: "bot.example.com".to_string(),
+            origin: None,
+            headers: vec![
+                (
+                    "X-Slack-Request-Timestamp".to_string(),
+                    timestamp.to_string(),
+                ),
+                ("X-Slack-Signature".to_string(), signature.to_string()),
+            ],
+            body: body.as_bytes().to_vec(),
+        }
+    }
+
+    #[test]
+    fn signatures_are_checked() {
+        let body = r#"{"channel":"C1","text":"hi"}"#;
+        let signature = sign("secret", "1000", body.as_bytes()).unwrap();
+        assert!(verify_signature(&delivery("1000", &signature, body), "secret", 1010).is_ok());
+        assert!(verify_signature(&delivery("1000", &signature, body), "other", 1010).is_err());
+        assert!(verify_signature(&delivery("1000", &signature, "{}"), "secret", 1010).is_err());
+        assert!(verify_signature(&delivery("1000", "v0=00", body), "secret", 1010).is_err());
+        // Replayed later
+        assert!(verify_signature(&delivery("1000", &signature, body), "secret", 2000).is_err());
+    }
+
+    #[test]
+    fn channels_keep_distinct_transcripts() {
+        let settings = Settings::default();
+        assert_ne!(
+            transcript_path("a.b", &settings),
+            transcript_path("a_b", &settings)
+        );
+        assert!(transcript_path("C024BE91L", &settings)
+            .display()
+            .to_string()
+            .ends_with("bot-C024BE91L"));
+    }
+}
@@ -0,0 +1,558 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// A small evaluator for the agent's CALC directive: arithmetic, unit conversion and date
+// math, so numbers don't depend on the model's arithmetic or on running python.
+
+// (names, dimension, factor to the dimension's base unit)
+const UNITS: &[(&[&str], &str, f64)] = &[
+    (&["mm", "millimeter", "millimeters"], "length", 0.001),
+    (&["cm", "centimeter", "centimeters"], "length", 0.01),
+    (&["m", "meter", "meters", "metre", "metres"], "length", 1.0),
+    (&["km", "kilometer", "kilometers"], "length", 1000.0),
+    (&["in", "inch", "inches"], "length", 0.0254),
+    (&["ft", "foot", "feet"], "length", 0.3048),
+    (&["yd", "yard", "yards"], "length", 0.9144),
+    (&["mi", "mile", "miles"], "length", 1609.344),
+    (&["mg", "milligram", "milligrams"], "mass", 0.000001),
+    (&["g", "gram", "grams"], "mass", 0.001),
+    (&["kg", "kilogram", "kilograms"], "mass", 1.0),
+    (&["t", "tonne", "tonnes"], "mass", 1000.0),
+    (&["oz", "ounce", "ounces"], "mass", 0.028349523125),
+    (&["lb", "lbs", "pound", "pounds"], "mass", 0.45359237),
+    (&["ms", "millisecond", "milliseconds"], "time", 0.001),
+    (&["s", "sec", "second", "seconds"], "time", 1.0),
+    (&["min", "minute", "minutes"], "time", 60.0),
+    (&["h", "hr", "hour", "hours"], "time", 3600.0),
+    (&["day", "days"], "time", 86400.0),
+    (&["week", "weeks"], "time", 604800.0),
+    (&["b", "byte", "bytes"], "data", 1.0),
+    (&["kb"], "data", 1e3),
+    (&["mb"], "data", 1e6),
+    (&["gb"], "data", 1e9),
+    (&["tb"], "data", 1e12),
+    (&["kib"], "data", 1024.0),
+    (&["mib"], "data", 1048576.0),
+    (&["gib"], "data", 1073741824.0),
+    (&["tib"], "data", 1099511627776.0),
+    (&["ml", "milliliter", "milliliters"], "volume", 0.001),
+    (&["l", "liter", "liters", "litre", "litres"], "volume", 1.0),
+    (&["gal", "gallon", "gallons"], "volume", 3.785411784),
+    (&["m/s"], "speed", 1.0),
+    (&["km/h", "kph"], "speed", 1.0 / 3.6),
+    (&["mph"], "speed", 0.44704),
+];
+
+pub fn evaluate(input: &str) -> Result<String, String> {
+    let input = input.trim();
+    if input.is_empty() {
+        return Err("nothing to evaluate".to_string());
+    }
+    if let Some(result) = date_math(input) {
+        return result;
+    }
+    if let Some(result) = convert(input) {
+        return result;
+    }
+    arithmetic(input).map(format_number)
+}
+
+fn format_number(value: f64) -> String {
+    if value.fract() == 0.0 && value.abs() < 1e15 {
+        format!("{}", value as i64)
+    } else {
+        // Hide float noise such as 0.30000000000000004
+        format!("{}", (value * 1e10).round() / 1e10)
+    }
+}
+
+// Arithmetic
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Name(String),
+    Operator(char),
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit() || c == '.' {
+            let start = i;
+            while i < chars.len()
+                && (chars[i].is_ascii_digit()
+                    || chars[i] == '.'
+                    || chars[i] == '_'
+                    || chars[i] == ',')
+            {
+                i += 1;
+            }
+            // Scientific notation: 1e6, 2.5E-3
+            if i < chars.len() && (chars[i] == 'e' || chars[i] == 'E') {
+                let mut j = i + 1;
+                if j < chars.len() && (chars[j] == '-' || chars[j] == '+') {
+                    j += 1;
+                }
+                if j < chars.len() && chars[j].is_ascii_digit() {
+                    i = j;
+                    while i < chars.len() && chars[i].is_ascii_digit() {
+                        i += 1;
+                    }
+                }
+            }
+            // Digit separators like 1_000 or 1,000,000
+            let raw: String = chars[start..i].iter().collect();
+            if raw.contains(',') && !is_thousands_grouping(&raw) {
+                return Err(format!(
+                    "ambiguous comma in '{}': write decimals with '.' and thousands as 1,000,000 or 1000000",
+                    raw
+                ));
+            }
+            let text: String = raw.chars().filter(|c| *c != '_' && *c != ',').collect();
+            tokens.push(Token::Number(
+                text.parse()
+                    .map_err(|_| format!("invalid number '{}'", text))?,
+            ));
+        } else if c.is_alphabetic() {
+            let start = i;
+            while i < chars.len() && chars[i].is_alphanumeric() {
+                i += 1;
+            }
+            tokens.push(Token::Name(
+                chars[start..i].iter().collect::<String>().to_lowercase(),
+            ));
+        } else if "+-*/%^()".contains(c) {
+            tokens.push(Token::Operator(c));
+            i += 1;
+        } else {
+            return Err(format!("unexpected character '{}'", c));
+        }
+    }
+
+    Ok(tokens)
+}
+
+// Commas only separate groups of three digits before the decimal point. Anything else, like
+// 1,5 (a decimal comma in much of Europe), would be a guess.
+fn is_thousands_grouping(number: &str) -> bool {
+    let mantissa = number.split(['e', 'E']).next().unwrap_or_default();
+    let (integer, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    let mut groups = integer.split(',');
+    let first = groups.next().unwrap_or_default();
+    !fraction.contains(',')
+        && (1..=3).contains(&first.len())
+        && groups.all(|group| group.len() == 3 && group.chars().all(|c| c.is_ascii_digit()))
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn eat(&mut self, operator: char) -> bool {
+        if self.peek() == Some(&Token::Operator(operator)) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expression(&mut self) -> Result<f64, String> {
+        let mut value = self.term()?;
+        loop {
+            if self.eat('+') {
+                value += self.term()?;
+            } else if self.eat('-') {
+                value -= self.term()?;
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn term(&mut self) -> Result<f64, String> {
+        let mut value = self.power()?;
+        loop {
+            if self.eat('*') {
+                value *= self.power()?;
+            } else if self.eat('/') {
+                let divisor = self.power()?;
+                if divisor == 0.0 {
+                    return Err("division by zero".to_string());
+                }
+                value /= divisor;
+            } else if self.eat('%') {
+                let divisor = self.power()?;
+                if divisor == 0.0 {
+                    return Err("modulo by zero".to_string());
+                }
+                value %= divisor;
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    // Right associative: 2^3^2 = 2^9
+    fn power(&mut self) -> Result<f64, String> {
+        let base = self.unary()?;
+        if self.eat('^') {
+            Ok(base.powf(self.power()?))
+        } else {
+            Ok(base)
+        }
+    }
+
+    fn unary(&mut self) -> Result<f64, String> {
+        if self.eat('-') {
+            Ok(-self.unary()?)
+        } else if self.eat('+') {
+            self.unary()
+        } else {
+            self.primary()
+        }
+    }
+
+    fn primary(&mut self) -> Result<f64, String> {
+        match self.next() {
+            Some(Token::Number(value)) => Ok(value),
+            Some(Token::Operator('(')) => {
+                let value = self.expression()?;
+                if !self.eat(')') {
+                    return Err("missing ')'".to_string());
+                }
+                Ok(value)
+            }
+            Some(Token::Name(name)) => match name.as_str() {
+                "pi" => Ok(std::f64::consts::PI),
+                "e" => Ok(std::f64::consts::E),
+                _ => {
+                    if !self.eat('(') {
+                        return Err(format!("unknown name '{}'", name));
+                    }
+                    let argument = self.expression()?;
+                    if !self.eat(')') {
+                        return Err("missing ')'".to_string());
+                    }
+                    apply(&name, argument)
+                }
+            },
+            Some(token) => Err(format!("unexpected {:?}", token)),
+            None => Err("unexpected end of expression".to_string()),
+        }
+    }
+}
+
+fn apply(function: &str, x: f64) -> Result<f64, String> {
+    Ok(match function {
+        "sqrt" => x.sqrt(),
+        "abs" => x.abs(),
+        "round" => x.round(),
+        "floor" => x.floor(),
+        "ceil" => x.ceil(),
+        "ln" => x.ln(),
+        "log" | "log10" => x.log10(),
+        "log2" => x.log2(),
+        "exp" => x.exp(),
+        "sin" => x.sin(),
+        "cos" => x.cos(),
+        "tan" => x.tan(),
+        "asin" => x.asin(),
+        "acos" => x.acos(),
+        "atan" => x.atan(),
+        _ => return Err(format!("unknown function '{}'", function)),
+    })
+}
+
+fn arithmetic(input: &str) -> Result<f64, String> {
+    let mut parser = Parser {
+        tokens: tokenize(input)?,
+        position: 0,
+    };
+    let value = parser.expression()?;
+    if parser.position < parser.tokens.len() {
+        return Err(format!("unexpected {:?}", parser.tokens[parser.position]));
+    }
+    if !value.is_finite() {
+        return Err("result is not a finite number".to_string());
+    }
+    Ok(value)
+}
+
+// Unit conversion: "<expression> <unit> to|in <unit>"
+
+fn find_unit(name: &str) -> Option<(&'static str, f64)> {
+    let name = name.to_lowercase();
+    UNITS
+        .iter()
+        .find(|(names, _, _)| names.contains(&name.as_str()))
+        .map(|(_, dimension, factor)| (*dimension, *factor))
+}
+
+fn to_kelvin(value: f64, unit: &str) -> Option<f64> {
+    match unit {
+        "c" | "celsius" => Some(value + 273.15),
+        "f" | "fahrenheit" => Some((value - 32.0) * 5.0 / 9.0 + 273.15),
+        "k" | "kelvin" => Some(value),
+        _ => None,
+    }
+}
+
+fn from_kelvin(value: f64, unit: &str) -> Option<f64> {
+    match unit {
+        "c" | "celsius" => Some(value - 273.15),
+        "f" | "fahrenheit" => Some((value - 273.15) * 9.0 / 5.0 + 32.0),
+        "k" | "kelvin" => Some(value),
+        _ => None,
+    }
+}
+
+fn convert(input: &str) -> Option<Result<String, String>> {
+    let words: Vec<&str> = input.split_whitespace().collect();
+    // The target unit is the last word and the separator the one before it, since "in" is a
+    // unit too (5 ft to in)
+    if words.len() < 4 || !["to", "in"].contains(&words[words.len() - 2]) {
+        return None;
+    }
+    let separator = words.len() - 2;
+
+    let from = words[separator - 1].to_lowercase();
+    let to = words[separator + 1].to_lowercase();
+    let amount = words[..separator - 1].join(" ");
+
+    let (from, to) = (from.trim_start_matches('°'), to.trim_start_matches('°'));
+    if to_kelvin(0.0, from).is_some() {
+        let value = match arithmetic(&amount) {
+            Ok(value) => value,
+            Err(e) => return Some(Err(e)),
+        };
+        return Some(
+            to_kelvin(value, from)
+                .and_then(|kelvin| from_kelvin(kelvin, to))
+                .map(|result| {
+                    format!(
+                        "{} {} = {} {}",
+                        format_number(value),
+                        from,
+                        format_number(result),
+                        to
+                    )
+                })
+                .ok_or_else(|| format!("cannot convert {} to {}", from, to)),
+        );
+    }
+
+    let (from_dimension, from_factor) = find_unit(from)?;
+    let result = match find_unit(to) {
+        Some((to_dimension, to_factor)) if to_dimension == from_dimension => arithmetic(&amount)
+            .map(|value| {
+                format!(
+                    "{} {} = {} {}",
+                    format_number(value),
+                    from,
+                    format_number(value * from_factor / to_factor),
+                    to
+                )
+            }),
+        Some((to_dimension, _)) => Err(format!(
+            "cannot convert {} ({}) to {} ({})",
+            from, from_dimension, to, to_dimension
+        )),
+        None => Err(format!("unknown unit '{}'", to)),
+    };
+    Some(result)
+}
+
+// Date math: "2024-03-01 + 90 days", "2024-12-25 - today", "today - 2 weeks"
+
+// Days since 1970-01-01 for a proleptic Gregorian date (Howard Hinnant's algorithm)
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let month_index = (month + 9) % 12;
+    let day_of_year = (153 * month_index + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+pub fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = if days >= 0 { days } else { days - 146096 } / 146097;
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn parse_date(text: &str) -> Option<i64> {
+    if text == "today" {
+        let seconds = SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_secs() as i64;
+        return Some(seconds / 86400);
+    }
+    let parts: Vec<i64> = text
+        .split('-')
+        .map(|p| p.parse().ok())
+        .collect::<Option<_>>()?;
+    match parts[..] {
+        [year, month, day]
+            if (1..=12).contains(&month) && (1..=31).contains(&day) && text.len() == 10 =>
+        {
+            let days = days_from_civil(year, month, day);
+            // Reject dates like 2023-02-30 that would silently roll over
+            (civil_from_days(days) == (year, month, day)).then_some(days)
+        }
+        _ => None,
+    }
+}
+
+// Today's date as YYYY-MM-DD (UTC)
+pub fn today() -> String {
+    let days = parse_date("today").unwrap_or(0);
+    let (year, month, day) = civil_from_days(days);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+fn format_date(days: i64) -> String {
+    const WEEKDAYS: [&str; 7] = [
+        "Thursday",
+        "Friday",
+        "Saturday",
+        "Sunday",
+        "Monday",
+        "Tuesday",
+        "Wednesday",
+    ];
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{:04}-{:02}-{:02} ({})",
+        year,
+        month,
+        day,
+        WEEKDAYS[days.rem_euclid(7) as usize]
+    )
+}
+
+fn date_math(input: &str) -> Option<Result<String, String>> {
+    let words: Vec<String> = input.split_whitespace().map(|w| w.to_lowercase()).collect();
+    let first = words.first()?;
+    let looks_like_date = first.len() == 10 && first.chars().filter(|c| *c == '-').count() == 2;
+    let start = match parse_date(first) {
+        Some(start) => start,
+        None if looks_like_date => return Some(Err(format!("invalid date '{}'", first))),
+        None => return None,
+    };
+
+    match words.iter().map(String::as_str).collect::<Vec<_>>()[1..] {
+        [] => Some(Ok(format_date(start))),
+        ["-", other] if parse_date(other).is_some() => {
+            let difference = start - parse_date(other).unwrap();
+            Some(Ok(format!("{} days", difference)))
+        }
+        [sign @ ("+" | "-"), amount, unit] => {
+            let amount: i64 = match amount.parse() {
+                Ok(amount) => amount,
+                Err(_) => return Some(Err(format!("invalid amount '{}'", amount))),
+            };
+            let days = match unit.trim_end_matches('s') {
+                "day" => amount,
+                "week" => amount * 7,
+                _ => {
+                    return Some(Err(format!(
+                        "date math supports days and weeks, not '{}'",
+                        unit
+                    )))
+                }
+            };
+            let days = if sign == "+" { days } else { -days };
+            Some(Ok(format_date(start + days)))
+        }
+        _ => Some(Err(
+            "expected '<date> +/- <n> days|weeks' or '<date> - <date>'".to_string(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn precedence_and_associativity() {
+        assert_eq!(evaluate("2 + 3 * 4").unwrap(), "14");
+        assert_eq!(evaluate("(2 + 3) * 4").unwrap(), "20");
+        assert_eq!(evaluate("2 ^ 3 ^ 2").unwrap(), "512");
+        assert_eq!(evaluate("10 - 4 - 3").unwrap(), "3");
+        assert_eq!(evaluate("7 % 4 * 2").unwrap(), "6");
+        assert_eq!(evaluate("0.1 + 0.2").unwrap(), "0.3");
+        assert_eq!(evaluate("sqrt(16) + 2e3").unwrap(), "2004");
+        assert!(evaluate("1 / 0").is_err());
+        assert!(evaluate("(1 + 2").is_err());
+    }
+
+    #[test]
+    fn unit_conversion() {
+        assert_eq!(evaluate("5 ft to in").unwrap(), "5 ft = 60 in");
+        assert_eq!(evaluate("12 in in cm").unwrap(), "12 in = 30.48 cm");
+        assert_eq!(evaluate("2 * 3 km to m").unwrap(), "6 km = 6000 m");
+        assert_eq!(evaluate("1 gib to mib").unwrap(), "1 gib = 1024 mib");
+        assert_eq!(evaluate("100 C to F").unwrap(), "100 c = 212 f");
+        assert!(evaluate("5 kg to m")
+            .unwrap_err()
+            .contains("cannot convert"));
+        assert!(evaluate("5 ft to furlongs")
+            .unwrap_err()
+            .contains("unknown unit"));
+    }
+
+    #[test]
+    fn digit_separators() {
+        assert_eq!(evaluate("1,000,000 / 4").unwrap(), "250000");
+        assert_eq!(evaluate("1,234.5 + 0.5").unwrap(), "1235");
+        assert_eq!(evaluate("1_000 * 3").unwrap(), "3000");
+        for ambiguous in ["1,5", "1,50", "1,0000", "1.000,5", "1,2,3", "1, 5"] {
+            assert!(evaluate(ambiguous).is_err(), "{}", ambiguous);
+        }
+    }
+
+    #[test]
+    fn date_arithmetic() {
+        assert_eq!(
+            evaluate("2024-03-01 + 90 days").unwrap(),
+            "2024-05-30 (Thursday)"
+        );
+        assert_eq!(evaluate("2024-12-25 - 2024-01-01").unwrap(), "359 days");
+        assert_eq!(
+            evaluate("2024-03-01 - 1 week").unwrap(),
+            "2024-02-23 (Friday)"
+        );
+        assert!(evaluate("2023-02-30").is_err());
+    }
+}
@@ -0,0 +1,132 @@
+use crate::conversation::Message;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+// What a model accepts, so request building never has to guess from its name
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ModelCapabilities {
+    pub system_role: bool,     //Otherwise the startup message is sent as a user turn
+    pub sampling_params: bool, //max_tokens and temperature
+    pub vision: bool,
+    pub context_window: usize, //In tokens, for the context gauge; 0 when unknown
+}
+
+impl Default for ModelCapabilities {
+    fn default() -> Self {
+        ModelCapabilities {
+            system_role: true,
+            sampling_params: true,
+            vision: true,
+            context_window: 0,
+        }
+    }
+}
+
+// Known reasoning and text-only models. Entries in `model_capabilities` take precedence.
+fn built_in() -> Vec<(&'static str, ModelCapabilities)> {
+    let reasoning = ModelCapabilities {
+        sampling_params: false,
+        context_window: 200_000,
+        ..Default::default()
+    };
+    let window = |context_window| ModelCapabilities {
+        context_window,
+        ..Default::default()
+    };
+    vec![
+        (
+            "o1*",
+            ModelCapabilities {
+                system_role: false,
+                sampling_params: false,
+                vision: true,
+                context_window: 200_000,
+            },
+        ),
+        (
+            "o1-mini*",
+            ModelCapabilities {
+                system_role: false,
+                sampling_params: false,
+                vision: false,
+                context_window: 128_000,
+            },
+        ),
+        ("o3*", reasoning.clone()),
+        (
+            "o3-mini*",
+            ModelCapabilities {
+                vision: false,
+                ..reasoning.clone()
+            },
+        ),
+        ("o4*", reasoning.clone()),
+        (
+            "gpt-5*",
+            ModelCapabilities {
+                context_window: 400_000,
+                ..reasoning
+            },
+        ),
+        (
+            "gpt-3.5*",
+            ModelCapabilities {
+                vision: false,
+                context_window: 16_385,
+                ..Default::default()
+            },
+        ),
+        ("gpt-4*", window(8_192)),
+        ("gpt-4-turbo*", window(128_000)),
+        ("gpt-4o*", window(128_000)),
+        ("gpt-4.1*", window(1_047_576)),
+        ("claude*", window(200_000)),
+    ]
+}
+
+// A trailing `*` matches any suffix, anything else must match the whole name
+fn matches(pattern: &str, model: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => model.starts_with(prefix),
+        None => pattern == model,
+    }
+}
+
+// The most specific matching pattern wins, configured ones before built-in ones
+pub fn for_model(model: &str, configured: &HashMap<String, ModelCapabilities>) -> ModelCapabilities {
+    let most_specific = |candidates: Vec<(&str, &ModelCapabilities)>| {
+        candidates
+            .into_iter()
+            .filter(|(pattern, _)| matches(pattern, model))
+            .max_by_key(|(pattern, _)| (!pattern.ends_with('*'), pattern.len()))
+            .map(|(_, capabilities)| capabilities.clone())
+    };
+
+    let built_in = built_in();
+    most_specific(configured.iter().map(|(pattern, c)| (pattern.as_str(), c)).collect())
+        .or_else(|| most_specific(built_in.iter().map(|(pattern, c)| (*pattern, c)).collect()))
+        .unwrap_or_default()
+}
+
+// Rewrites what a model can't take: system turns become user turns, images become a note.
+// Returns how many messages changed.
+pub fn fit_history(messages: &mut [Message], capabilities: &ModelCapabilities) -> usize {
+    let mut changed = 0;
+    for message in messages {
+        let mut fitted = false;
+        if !capabilities.system_role && message.role == "system" {
+            message.role = "user".to_string();
+            fitted = true;
+        }
+        if let (false, Value::Array(parts)) = (capabilities.vision, &mut message.content) {
+            for part in parts.iter_mut().filter(|part| part["type"] == "image_url") {
+                *part = json!({ "type": "text", "text": "[An image was attached here]" });
+                fitted = true;
+            }
+        }
+        changed += fitted as usize;
+    }
+    changed
+}
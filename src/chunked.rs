@@ -0,0 +1,114 @@
+use crate::conversation::{self, ConversationState, Message};
+use crate::style::{epaint, Style};
+use crate::{complete, print_response, Settings};
+use serde_json::Value;
+use std::path::Path;
+
+const DEFAULT_QUESTION: &str = "Summarize the key points.";
+const NOTHING_RELEVANT: &str = "NOTHING RELEVANT";
+
+// Splits at line boundaries into pieces of at most `size` characters. Lines longer than that
+// are cut.
+fn split_chunks(text: &str, size: usize) -> Vec<String> {
+    let mut chunks = vec![];
+    let mut current = String::new();
+    let mut current_chars = 0;
+
+    for line in text.lines() {
+        let mut line: Vec<char> = line.chars().collect();
+        line.push('\n');
+        for piece in line.chunks(size) {
+            if current_chars + piece.len() > size && !current.is_empty() {
+                chunks.push(std::mem::take(&mut current));
+                current_chars = 0;
+            }
+            current.extend(piece);
+            current_chars += piece.len();
+        }
+    }
+    if !current.trim().is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+// Answers a question about input too large for one request: every chunk is processed on its
+// own (map), then the partial answers are merged in a final call (reduce)
+pub fn map_reduce(
+    input: &str,
+    question: Option<String>,
+    state: &mut ConversationState,
+    transcript_path: &Path,
+    settings: &Settings,
+) {
+    let question = question.unwrap_or_else(|| DEFAULT_QUESTION.to_string());
+    let chunks = split_chunks(input, settings.chunk_chars.max(1));
+    let total = chunks.len();
+
+    let mut partials = vec![];
+    for (index, chunk) in chunks.iter().enumerate() {
+        eprintln!(
+            "{}",
+            epaint(
+                Style::Dim,
+                &format!("Processing part {}/{}...", index + 1, total)
+            )
+        );
+        let prompt = format!(
+            "You are reading part {} of {} of a large input, one part at a time. Extract everything in this part that helps answer the question, as concise notes. If nothing is relevant, reply {} only.\n\nQuestion: {}\n\nPart {}:\n```\n{}\n```",
+            index + 1,
+            total,
+            NOTHING_RELEVANT,
+            question,
+            index + 1,
+            chunk
+        );
+        match complete(prompt, settings) {
+            Some(partial) if !partial.trim().starts_with(NOTHING_RELEVANT) => {
+                partials.push(format!("### Part {}\n{}", index + 1, partial))
+            }
+            Some(_) => {}
+            None => {
+                eprintln!(
+                    "{}",
+                    epaint(
+                        Style::Error,
+                        &format!("Part {} failed, aborting.", index + 1)
+                    )
+                );
+                return;
+            }
+        }
+    }
+
+    let answer = if partials.is_empty() {
+        "None of the input was relevant to the question.".to_string()
+    } else {
+        let prompt = format!(
+            "The notes below were extracted from consecutive parts of one large input. Combine them into a single, coherent answer to the question, removing repetition.\n\nQuestion: {}\n\n{}",
+            question,
+            partials.join("\n\n")
+        );
+        match complete(prompt, settings) {
+            Some(answer) => answer,
+            None => return,
+        }
+    };
+
+    print_response(&answer, settings);
+
+    // The conversation records the question and the merged answer, not the raw input
+    state.messages.push(Message::new(
+        "user",
+        Value::String(format!(
+            "{}\n\n[Piped input of {} characters, processed in {} parts]",
+            question,
+            input.chars().count(),
+            total
+        )),
+    ));
+    state
+        .messages
+        .push(Message::new("assistant", Value::String(answer)));
+    conversation::save(state, transcript_path);
+}
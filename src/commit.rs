@@ -0,0 +1,67 @@
+use crate::style::{self, epaint, Style};
+use crate::{complete, Settings};
+use std::process::Command;
+
+// Drafts a commit message for the staged changes, lets the user edit it and optionally commits
+pub fn generate_commit_message(settings: &Settings) {
+    let diff = match Command::new("git").args(["diff", "--staged"]).output() {
+        Ok(output) if output.status.success() => String::from_utf8_lossy(&output.stdout).to_string(),
+        Ok(output) => {
+            eprintln!("{}", String::from_utf8_lossy(&output.stderr).trim());
+            return;
+        }
+        Err(e) => {
+            eprintln!("Failed to run git: {}", e);
+            return;
+        }
+    };
+
+    if diff.trim().is_empty() {
+        println!("Nothing staged. Stage your changes with git add first.");
+        return;
+    }
+
+    let prompt = format!("{}\n\n{}", settings.commit_template, diff);
+    let draft = match complete(prompt, settings) {
+        Some(draft) => strip_fences(&draft),
+        None => return,
+    };
+
+    // Editor::edit returns None when the file is closed without saving
+    let message = match dialoguer::Editor::new().edit(&draft) {
+        Ok(Some(edited)) => edited.trim().to_string(),
+        Ok(None) => draft,
+        Err(e) => {
+            eprintln!("Could not open editor ({}). Using the generated message.", e);
+            draft
+        }
+    };
+
+    if message.is_empty() {
+        println!("Empty commit message, aborting.");
+        return;
+    }
+
+    println!("{}\n", message);
+    let confirm = dialoguer::Confirm::with_theme(&*style::dialog_theme())
+        .with_prompt(epaint(Style::Prompt, "Commit with this message?"))
+        .default(false)
+        .interact()
+        .unwrap_or(false);
+
+    if confirm {
+        if let Err(e) = Command::new("git").args(["commit", "-m", &message]).status() {
+            eprintln!("Failed to run git commit: {}", e);
+        }
+    }
+}
+
+// Models like to wrap the message in a code block despite being told not to
+pub fn strip_fences(text: &str) -> String {
+    text.lines()
+        .filter(|line| !line.trim_start().starts_with("```"))
+        .collect::<Vec<&str>>()
+        .join("\n")
+        .trim()
+        .to_string()
+}
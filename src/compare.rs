@@ -0,0 +1,246 @@
+use crate::commit::strip_fences;
+use crate::style::{epaint, paint, Style};
+use crate::{capabilities, complete, Settings, Spinner};
+use serde_json::{json, Value};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// One compared answer
+pub struct Candidate {
+    pub name: String,
+    pub model: String,
+    pub answer: Option<String>,
+    pub elapsed: Duration,
+}
+
+// Settings for a --compare target: a configured provider, or else a model on the default one
+pub fn target_settings(name: &str, settings: &Settings) -> Settings {
+    let mut target = settings.clone();
+    target.show_spinner = false;
+    target.show_stats = false;
+
+    match settings.providers.get(name) {
+        Some(provider) => {
+            target.model = provider.model.clone();
+            if let Some(host) = &provider.host {
+                target.host = host.clone();
+            }
+            if let Some(endpoint) = &provider.endpoint {
+                target.endpoint = endpoint.clone();
+            }
+            // A provider with its own key doesn't inherit the default credentials
+            if let Some(variable) = &provider.api_key_variable {
+                target.api_key_variable = variable.clone();
+                target.api_key_variables = vec![];
+                target.api_keys = vec![];
+                target.api_key_command = "".to_string();
+                target.api_key_keyring = "".to_string();
+            }
+        }
+        None => target.model = name.to_string(),
+    }
+    target
+}
+
+// Sends the prompt to every target at once. Answers come back in the order given.
+pub fn ask_all(targets: &[String], prompt: &str, settings: &Settings) -> Vec<Candidate> {
+    let spinner = Spinner::start(settings.show_spinner);
+    let candidates = thread::scope(|scope| {
+        let handles: Vec<_> = targets
+            .iter()
+            .map(|name| {
+                let target = target_settings(name, settings);
+                scope.spawn(move || {
+                    let start = Instant::now();
+                    let answer = complete(prompt.to_string(), &target);
+                    Candidate {
+                        name: name.clone(),
+                        model: target.model.clone(),
+                        answer,
+                        elapsed: start.elapsed(),
+                    }
+                })
+            })
+            .collect();
+        handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+    });
+    spinner.stop();
+    candidates
+}
+
+// Answers go to stderr when stdout is reserved for the judge's JSON
+pub fn print_candidates(candidates: &[Candidate], to_stderr: bool) {
+    let show = |text: String| {
+        if to_stderr {
+            eprintln!("{}", text)
+        } else {
+            println!("{}", text)
+        }
+    };
+    let style = |style: Style, text: &str| if to_stderr { epaint(style, text) } else { paint(style, text) };
+
+    for candidate in candidates {
+        let label = if candidate.name == candidate.model {
+            candidate.name.clone()
+        } else {
+            format!("{} ({})", candidate.name, candidate.model)
+        };
+        show(format!(
+            "\n{}",
+            style(Style::Prompt, &format!("━━ {} · {:.1}s ━━", label, candidate.elapsed.as_secs_f64()))
+        ));
+        match &candidate.answer {
+            Some(answer) => show(answer.clone()),
+            None => show(style(Style::Error, "(no answer)")),
+        }
+    }
+}
+
+// Candidates are shown to the judge as A, B, C... so it can't favor a model by name
+fn candidate_label(index: usize) -> String {
+    ((b'A' + (index % 26) as u8) as char).to_string()
+}
+
+// Asks the judge to score every answer, then maps its verdict back to the targets
+fn judge(candidates: &[Candidate], prompt: &str, judge: &str, criteria: &str, settings: &Settings) -> Value {
+    let answers: Vec<String> = candidates
+        .iter()
+        .enumerate()
+        .filter_map(|(index, candidate)| {
+            candidate
+                .answer
+                .as_ref()
+                .map(|answer| format!("### Candidate {}\n{}", candidate_label(index), answer))
+        })
+        .collect();
+
+    let judge_prompt = format!(
+        "You are judging answers to the question below. Criteria: {}\n\nScore every candidate from 0 to 10 and reply with JSON only, in the form {{\"scores\": [{{\"candidate\": \"A\", \"score\": 7, \"reason\": \"...\"}}]}}.\n\n## Question\n{}\n\n## Answers\n{}",
+        criteria,
+        prompt,
+        answers.join("\n\n")
+    );
+
+    let judge_settings = target_settings(judge, settings);
+    let verdict: Option<Value> = complete(judge_prompt, &judge_settings)
+        .and_then(|reply| serde_json::from_str(&strip_fences(&reply)).ok());
+
+    let mut scores: Vec<Value> = candidates
+        .iter()
+        .enumerate()
+        .map(|(index, candidate)| {
+            let label = candidate_label(index);
+            let entry = verdict
+                .as_ref()
+                .and_then(|verdict| verdict["scores"].as_array())
+                .and_then(|scores| scores.iter().find(|score| score["candidate"] == label.as_str()));
+            json!({
+                "target": candidate.name,
+                "model": candidate.model,
+                "score": entry.map(|entry| entry["score"].clone()).unwrap_or(Value::Null),
+                "reason": entry.map(|entry| entry["reason"].clone()).unwrap_or(Value::Null),
+                "seconds": (candidate.elapsed.as_secs_f64() * 10.0).round() / 10.0,
+            })
+        })
+        .collect();
+    scores.sort_by(|a, b| {
+        let score = |entry: &Value| entry["score"].as_f64().unwrap_or(f64::MIN);
+        score(b).total_cmp(&score(a))
+    });
+    for (rank, entry) in scores.iter_mut().enumerate() {
+        entry["rank"] = json!(rank + 1);
+    }
+
+    if verdict.is_none() {
+        eprintln!("{}", epaint(Style::Warning, "The judge's reply could not be parsed as scores."));
+    }
+    json!({
+        "question": prompt,
+        "judge": judge_settings.model,
+        "criteria": criteria,
+        "scores": scores,
+    })
+}
+
+pub fn compare(targets: &str, prompt: &str, judge_target: Option<&String>, criteria: Option<&String>, settings: &Settings) {
+    let targets: Vec<String> = targets
+        .split(',')
+        .map(|target| target.trim().to_string())
+        .filter(|target| !target.is_empty())
+        .collect();
+    let candidates = ask_all(&targets, prompt, settings);
+    print_candidates(&candidates, judge_target.is_some());
+
+    if let Some(judge_target) = judge_target {
+        let criteria = criteria.unwrap_or(&settings.judge_criteria);
+        let spinner = Spinner::start(settings.show_spinner);
+        let verdict = judge(&candidates, prompt, judge_target, criteria, settings);
+        spinner.stop();
+        println!("{}", serde_json::to_string_pretty(&verdict).unwrap());
+    }
+}
+
+// `--sweep temperature=0.0,0.5,1.0`: the same prompt at every value at once, on the current
+// model. `max_tokens` can be swept the same way.
+pub fn sweep(spec: &str, prompt: &str, settings: &Settings) -> Result<(), String> {
+    let (parameter, values) = spec
+        .split_once('=')
+        .ok_or_else(|| format!("--sweep expects PARAMETER=VALUE,VALUE..., got '{}'", spec))?;
+    let parameter = parameter.trim();
+    let mut targets = vec![];
+    for value in values.split(',').map(str::trim).filter(|value| !value.is_empty()) {
+        let mut target = settings.clone();
+        target.show_spinner = false;
+        target.show_stats = false;
+        match parameter {
+            "temperature" => {
+                target.temperature = value
+                    .parse()
+                    .map_err(|_| format!("'{}' is not a temperature", value))?
+            }
+            "max_tokens" => {
+                target.max_tokens = value
+                    .parse()
+                    .map_err(|_| format!("'{}' is not a token count", value))?
+            }
+            _ => {
+                return Err(format!(
+                    "Can't sweep '{}', only temperature and max_tokens",
+                    parameter
+                ))
+            }
+        }
+        targets.push((format!("{}={}", parameter, value), target));
+    }
+    if targets.is_empty() {
+        return Err(format!("--sweep {} has no values", parameter));
+    }
+    let capabilities = capabilities::for_model(&settings.model, &settings.model_capabilities);
+    if !capabilities.sampling_params {
+        let message = format!("{} takes no {}, so every answer uses the same settings.", settings.model, parameter);
+        eprintln!("{}", epaint(Style::Warning, &message));
+    }
+
+    let spinner = Spinner::start(settings.show_spinner);
+    let candidates: Vec<Candidate> = thread::scope(|scope| {
+        let handles: Vec<_> = targets
+            .iter()
+            .map(|(name, target)| {
+                scope.spawn(move || {
+                    let start = Instant::now();
+                    let answer = complete(prompt.to_string(), target);
+                    Candidate {
+                        name: name.clone(),
+                        model: target.model.clone(),
+                        answer,
+                        elapsed: start.elapsed(),
+                    }
+                })
+            })
+            .collect();
+        handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+    });
+    spinner.stop();
+    print_candidates(&candidates, false);
+    Ok(())
+}
@@ -0,0 +1,553 @@
+use crate::message_text;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+
+// Set by --ephemeral: nothing gets written to disk for the rest of the process
+static EPHEMERAL: AtomicBool = AtomicBool::new(false);
+
+// What this process last read from or wrote to each transcript, so that on saving, changes
+// another ask process made in the meantime can be told apart from its own
+struct Synced {
+    stamp: Option<(u64, (u64, u32))>,
+    messages: Vec<u64>, //Fingerprints
+}
+
+static SYNCED: Mutex<BTreeMap<PathBuf, Synced>> = Mutex::new(BTreeMap::new());
+
+pub fn set_ephemeral() {
+    EPHEMERAL.store(true, Ordering::Relaxed);
+}
+
+// Bump whenever the on-disk format changes and add the matching step to `migrate`.
+pub const TRANSCRIPT_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Message {
+    pub role: String,
+    #[serde(default)] //Assistant turns that only call tools may omit it
+    pub content: Value,
+    // Native tool calling: calls requested by an assistant turn, and on "tool" turns the id
+    // of the call being answered and the tool's name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_calls: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+impl Message {
+    pub fn new(role: &str, content: Value) -> Message {
+        Message {
+            role: role.to_string(),
+            content,
+            tool_calls: None,
+            tool_call_id: None,
+            name: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ConversationState {
+    #[serde(default)]
+    pub version: u32,
+    pub model: String,
+    pub messages: Vec<Message>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<ConversationMetadata>,
+    #[serde(default, skip_serializing_if = "ConversationOverrides::is_empty")]
+    pub overrides: ConversationOverrides,
+    // --var NAME=VALUE, expanded wherever {{NAME}} appears in prompts and agent directives
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub variables: BTreeMap<String, String>,
+    // --note: added to the system prompt of this invocation's requests only, never saved
+    #[serde(skip)]
+    pub note: Option<String>,
+}
+
+// Settings changed for one conversation only (--set), so later invocations keep them
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ConversationOverrides {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    // Appended to the system prompt of every request
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_addendum: Option<String>,
+}
+
+impl ConversationOverrides {
+    pub fn is_empty(&self) -> bool {
+        self.temperature.is_none() && self.max_tokens.is_none() && self.system_addendum.is_none()
+    }
+}
+
+// Where the conversation was started, to help find it again later
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ConversationMetadata {
+    pub cwd: String,
+    pub hostname: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub git_branch: Option<String>,
+}
+
+impl ConversationMetadata {
+    pub fn capture() -> ConversationMetadata {
+        let git_branch = Command::new("git")
+            .args(["rev-parse", "--abbrev-ref", "HEAD"])
+            .stderr(Stdio::null())
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string());
+
+        ConversationMetadata {
+            cwd: env::current_dir()
+                .map(|dir| dir.display().to_string())
+                .unwrap_or_default(),
+            hostname: whoami::fallible::hostname().unwrap_or_default(),
+            git_branch,
+        }
+    }
+}
+
+impl ConversationState {
+    pub fn empty() -> ConversationState {
+        ConversationState {
+            version: TRANSCRIPT_VERSION,
+            model: "".to_string(),
+            messages: vec![],
+            title: None,
+            metadata: None,
+            overrides: ConversationOverrides::default(),
+            variables: BTreeMap::new(),
+            note: None,
+        }
+    }
+
+    // Applies a `key=value` override and describes the change. An empty value clears it.
+    pub fn apply_override(&mut self, assignment: &str) -> Result<String, String> {
+        let (key, value) = assignment
+            .split_once('=')
+            .ok_or_else(|| format!("Expected key=value, got '{}'", assignment))?;
+        let value = value.trim();
+        let parse_error = |kind: &str| format!("{} expects {}, got '{}'", key, kind, value);
+
+        match key.trim() {
+            "model" if value.is_empty() => return Err("model can't be empty".to_string()),
+            "model" => self.model = value.to_string(),
+            "temperature" | "temp" => {
+                self.overrides.temperature = match value {
+                    "" => None,
+                    _ => Some(value.parse().map_err(|_| parse_error("a number"))?),
+                }
+            }
+            "max_tokens" => {
+                self.overrides.max_tokens = match value {
+                    "" => None,
+                    _ => Some(value.parse().map_err(|_| parse_error("a whole number"))?),
+                }
+            }
+            "system" => self.overrides.system_addendum = Some(value.to_string()).filter(|v| !v.is_empty()),
+            other => {
+                return Err(format!(
+                    "Unknown setting '{}'. Conversations can override model, temperature, max_tokens and system",
+                    other
+                ))
+            }
+        }
+        Ok(if value.is_empty() {
+            format!("{} is back to the default for this conversation.", key)
+        } else {
+            format!("{} set to {} for this conversation.", key, value)
+        })
+    }
+}
+
+impl ConversationState {
+    // Sets a `NAME=value` variable and describes the change. An empty value removes it.
+    pub fn set_variable(&mut self, assignment: &str) -> Result<String, String> {
+        let (name, value) = assignment
+            .split_once('=')
+            .ok_or_else(|| format!("Expected NAME=value, got '{}'", assignment))?;
+        let name = name.trim();
+        if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+            return Err(format!("Variable names take letters, digits and _, got '{}'", name));
+        }
+        Ok(if value.is_empty() {
+            self.variables.remove(name);
+            format!("{{{{{}}}}} removed from this conversation.", name)
+        } else {
+            self.variables.insert(name.to_string(), value.to_string());
+            format!("{{{{{}}}}} is now {} in this conversation.", name, value)
+        })
+    }
+
+    // Replaces {{NAME}} with the variable's value. Unknown names are left as written.
+    pub fn expand(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        for (name, value) in &self.variables {
+            text = text.replace(&format!("{{{{{}}}}}", name), value);
+        }
+        text
+    }
+}
+
+// Upgrades a raw transcript one version at a time until it matches TRANSCRIPT_VERSION
+fn migrate(mut data: Value) -> Result<Value, String> {
+    let object = data
+        .as_object_mut()
+        .ok_or_else(|| "transcript is not a JSON object".to_string())?;
+    let mut version = object.get("version").and_then(|v| v.as_u64()).unwrap_or(0) as u32;
+
+    if version > TRANSCRIPT_VERSION {
+        return Err(format!(
+            "transcript version {} is newer than this build supports ({})",
+            version, TRANSCRIPT_VERSION
+        ));
+    }
+
+    while version < TRANSCRIPT_VERSION {
+        match version {
+            // Unversioned transcripts: only model and messages were guaranteed
+            0 => {
+                object.entry("model").or_insert_with(|| Value::String("".to_string()));
+                object.entry("messages").or_insert_with(|| Value::Array(vec![]));
+            }
+            _ => unreachable!(),
+        }
+        version += 1;
+    }
+
+    object.insert("version".to_string(), Value::from(TRANSCRIPT_VERSION));
+    Ok(data)
+}
+
+pub fn parse(data: &str) -> Result<ConversationState, String> {
+    let raw: Value = serde_json::from_str(data).map_err(|e| e.to_string())?;
+    serde_json::from_value(migrate(raw)?).map_err(|e| e.to_string())
+}
+
+// Loads a transcript, migrating older formats. Returns None when there is none yet.
+// Unreadable transcripts are set aside instead of aborting, so a fresh one can start.
+pub fn load(path: &Path) -> Option<ConversationState> {
+    if !path.exists() {
+        return None;
+    }
+
+    let (data, stamp) = {
+        let _lock = lock(path, false);
+        let data = fs::read_to_string(path).expect("Unable to read transcript file");
+        (data, file_stamp(path))
+    };
+    match parse(&data) {
+        Ok(state) => {
+            remember(path, stamp, &state.messages);
+            Some(state)
+        }
+        Err(e) => {
+            let backup = PathBuf::from(format!("{}.corrupt", path.display()));
+            eprintln!(
+                "WARNING: Could not load transcript ({}). Moved it to {} and starting over.",
+                e,
+                backup.display()
+            );
+            let _ = fs::rename(path, &backup);
+            None
+        }
+    }
+}
+
+// Lenient variant for listings, where a broken file shouldn't be moved around
+pub fn load_or_empty(path: &Path) -> ConversationState {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|data| parse(&data).ok())
+        .unwrap_or_else(ConversationState::empty)
+}
+
+// Saves the conversation, first taking in what other ask processes saved to the same
+// transcript since this one loaded it, so neither overwrites the other's turns
+pub fn save(state: &mut ConversationState, path: &Path) {
+    if EPHEMERAL.load(Ordering::Relaxed) {
+        return;
+    }
+
+    // Write-then-rename so a reader never sees a half-written file. The lock is held from
+    // re-reading to renaming, so no other save can slip in between.
+    let _lock = lock(path, true);
+    merge_concurrent_changes(state, path);
+    let conversation_json = serde_json::to_string(&*state).unwrap();
+    let temporary = PathBuf::from(format!("{}.tmp", path.display()));
+    fs::write(&temporary, &conversation_json).expect("Unable to write transcript file");
+    fs::rename(&temporary, path).expect("Unable to replace transcript file");
+    write_index(path, state, &conversation_json);
+    remember(path, file_stamp(path), &state.messages);
+}
+
+fn fingerprint(message: &Message) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    serde_json::to_string(message).unwrap().hash(&mut hasher);
+    hasher.finish()
+}
+
+fn remember(path: &Path, stamp: Option<(u64, (u64, u32))>, messages: &[Message]) {
+    let synced = Synced {
+        stamp,
+        messages: messages.iter().map(fingerprint).collect(),
+    };
+    SYNCED.lock().unwrap().insert(path.to_path_buf(), synced);
+}
+
+// Two processes that loaded the same transcript (two terminals on a global session, the bot
+// and a terminal...) each append their turn. Whichever saves second takes in the other's new
+// messages: theirs first, then its own. When both rewrote the history (edit, compact, clear),
+// this process's version wins and the other is kept next to it as `.conflict`.
+fn merge_concurrent_changes(state: &mut ConversationState, path: &Path) {
+    let base = {
+        let synced = SYNCED.lock().unwrap();
+        // A transcript this process never read is new, or replaced on purpose
+        match synced.get(path) {
+            Some(synced) if synced.stamp != file_stamp(path) => synced.messages.clone(),
+            _ => return,
+        }
+    };
+    let Some(disk) = fs::read_to_string(path).ok().and_then(|data| parse(&data).ok()) else {
+        return;
+    };
+    let theirs: Vec<u64> = disk.messages.iter().map(fingerprint).collect();
+    if theirs == base {
+        return;
+    }
+
+    let ours: Vec<u64> = state.messages.iter().map(fingerprint).collect();
+    if ours.starts_with(&base) {
+        let new = state.messages.split_off(base.len());
+        state.messages = disk.messages;
+        state.messages.extend(new);
+    } else if theirs.starts_with(&base) {
+        state
+            .messages
+            .extend(disk.messages.into_iter().skip(base.len()));
+    } else {
+        let conflict = PathBuf::from(format!("{}.conflict", path.display()));
+        let _ = fs::copy(path, &conflict);
+        eprintln!(
+            "WARNING: Another ask process changed this conversation's history too. Kept this version, theirs is in {}.",
+            conflict.display()
+        );
+    }
+}
+
+// `<transcript>.index`: what listings show about a transcript, and where each message sits in
+// the file. Listings, --last and previews read it instead of parsing every message body,
+// which pasted images can make megabytes long.
+#[derive(Serialize, Deserialize)]
+pub struct Header {
+    pub model: String,
+    pub title: Option<String>,
+    pub metadata: Option<ConversationMetadata>,
+    pub preview: String, //First line of the first message after the system prompt
+    messages: Vec<Span>,
+    // The transcript's size and modification time when indexed. Anything else means it was
+    // written without the index (an older ask, an edit by hand), so the index is ignored.
+    length: u64,
+    modified: (u64, u32),
+}
+
+// A message's role and its byte range in the transcript
+#[derive(Serialize, Deserialize)]
+struct Span {
+    role: String,
+    start: usize,
+    end: usize,
+}
+
+impl Header {
+    pub fn message_count(&self) -> usize {
+        self.messages.len()
+    }
+}
+
+fn index_path(path: &Path) -> PathBuf {
+    PathBuf::from(format!("{}.index", path.display()))
+}
+
+fn file_stamp(path: &Path) -> Option<(u64, (u64, u32))> {
+    let metadata = fs::metadata(path).ok()?;
+    let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some((
+        metadata.len(),
+        (modified.as_secs(), modified.subsec_nanos()),
+    ))
+}
+
+// Finds each message in the serialized transcript. serde_json writes a message inside the
+// transcript exactly as it writes it alone, and `"messages":[` can only be the key, since
+// quotes inside strings are escaped.
+fn spans(json: &str, messages: &[Message]) -> Option<Vec<Span>> {
+    let mut position = json.find("\"messages\":[")? + "\"messages\":[".len();
+    let mut spans = vec![];
+    for message in messages {
+        let text = serde_json::to_string(message).ok()?;
+        if !json[position..].starts_with(&text) {
+            return None;
+        }
+        spans.push(Span {
+            role: message.role.clone(),
+            start: position,
+            end: position + text.len(),
+        });
+        position += text.len() + 1; //The comma
+    }
+    Some(spans)
+}
+
+fn build_header(path: &Path, state: &ConversationState, json: &str) -> Option<Header> {
+    let (length, modified) = file_stamp(path)?;
+    let preview = state
+        .messages
+        .get(1)
+        .map(|message| message_text(&message.content))
+        .and_then(|text| text.lines().next())
+        .unwrap_or("")
+        .chars()
+        .take(100)
+        .collect();
+    Some(Header {
+        model: state.model.clone(),
+        title: state.title.clone(),
+        metadata: state.metadata.clone(),
+        preview,
+        messages: spans(json, &state.messages)?,
+        length,
+        modified,
+    })
+}
+
+fn write_index(path: &Path, state: &ConversationState, json: &str) {
+    match build_header(path, state, json) {
+        Some(header) => {
+            let _ = fs::write(index_path(path), serde_json::to_string(&header).unwrap());
+        }
+        None => {
+            let _ = fs::remove_file(index_path(path));
+        }
+    }
+}
+
+fn read_index(path: &Path) -> Option<Header> {
+    let header: Header = serde_json::from_str(&fs::read_to_string(index_path(path)).ok()?).ok()?;
+    let (length, modified) = file_stamp(path)?;
+    (header.length == length && header.modified == modified).then_some(header)
+}
+
+// The header from the index, or from parsing the whole transcript when the index is missing
+// or stale. The index is written then, so the next listing is fast.
+pub fn header(path: &Path) -> Header {
+    if let Some(header) = read_index(path) {
+        return header;
+    }
+    let data = fs::read_to_string(path).unwrap_or_default();
+    let state = parse(&data).unwrap_or_else(|_| ConversationState::empty());
+    match build_header(path, &state, &data) {
+        Some(header) => {
+            if !EPHEMERAL.load(Ordering::Relaxed) {
+                let _ = fs::write(index_path(path), serde_json::to_string(&header).unwrap());
+            }
+            header
+        }
+        // Older or hand-edited transcripts that aren't laid out like ours; no message offsets
+        None => Header {
+            model: state.model.clone(),
+            title: state.title.clone(),
+            metadata: state.metadata.clone(),
+            preview: String::new(),
+            messages: vec![],
+            length: 0,
+            modified: (0, 0),
+        },
+    }
+}
+
+// The last `count` messages (of `role`, when given), reading only their bytes when the
+// index is current
+pub fn load_tail(path: &Path, count: usize, role: Option<&str>) -> Vec<Message> {
+    let matches = |message_role: &str| role.is_none_or(|role| message_role == role);
+    let indexed = read_index(path).and_then(|header| {
+        let _lock = lock(path, false);
+        let mut file = File::open(path).ok()?;
+        let selected: Vec<&Span> = header
+            .messages
+            .iter()
+            .filter(|span| matches(&span.role))
+            .collect();
+        selected[selected.len().saturating_sub(count)..]
+            .iter()
+            .map(|span| {
+                let mut bytes = vec![0; span.end - span.start];
+                file.seek(SeekFrom::Start(span.start as u64)).ok()?;
+                file.read_exact(&mut bytes).ok()?;
+                serde_json::from_slice::<Message>(&bytes).ok()
+            })
+            .collect::<Option<Vec<Message>>>()
+    });
+    indexed.unwrap_or_else(|| {
+        let messages: Vec<Message> = load_or_empty(path)
+            .messages
+            .into_iter()
+            .filter(|message| matches(&message.role))
+            .collect();
+        let skip = messages.len().saturating_sub(count);
+        messages.into_iter().skip(skip).collect()
+    })
+}
+
+// Advisory lock on `<transcript>.lock`, held until the returned file is dropped. Shared for
+// reading, exclusive for writing. Locking is best effort: if it fails we proceed unlocked.
+fn lock(path: &Path, exclusive: bool) -> Option<File> {
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(format!("{}.lock", path.display()))
+        .ok()?;
+    let locked = if exclusive { file.lock() } else { file.lock_shared() };
+    locked.ok().map(|_| file)
+}
+
+// Deletes a transcript along with its lock file and index
+pub fn remove(path: &Path) -> std::io::Result<()> {
+    fs::remove_file(path)?;
+    remove_auxiliary_files(path);
+    Ok(())
+}
+
+pub fn remove_auxiliary_files(path: &Path) {
+    let _ = fs::remove_file(format!("{}.lock", path.display()));
+    let _ = fs::remove_file(index_path(path));
+}
+
+// Lock, index and temporary files that sit next to transcripts
+pub fn is_auxiliary_file(name: &str) -> bool {
+    name.ends_with(".corrupt")
+        || name.ends_with(".conflict")
+        || name.ends_with(".lock")
+        || name.ends_with(".tmp")
+        || name.ends_with(".index")
+}
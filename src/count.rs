@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Directories that hold dependencies or build output rather than the project's code. Hidden
+// ones (.git, .venv...) are skipped anyway.
+const SKIPPED_DIRS: &[&str] = &["node_modules", "target", "dist", "build", "vendor", "__pycache__", "venv"];
+
+pub const LANGUAGES: &[(&str, &str)] = &[
+    ("rs", "Rust"),
+    ("py", "Python"),
+    ("js", "JavaScript"),
+    ("mjs", "JavaScript"),
+    ("jsx", "JavaScript"),
+    ("ts", "TypeScript"),
+    ("tsx", "TypeScript"),
+    ("go", "Go"),
+    ("java", "Java"),
+    ("kt", "Kotlin"),
+    ("c", "C"),
+    ("h", "C"),
+    ("cpp", "C++"),
+    ("cc", "C++"),
+    ("hpp", "C++"),
+    ("cs", "C#"),
+    ("rb", "Ruby"),
+    ("php", "PHP"),
+    ("swift", "Swift"),
+    ("sh", "Shell"),
+    ("bash", "Shell"),
+    ("html", "HTML"),
+    ("css", "CSS"),
+    ("scss", "CSS"),
+    ("sql", "SQL"),
+    ("md", "Markdown"),
+    ("json", "JSON"),
+    ("yaml", "YAML"),
+    ("yml", "YAML"),
+    ("toml", "TOML"),
+    ("xml", "XML"),
+];
+
+const LARGEST_FILES: usize = 10;
+
+#[derive(Default)]
+struct Totals {
+    files: usize,
+    lines: usize,
+    blank: usize,
+}
+
+// Files, lines and non-blank lines per language under `dir`, followed by the largest files.
+// Hidden entries, dependency and build directories and anything `skip` rejects are left out.
+pub fn count(dir: &Path, skip: impl Fn(&Path) -> bool) -> Result<String, String> {
+    if !dir.is_dir() {
+        return Err(format!("{} is not a directory", dir.display()));
+    }
+
+    let mut languages: HashMap<&str, Totals> = HashMap::new();
+    let mut sizes: Vec<(usize, PathBuf)> = vec![];
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(current) = pending.pop() {
+        let Ok(entries) = fs::read_dir(&current) else {
+            continue;
+        };
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let path = entry.path();
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name.starts_with('.') || skip(&path) {
+                continue;
+            }
+            // file_type doesn't follow symlinks, so links can't send the walk in circles
+            match entry.file_type() {
+                Ok(kind) if kind.is_dir() && !SKIPPED_DIRS.contains(&name.as_str()) => {
+                    pending.push(path);
+                }
+                Ok(kind) if kind.is_file() => {
+                    let Some(language) = language(&path) else {
+                        continue;
+                    };
+                    // Not UTF-8: binary or generated, not worth counting
+                    let Ok(text) = fs::read_to_string(&path) else {
+                        continue;
+                    };
+                    let totals = languages.entry(language).or_default();
+                    let lines = text.lines().count();
+                    totals.files += 1;
+                    totals.lines += lines;
+                    totals.blank += text.lines().filter(|line| line.trim().is_empty()).count();
+                    sizes.push((lines, path));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    if languages.is_empty() {
+        return Ok(format!("No source files found under {}", dir.display()));
+    }
+
+    let mut rows: Vec<(&str, Totals)> = languages.into_iter().collect();
+    rows.sort_by_key(|(_, totals)| std::cmp::Reverse(totals.lines));
+    let mut report = format!(
+        "{:<12} {:>7} {:>9} {:>9}\n",
+        "Language", "Files", "Lines", "Code"
+    );
+    let mut total = Totals::default();
+    for (language, totals) in &rows {
+        report.push_str(&format!(
+            "{:<12} {:>7} {:>9} {:>9}\n",
+            language,
+            totals.files,
+            totals.lines,
+            totals.lines - totals.blank
+        ));
+        total.files += totals.files;
+        total.lines += totals.lines;
+        total.blank += totals.blank;
+    }
+    report.push_str(&format!(
+        "{:<12} {:>7} {:>9} {:>9}\n",
+        "Total",
+        total.files,
+        total.lines,
+        total.lines - total.blank
+    ));
+
+    sizes.sort_by_key(|(lines, _)| std::cmp::Reverse(*lines));
+    report.push_str("\nLargest files:\n");
+    for (lines, path) in sizes.iter().take(LARGEST_FILES) {
+        let shown = path.strip_prefix(dir).unwrap_or(path);
+        report.push_str(&format!("{:>9} {}\n", lines, shown.display()));
+    }
+    Ok(report)
+}
+
+fn language(path: &Path) -> Option<&'static str> {
+    let extension = path.extension()?.to_str()?.to_lowercase();
+    LANGUAGES
+        .iter()
+        .find(|(known, _)| *known == extension)
+        .map(|(_, language)| *language)
+}
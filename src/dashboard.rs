@@ -0,0 +1,337 @@
+use crate::audit;
+use crate::calc;
+use crate::conversation;
+use crate::ledger::{self, Totals};
+use crate::serve::{is_local_host, read_request};
+use crate::share::{self, escape};
+use crate::style::{epaint, Style};
+use crate::{archive_dir, list_transcripts, Settings};
+use std::collections::BTreeMap;
+use std::env;
+use std::io::Write;
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const RECENT_CONVERSATIONS: usize = 30;
+const RECENT_COMMANDS: usize = 100;
+const DAYS_SHOWN: i64 = 14;
+
+const STYLE: &str = "
+body { font: 14px/1.5 system-ui, sans-serif; max-width: 64rem; margin: 2rem auto; padding: 0 1rem; color: #1f2328; background: #fff; }
+h1 { font-size: 1.4rem; } h2 { font-size: 1.1rem; margin-top: 2rem; }
+table { border-collapse: collapse; width: 100%; }
+th, td { text-align: left; padding: 0.25rem 0.6rem; border-bottom: 1px solid #d0d7de; vertical-align: top; }
+td.n, th.n { text-align: right; font-variant-numeric: tabular-nums; }
+.dim { color: #656d76; } .ok { color: #1a7f37; } .bad { color: #cf222e; }
+.bar { background: #54aeff; height: 0.8rem; border-radius: 2px; }
+code { font-size: 0.85rem; white-space: pre-wrap; overflow-wrap: anywhere; }
+a { color: #0969da; }
+@media (prefers-color-scheme: dark) {
+  body { color: #e6edf3; background: #0d1117; } th, td { border-color: #30363d; }
+  .dim { color: #8b949e; } .ok { color: #3fb950; } .bad { color: #ff7b72; } a { color: #4493f8; }
+}
+";
+
+// `YYYY-MM-DD HH:MM`, UTC like the ledger's dates
+fn format_time(timestamp: u64) -> String {
+    let (year, month, day) = calc::civil_from_days((timestamp / 86400) as i64);
+    let minutes = timestamp % 86400 / 60;
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}",
+        year,
+        month,
+        day,
+        minutes / 60,
+        minutes % 60
+    )
+}
+
+fn page(title: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>{}</style>\n</head>\n<body>\n{}</body>\n</html>\n",
+        escape(title),
+        STYLE,
+        body
+    )
+}
+
+fn totals_row(label: &str, totals: &Totals) -> String {
+    format!(
+        "<tr><td>{}</td><td class=\"n\">{}</td><td class=\"n\">{}</td><td class=\"n\">{}</td><td class=\"n\">${:.4}</td></tr>\n",
+        escape(label),
+        totals.requests,
+        totals.prompt_tokens,
+        totals.completion_tokens,
+        totals.cost
+    )
+}
+
+fn usage_section() -> String {
+    let entries = ledger::entries();
+    if entries.is_empty() {
+        return "<h2>Usage</h2>\n<p class=\"dim\">No requests recorded yet.</p>\n".to_string();
+    }
+    let today = calc::today();
+    let month = &today[..7];
+    let mut periods = [Totals::default(), Totals::default(), Totals::default()];
+    let mut models: BTreeMap<&str, Totals> = BTreeMap::new();
+    let mut days: BTreeMap<&str, Totals> = BTreeMap::new();
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let first_day = (now / 86400) as i64 - DAYS_SHOWN + 1;
+    let (year, month_number, day) = calc::civil_from_days(first_day);
+    let since = format!("{:04}-{:02}-{:02}", year, month_number, day);
+    for entry in &entries {
+        if entry.date == today {
+            periods[0].add(entry);
+        }
+        if entry.date.starts_with(month) {
+            periods[1].add(entry);
+            models.entry(&entry.model).or_default().add(entry);
+        }
+        if entry.date >= since {
+            days.entry(&entry.date).or_default().add(entry);
+        }
+        periods[2].add(entry);
+    }
+
+    let header = "<tr><th></th><th class=\"n\">Requests</th><th class=\"n\">Tokens in</th><th class=\"n\">Tokens out</th><th class=\"n\">Cost</th></tr>\n";
+    let mut html = format!("<h2>Usage</h2>\n<table>\n{}", header);
+    for (label, totals) in ["Today", "This month", "All time"].iter().zip(&periods) {
+        html.push_str(&totals_row(label, totals));
+    }
+    html.push_str("</table>\n");
+
+    if !models.is_empty() {
+        html.push_str(&format!(
+            "<h2>This month by model</h2>\n<table>\n{}",
+            header
+        ));
+        for (model, totals) in &models {
+            html.push_str(&totals_row(model, totals));
+        }
+        html.push_str("</table>\n");
+    }
+
+    // Tokens per day, drawn as bars relative to the busiest day
+    let busiest = days
+        .values()
+        .map(|totals| totals.prompt_tokens + totals.completion_tokens)
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    html.push_str(&format!(
+        "<h2>Last {} days</h2>\n<table>\n<tr><th>Date</th><th class=\"n\">Requests</th><th class=\"n\">Tokens</th><th style=\"width: 50%\"></th></tr>\n",
+        DAYS_SHOWN
+    ));
+    for offset in 0..DAYS_SHOWN {
+        let (year, month, day) = calc::civil_from_days(first_day + offset);
+        let date = format!("{:04}-{:02}-{:02}", year, month, day);
+        let (requests, tokens) = days
+            .get(date.as_str())
+            .map(|totals| {
+                (
+                    totals.requests,
+                    totals.prompt_tokens + totals.completion_tokens,
+                )
+            })
+            .unwrap_or((0, 0));
+        html.push_str(&format!(
+            "<tr><td>{}</td><td class=\"n\">{}</td><td class=\"n\">{}</td><td><div class=\"bar\" style=\"width: {:.1}%\"></div></td></tr>\n",
+            date,
+            requests,
+            tokens,
+            tokens as f64 * 100.0 / busiest as f64
+        ));
+    }
+    html.push_str("</table>\n");
+    html
+}
+
+// Active and archived transcripts, newest first
+fn transcripts(settings: &Settings) -> Vec<(PathBuf, bool, u64)> {
+    let active = list_transcripts(&env::temp_dir(), settings)
+        .into_iter()
+        .map(|path| (path, false));
+    let archived = list_transcripts(&archive_dir(settings), settings)
+        .into_iter()
+        .map(|path| (path, true));
+    let mut transcripts: Vec<(PathBuf, bool, u64)> = active
+        .chain(archived)
+        .map(|(path, archived)| {
+            let modified = path
+                .metadata()
+                .and_then(|metadata| metadata.modified())
+                .ok()
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map(|d| d.as_secs())
+                .unwrap_or(0);
+            (path, archived, modified)
+        })
+        .collect();
+    transcripts.sort_by_key(|(_, _, modified)| std::cmp::Reverse(*modified));
+    transcripts
+}
+
+fn conversations_section(settings: &Settings) -> String {
+    let transcripts = transcripts(settings);
+    let mut html = format!(
+        "<h2>Recent conversations</h2>\n<p class=\"dim\">{} in total</p>\n<table>\n<tr><th>Updated</th><th>Title</th><th>Model</th><th class=\"n\">Messages</th><th>Directory</th></tr>\n",
+        transcripts.len()
+    );
+    for (path, archived, modified) in transcripts.iter().take(RECENT_CONVERSATIONS) {
+        let file = path.file_name().unwrap().to_string_lossy();
+        let header = conversation::header(path);
+        let title = header.title.clone().unwrap_or_else(|| file.to_string());
+        let directory = header
+            .metadata
+            .as_ref()
+            .map(|metadata| metadata.cwd.clone())
+            .unwrap_or_default();
+        html.push_str(&format!(
+            "<tr><td>{}</td><td><a href=\"/conversation?file={}\">{}</a>{}</td><td>{}</td><td class=\"n\">{}</td><td class=\"dim\">{}</td></tr>\n",
+            format_time(*modified),
+            escape(&file),
+            escape(&title),
+            if *archived { " <span class=\"dim\">(archived)</span>" } else { "" },
+            escape(&header.model),
+            header.message_count(),
+            escape(&directory)
+        ));
+    }
+    html.push_str("</table>\n");
+    html
+}
+
+fn audit_section() -> String {
+    let entries = audit::entries();
+    let mut html = "<h2>Agent commands</h2>\n".to_string();
+    if entries.is_empty() {
+        html.push_str("<p class=\"dim\">No agent commands recorded yet.</p>\n");
+        return html;
+    }
+    html.push_str(&format!(
+        "<p class=\"dim\">The latest {} of {}</p>\n<table>\n<tr><th>Time</th><th>Directory</th><th>Command</th><th>Outcome</th></tr>\n",
+        entries.len().min(RECENT_COMMANDS),
+        entries.len()
+    ));
+    for entry in entries.iter().rev().take(RECENT_COMMANDS) {
+        let place = match &entry.target {
+            Some(target) => escape(target),
+            None => escape(&entry.cwd),
+        };
+        let class = if entry.outcome == "exit 0" || entry.outcome.starts_with("started") {
+            "ok"
+        } else {
+            "bad"
+        };
+        html.push_str(&format!(
+            "<tr><td>{}</td><td class=\"dim\">{}</td><td><code>{}</code></td><td class=\"{}\">{}</td></tr>\n",
+            format_time(entry.timestamp),
+            place,
+            escape(&entry.command),
+            class,
+            escape(&entry.outcome)
+        ));
+    }
+    html.push_str("</table>\n");
+    html
+}
+
+// Status and body for a request. Only files listed on the overview can be opened.
+fn route(path: &str, settings: &Settings) -> (&'static str, String) {
+    let (path, query) = path.split_once('?').unwrap_or((path, ""));
+    match path {
+        "/" => {
+            let body = format!(
+                "<h1>ask</h1>\n{}{}{}",
+                usage_section(),
+                conversations_section(settings),
+                audit_section()
+            );
+            ("200 OK", page("ask dashboard", &body))
+        }
+        "/conversation" => {
+            let file = query.strip_prefix("file=").unwrap_or_default();
+            match transcripts(settings)
+                .into_iter()
+                .find(|(path, _, _)| path.file_name().is_some_and(|name| name == file))
+            {
+                Some((path, _, _)) => {
+                    ("200 OK", share::render(&conversation::load_or_empty(&path)))
+                }
+                None => (
+                    "404 Not Found",
+                    page("Not found", "<p>No such conversation.</p>\n"),
+                ),
+            }
+        }
+        _ => ("404 Not Found", page("Not found", "<p>Not found.</p>\n")),
+    }
+}
+
+// No CORS headers: other sites open in the browser must not read these pages
+fn respond(mut stream: &TcpStream, status: &str, body: &str) {
+    let _ = write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+}
+
+fn handle(stream: TcpStream, port: u16, settings: &Settings) {
+    let request = match read_request(&stream) {
+        Ok(request) => request,
+        Err(e) => {
+            respond(&stream, e.status, &escape(&e.message));
+            return;
+        }
+    };
+    if !is_local_host(&request.host, port) {
+        respond(&stream, "403 Forbidden", "Forbidden");
+        return;
+    }
+    if request.method != "GET" {
+        respond(
+            &stream,
+            "405 Method Not Allowed",
+            "The dashboard is read-only",
+        );
+        return;
+    }
+    let (status, body) = route(&request.path, settings);
+    respond(&stream, status, &body);
+}
+
+// `--dashboard`: a read-only page on localhost with the usage ledger, recent conversations and
+// the agent's command log, all read from local files
+pub fn dashboard(port: u16, settings: &Settings) {
+    let listener = match TcpListener::bind(("127.0.0.1", port)) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!(
+                "{}",
+                epaint(
+                    Style::Error,
+                    &format!("Could not listen on port {}: {}", port, e)
+                )
+            );
+            std::process::exit(1);
+        }
+    };
+    eprintln!("Dashboard at http://127.0.0.1:{}/ (Ctrl-C to stop)", port);
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => handle(stream, port, settings),
+            Err(e) => eprintln!(
+                "{}",
+                epaint(Style::Warning, &format!("Connection failed: {}", e))
+            ),
+        }
+    }
+}
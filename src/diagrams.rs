@@ -0,0 +1,83 @@
+use crate::style::{self, epaint, Style};
+use crate::{graphics, Settings};
+use atty::Stream;
+use std::env;
+use std::fs;
+use std::process::Command;
+
+// Finds ```mermaid / ```dot blocks in a response and offers to render them with a local tool
+pub fn offer_rendering(text: &str, settings: &Settings) {
+    if !atty::is(Stream::Stdin) || !atty::is(Stream::Stdout) {
+        return;
+    }
+
+    for (index, (language, source)) in extract_blocks(text).iter().enumerate() {
+        let command = match settings.diagram_commands.get(language) {
+            Some(command) => command,
+            None => continue,
+        };
+
+        let confirm = dialoguer::Confirm::with_theme(&*style::dialog_theme())
+            .with_prompt(epaint(Style::Prompt, &format!("Render {} diagram #{}?", language, index + 1)))
+            .default(false)
+            .interact()
+            .unwrap_or(false);
+        if !confirm {
+            continue;
+        }
+
+        let stem = env::temp_dir().join(format!("ask_diagram_{}_{}", std::process::id(), index));
+        let input = stem.with_extension("src");
+        let output = stem.with_extension("png");
+        if let Err(e) = fs::write(&input, source) {
+            eprintln!("Could not write diagram source: {}", e);
+            continue;
+        }
+
+        let command = command
+            .replace("{input}", &input.display().to_string())
+            .replace("{output}", &output.display().to_string());
+        let rendered = Command::new("sh")
+            .arg("-c")
+            .arg(&command)
+            .status()
+            .map(|status| status.success())
+            .unwrap_or(false);
+        let _ = fs::remove_file(&input);
+
+        match fs::read(&output) {
+            Ok(bytes) if rendered => {
+                println!("Rendered to {}", output.display());
+                graphics::display_image(&bytes, Some(&output), &settings.image_protocol);
+            }
+            _ => eprintln!("{}", epaint(Style::Error, &format!("Diagram command failed: {}", command))),
+        }
+    }
+}
+
+// (language, source) for every fenced code block
+fn extract_blocks(text: &str) -> Vec<(String, String)> {
+    let mut blocks = vec![];
+    let mut current: Option<(String, Vec<&str>)> = None;
+
+    for line in text.lines() {
+        let trimmed = line.trim_start();
+        match current.take() {
+            None => {
+                if let Some(info) = trimmed.strip_prefix("```") {
+                    let language = info.split_whitespace().next().unwrap_or("").to_lowercase();
+                    current = Some((language, vec![]));
+                }
+            }
+            Some((language, lines)) if trimmed.starts_with("```") => {
+                blocks.push((language, lines.join("\n")));
+            }
+            Some((language, mut lines)) => {
+                lines.push(line);
+                current = Some((language, lines));
+            }
+        }
+    }
+
+    blocks
+}
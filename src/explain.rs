@@ -0,0 +1,140 @@
+use crate::attach::take_within;
+use serde_json::Value;
+
+enum Kind {
+    StackTrace,
+    CompilerErrors,
+    Json,
+    Logs,
+    Plain,
+}
+
+const STRUCTURE: &str = "Structure the answer as:\n\
+Cause: what went wrong, in one or two sentences.\n\
+Relevant lines: the few lines of the output that show it, quoted as they are.\n\
+Fix: the concrete change or command that resolves it.\n\
+Leave out everything the output doesn't support, and say so when the cause is a guess.";
+
+// Compiler diagnostics like `src/main.rs:12:5: error` or rustc's `error[E0308]`
+fn is_diagnostic(line: &str) -> bool {
+    let line = line.trim_start();
+    if line.starts_with("error[E") || line.starts_with("error TS") || line.starts_with("--> ") {
+        return true;
+    }
+    let mut parts = line.splitn(4, ':');
+    let (Some(file), Some(row), Some(column), Some(rest)) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return false;
+    };
+    !file.is_empty()
+        && row.trim().parse::<u32>().is_ok()
+        && column.trim().parse::<u32>().is_ok()
+        && ["error", "warning", "fatal error"]
+            .iter()
+            .any(|level| rest.trim_start().starts_with(level))
+}
+
+// `2024-05-01 ...`, `[12:00:01] ...` or a level such as `INFO`/`ERROR` near the start
+fn is_log_line(line: &str) -> bool {
+    let start: String = line.trim_start_matches('[').chars().take(10).collect();
+    let dated = start.len() == 10
+        && start.chars().enumerate().all(|(index, c)| match index {
+            4 | 7 => c == '-' || c == '/',
+            _ => c.is_ascii_digit(),
+        });
+    let timed = start.get(2..3) == Some(":") && start.get(5..6) == Some(":");
+    let head: String = line.chars().take(40).collect::<String>().to_uppercase();
+    let levelled = ["INFO", "WARN", "ERROR", "DEBUG", "TRACE", "FATAL"]
+        .iter()
+        .any(|level| head.contains(level));
+    dated || timed || levelled
+}
+
+fn detect(output: &str) -> Kind {
+    let lines: Vec<&str> = output
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .collect();
+    let stack_markers = [
+        "Traceback (most recent call last)",
+        "panicked at",
+        "Exception in thread",
+        "goroutine ",
+        "stack backtrace:",
+    ];
+    let frames = lines
+        .iter()
+        .filter(|line| {
+            line.trim_start().starts_with("at ") || line.trim_start().starts_with("File \"")
+        })
+        .count();
+    if stack_markers.iter().any(|marker| output.contains(marker)) || frames >= 3 {
+        return Kind::StackTrace;
+    }
+    if lines.iter().any(|line| is_diagnostic(line)) {
+        return Kind::CompilerErrors;
+    }
+    let trimmed = output.trim();
+    if (trimmed.starts_with('{') || trimmed.starts_with('['))
+        && serde_json::from_str::<Value>(trimmed).is_ok()
+    {
+        return Kind::Json;
+    }
+    if !lines.is_empty() && lines.iter().filter(|line| is_log_line(line)).count() * 3 >= lines.len()
+    {
+        return Kind::Logs;
+    }
+    Kind::Plain
+}
+
+fn instruction(kind: &Kind) -> &'static str {
+    match kind {
+        Kind::StackTrace => "The output is a stack trace. Find the frame in the user's own code closest to the error rather than in libraries or the runtime, and explain the exception there.",
+        Kind::CompilerErrors => "The output holds compiler or linter errors. Start with the first error, since later ones often follow from it, and group errors with the same cause.",
+        Kind::Json => "The output is JSON, likely an API response or a config dump. Point out error fields, unexpected values and what they mean.",
+        Kind::Logs => "The output is a log. Find the first entry where things start to go wrong and the chain of events leading to it, rather than the last error only.",
+        Kind::Plain => "The output comes from a command that went wrong, or whose result the user doesn't understand.",
+    }
+}
+
+// `--explain`: the system note and the prompt for piped command output. The end of the output
+// matters most (that is where errors are printed), so long output keeps more of its tail.
+pub fn prepare(output: &str, question: Option<&str>, budget: usize) -> (String, String) {
+    let kind = detect(output);
+    let note = format!("{}\n{}", instruction(&kind), STRUCTURE);
+    let question = question.unwrap_or("Explain this output.");
+    (
+        note,
+        format!(
+            "```\n{}\n```\n\n{}",
+            shorten(output.trim_end(), budget),
+            question
+        ),
+    )
+}
+
+fn shorten(output: &str, budget: usize) -> String {
+    if output.chars().count() <= budget {
+        return output.to_string();
+    }
+    let lines: Vec<&str> = output.lines().collect();
+    let head = take_within(lines.iter().copied(), budget / 4);
+    let mut tail = take_within(
+        lines[head.len()..].iter().rev().copied(),
+        budget - budget / 4,
+    );
+    tail.reverse();
+    if tail.is_empty() {
+        // One huge last line: keep its end
+        let characters: Vec<char> = output.chars().collect();
+        let start = characters.len().saturating_sub(budget);
+        return format!("[...]{}", characters[start..].iter().collect::<String>());
+    }
+    format!(
+        "{}\n[... {} lines omitted ...]\n{}",
+        head.join("\n"),
+        lines.len() - head.len() - tail.len(),
+        tail.join("\n")
+    )
+}
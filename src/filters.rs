@@ -0,0 +1,150 @@
+use crate::commit::strip_fences;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::thread;
+
+// Runs the reply through each of `output_filters` in turn:
+//   strip-markdown   plain text without headings, emphasis, code fences or link syntax
+//   code             only the contents of the fenced code blocks
+//   json             the JSON value in the reply, pretty-printed
+//   jq:<filter>      the JSON value in the reply passed through jq
+//   sh:<command>     any command reading the text on stdin
+pub fn apply(text: &str, filters: &[String]) -> Result<String, String> {
+    filters.iter().try_fold(text.to_string(), |text, filter| {
+        let filter = filter.trim();
+        match filter {
+            "strip-markdown" => Ok(strip_markdown(&text)),
+            "code" => Ok(code_blocks(&text).unwrap_or(text)),
+            "json" => {
+                extract_json(&text).map(|value| serde_json::to_string_pretty(&value).unwrap())
+            }
+            _ => {
+                if let Some(expression) = filter.strip_prefix("jq:") {
+                    let value = extract_json(&text)?;
+                    pipe(
+                        &format!("jq {}", shell_quote(expression.trim())),
+                        &value.to_string(),
+                    )
+                } else if let Some(command) = filter.strip_prefix("sh:") {
+                    pipe(command.trim(), &text)
+                } else {
+                    Err(format!("Unknown output filter '{}'", filter))
+                }
+            }
+        }
+    })
+}
+
+fn shell_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "'\\''"))
+}
+
+fn pipe(command: &str, input: &str) -> Result<String, String> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Could not run '{}': {}", command, e))?;
+
+    let mut stdin = child.stdin.take().unwrap();
+    let input = input.to_string();
+    let writer = thread::spawn(move || {
+        let _ = stdin.write_all(input.as_bytes());
+    });
+    let output = child
+        .wait_with_output()
+        .map_err(|e| format!("'{}' failed: {}", command, e))?;
+    let _ = writer.join();
+
+    if !output.status.success() {
+        return Err(format!(
+            "'{}' failed: {}",
+            command,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .trim_end()
+        .to_string())
+}
+
+// The reply (or its fenced code) as JSON, falling back to its outermost {...} or [...] span
+fn extract_json(text: &str) -> Result<serde_json::Value, String> {
+    let unfenced = code_blocks(text).unwrap_or_else(|| strip_fences(text));
+    if let Ok(value) = serde_json::from_str(unfenced.trim()) {
+        return Ok(value);
+    }
+    let start = unfenced
+        .find(['{', '['])
+        .ok_or("The reply contains no JSON")?;
+    let end = unfenced
+        .rfind(['}', ']'])
+        .ok_or("The reply contains no JSON")?;
+    serde_json::from_str(&unfenced[start..=end.max(start)])
+        .map_err(|e| format!("The reply contains no valid JSON: {}", e))
+}
+
+fn code_blocks(text: &str) -> Option<String> {
+    let mut blocks = vec![];
+    let mut current: Option<Vec<&str>> = None;
+    for line in text.lines() {
+        if line.trim_start().starts_with("```") {
+            match current.take() {
+                Some(block) => blocks.push(block.join("\n")),
+                None => current = Some(vec![]),
+            }
+        } else if let Some(block) = current.as_mut() {
+            block.push(line);
+        }
+    }
+    (!blocks.is_empty()).then(|| blocks.join("\n\n"))
+}
+
+fn strip_markdown(text: &str) -> String {
+    let mut in_code = false;
+    let mut lines = vec![];
+    for line in text.lines() {
+        if line.trim_start().starts_with("```") {
+            in_code = !in_code;
+        } else if in_code {
+            lines.push(line.to_string());
+        } else {
+            let line = match line.trim_start_matches('#') {
+                heading if heading.len() < line.len() && heading.starts_with(' ') => {
+                    heading.trim_start()
+                }
+                _ => line,
+            };
+            lines.push(strip_links(
+                &line.replace("**", "").replace("__", "").replace('`', ""),
+            ));
+        }
+    }
+    lines.join("\n")
+}
+
+// [text](url) => text (url)
+fn strip_links(line: &str) -> String {
+    let mut output = String::new();
+    let mut rest = line;
+    while let Some(open) = rest.find('[') {
+        let Some(close) = rest[open..].find("](").map(|i| open + i) else {
+            break;
+        };
+        let Some(end) = rest[close..].find(')').map(|i| close + i) else {
+            break;
+        };
+        output.push_str(&rest[..open]);
+        output.push_str(&format!(
+            "{} ({})",
+            &rest[open + 1..close],
+            &rest[close + 2..end]
+        ));
+        rest = &rest[end + 1..];
+    }
+    output.push_str(rest);
+    output
+}
@@ -0,0 +1,323 @@
+use crate::agent::is_protected;
+use crate::attach::take_within;
+use crate::conversation::ConversationState;
+use crate::style::{self, epaint, paint, Style};
+use crate::{message_text, perform_unfiltered_request, Settings};
+use atty::Stream;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+// Lines shown above and below each error location
+const CONTEXT_LINES: usize = 10;
+const MAX_FILES: usize = 5;
+
+const PATCH_FORMAT: &str = "Reply with a unified diff in a single ```diff block (paths relative to the project root, with `--- a/path` and `+++ b/path` headers and correct hunk headers), followed by one sentence on what you changed. Make the smallest change that fixes the errors, and don't touch unrelated code.";
+
+// The project's build command: `build_command`, or one guessed from the files at the root
+fn detect_build(settings: &Settings) -> Option<String> {
+    if !settings.build_command.is_empty() {
+        return Some(settings.build_command.clone());
+    }
+    [
+        ("Cargo.toml", "cargo build --all-targets"),
+        ("package.json", "npm run build"),
+        ("go.mod", "go build ./..."),
+        ("Makefile", "make"),
+        ("pyproject.toml", "python -m compileall -q ."),
+    ]
+    .iter()
+    .find(|(file, _)| Path::new(file).exists())
+    .map(|(_, command)| command.to_string())
+}
+
+// Runs the build with stdout and stderr together, in the order they were written
+fn build(command: &str) -> (bool, String) {
+    match Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} 2>&1", command))
+        .stdin(Stdio::null())
+        .output()
+    {
+        Ok(output) => (
+            output.status.success(),
+            String::from_utf8_lossy(&output.stdout).to_string(),
+        ),
+        Err(e) => (false, format!("Could not run {}: {}", command, e)),
+    }
+}
+
+// `path:line` locations of existing files in the build output, e.g. `src/main.rs:12:5` or
+// rustc's `--> src/main.rs:12:5`
+fn locations(output: &str) -> BTreeMap<String, Vec<usize>> {
+    let mut found: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+    for word in output.split_whitespace() {
+        let word = word.trim_matches(|c: char| "()[]<>'\",".contains(c));
+        let mut parts = word.split(':');
+        let (Some(file), Some(line)) = (parts.next(), parts.next()) else {
+            continue;
+        };
+        let Ok(line) = line.parse::<usize>() else {
+            continue;
+        };
+        let file = file.trim_start_matches("./");
+        if found.len() >= MAX_FILES && !found.contains_key(file) || !Path::new(file).is_file() {
+            continue;
+        }
+        found.entry(file.to_string()).or_default().push(line);
+    }
+    found
+}
+
+// Numbered lines around every error location, so the model sees the code it has to change
+fn excerpts(output: &str, budget: usize) -> String {
+    let mut blocks = vec![];
+    for (file, mut lines) in locations(output) {
+        let Ok(text) = fs::read_to_string(&file) else {
+            continue;
+        };
+        let source: Vec<&str> = text.lines().collect();
+        lines.sort_unstable();
+        let mut shown: Vec<String> = vec![];
+        let mut last = 0;
+        for line in lines {
+            let start = line.saturating_sub(CONTEXT_LINES + 1).max(last);
+            let end = (line + CONTEXT_LINES).min(source.len());
+            if start >= end {
+                continue;
+            }
+            if last > 0 && start > last {
+                shown.push("...".to_string());
+            }
+            for (number, code) in source[start..end].iter().enumerate() {
+                shown.push(format!("{:>5} {}", start + number + 1, code));
+            }
+            last = end;
+        }
+        blocks.push(format!("{}:\n```\n{}\n```", file, shown.join("\n")));
+    }
+    let kept = take_within(blocks.iter().map(String::as_str), budget);
+    kept.join("\n\n")
+}
+
+// The build output, cut to its end when long, since that is where the errors are
+fn tail(output: &str, budget: usize) -> String {
+    let lines: Vec<&str> = output.trim_end().lines().collect();
+    let mut kept = take_within(lines.iter().rev().copied(), budget);
+    kept.reverse();
+    if kept.len() < lines.len() {
+        format!(
+            "[... {} lines omitted ...]\n{}",
+            lines.len() - kept.len(),
+            kept.join("\n")
+        )
+    } else {
+        kept.join("\n")
+    }
+}
+
+// The first ```diff (or ```patch) block, or any block that looks like a diff
+fn diff_block(text: &str) -> Option<String> {
+    let mut blocks: Vec<(String, Vec<&str>)> = vec![];
+    let mut current: Option<(String, Vec<&str>)> = None;
+    for line in text.lines() {
+        match (current.take(), line.trim_start().strip_prefix("```")) {
+            (None, Some(info)) => current = Some((info.trim().to_string(), vec![])),
+            (Some(block), Some(_)) => blocks.push(block),
+            (Some((info, mut lines)), None) => {
+                lines.push(line);
+                current = Some((info, lines));
+            }
+            (None, None) => {}
+        }
+    }
+    blocks
+        .iter()
+        .find(|(info, _)| info == "diff" || info == "patch")
+        .or_else(|| {
+            blocks
+                .iter()
+                .find(|(_, lines)| lines.iter().any(|line| line.starts_with("+++ ")))
+        })
+        .map(|(_, lines)| format!("{}\n", lines.join("\n")))
+}
+
+fn patched_files(diff: &str) -> Vec<String> {
+    diff.lines()
+        .filter_map(|line| line.strip_prefix("+++ "))
+        .map(|path| path.split('\t').next().unwrap_or(path).trim())
+        .filter(|path| *path != "/dev/null")
+        .map(|path| path.strip_prefix("b/").unwrap_or(path).to_string())
+        .collect()
+}
+
+fn show_diff(diff: &str) {
+    for line in diff.lines() {
+        let style = if line.starts_with("+++") || line.starts_with("---") {
+            Some(Style::Prompt)
+        } else if line.starts_with('+') {
+            Some(Style::Success)
+        } else if line.starts_with('-') {
+            Some(Style::Error)
+        } else if line.starts_with("@@") {
+            Some(Style::Dim)
+        } else {
+            None
+        };
+        match style {
+            Some(style) => println!("{}", paint(style, line)),
+            None => println!("{}", line),
+        }
+    }
+}
+
+// `git apply` inside a work tree, `patch` elsewhere. Either applies all hunks or none: patch
+// gets a dry run first, since on its own it would leave the failed hunks behind in .rej files.
+fn apply(diff: &str) -> Result<(), String> {
+    let in_git = Command::new("git")
+        .args(["rev-parse", "--is-inside-work-tree"])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success());
+    if in_git {
+        pipe("git apply --recount --whitespace=nowarn -", diff)
+    } else {
+        pipe("patch -p1 --forward --batch --silent --dry-run", diff)?;
+        pipe("patch -p1 --forward --batch --silent", diff)
+    }
+}
+
+fn pipe(command: &str, input: &str) -> Result<(), String> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Could not run {}: {}", command, e))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        let _ = stdin.write_all(input.as_bytes());
+    }
+    let output = child.wait_with_output().map_err(|e| e.to_string())?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(format!(
+            "{}{}",
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        )
+        .trim()
+        .to_string())
+    }
+}
+
+// `--fix-build [COMMAND]`: builds, sends the errors with the code around them, applies the
+// patch the model answers with once approved, and builds again, until the build passes or
+// `fix_build_iterations` runs out. Everything happens in the current conversation.
+pub fn fix_build(
+    command: Option<&str>,
+    conversation_state: &mut ConversationState,
+    transcript_path: &Path,
+    settings: &Settings,
+) -> Result<(), String> {
+    let command = command
+        .map(str::to_string)
+        .or_else(|| detect_build(settings))
+        .ok_or(
+            "No build command found. Pass one (--fix-build \"make all\") or set build_command.",
+        )?;
+    if !atty::is(Stream::Stdin) || !atty::is(Stream::Stdout) {
+        return Err(
+            "--fix-build asks before applying patches, so it needs a terminal.".to_string(),
+        );
+    }
+    let mut problem: Option<String> = None;
+    for iteration in 1..=settings.fix_build_iterations {
+        // A patch that didn't apply left the files as they were, no need to build again
+        let intro = match problem.take() {
+            Some(problem) => problem,
+            None => {
+                println!("{}", epaint(Style::Prompt, &format!("Running {}", command)));
+                let (passed, output) = build(&command);
+                if passed {
+                    println!("{}", paint(Style::Success, "The build passes."));
+                    return Ok(());
+                }
+                let budget = settings.attachment_chars / 2;
+                let intro = if iteration == 1 {
+                    format!("The build command `{}` fails", command)
+                } else {
+                    "The build still fails".to_string()
+                };
+                format!(
+                    "{}:\n```\n{}\n```\n\nCode around the errors:\n{}",
+                    intro,
+                    tail(&output, budget),
+                    excerpts(&output, budget)
+                )
+            }
+        };
+        let prompt = format!("{}\n\n{}", intro, PATCH_FORMAT);
+        let messages = conversation_state.messages.len();
+        perform_unfiltered_request(
+            Value::String(prompt),
+            conversation_state,
+            transcript_path,
+            settings,
+        );
+        if conversation_state.messages.len() <= messages + 1 {
+            return Err("The request failed, stopping.".to_string());
+        }
+
+        let reply = message_text(&conversation_state.messages.last().unwrap().content).to_string();
+        let Some(diff) = diff_block(&reply) else {
+            problem = Some("Your reply contained no diff.".to_string());
+            continue;
+        };
+        if let Some((file, protected)) = patched_files(&diff).into_iter().find_map(|file| {
+            is_protected(Path::new(&file), &settings.protected_paths).map(|p| (file, p))
+        }) {
+            problem = Some(format!("The patch was refused: {} is under the protected path {}. Fix the build without changing it.", file, protected));
+            continue;
+        }
+
+        println!();
+        show_diff(&diff);
+        let approved = dialoguer::Confirm::with_theme(&*style::dialog_theme())
+            .with_prompt(epaint(Style::Prompt, "Apply this patch?"))
+            .default(true)
+            .interact()
+            .unwrap_or(false);
+        if !approved {
+            println!("Stopped, nothing was applied.");
+            return Ok(());
+        }
+        if let Err(e) = apply(&diff) {
+            eprintln!(
+                "{}",
+                epaint(Style::Warning, &format!("The patch did not apply: {}", e))
+            );
+            problem = Some(format!(
+                "The patch did not apply:\n```\n{}\n```\nSend a corrected diff against the current files.",
+                e
+            ));
+        }
+    }
+
+    let (passed, _) = build(&command);
+    if passed {
+        println!("{}", paint(Style::Success, "The build passes."));
+        Ok(())
+    } else {
+        Err(format!(
+            "The build still fails after {} attempts.",
+            settings.fix_build_iterations
+        ))
+    }
+}
@@ -0,0 +1,133 @@
+use atty::Stream;
+use base64::Engine;
+use serde_json::Value;
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::Command;
+
+// Inline image display through terminal graphics protocols
+enum Protocol {
+    Kitty,
+    Iterm,
+    Sixel,
+}
+
+fn detect_protocol(preference: &str) -> Option<Protocol> {
+    match preference {
+        "kitty" => return Some(Protocol::Kitty),
+        "iterm" => return Some(Protocol::Iterm),
+        "sixel" => return Some(Protocol::Sixel),
+        "none" => return None,
+        _ => {}
+    }
+
+    let term = env::var("TERM").unwrap_or_default();
+    let term_program = env::var("TERM_PROGRAM").unwrap_or_default();
+
+    if env::var("KITTY_WINDOW_ID").is_ok() || term.contains("kitty") || term_program == "ghostty" {
+        Some(Protocol::Kitty)
+    } else if term_program == "iTerm.app" || term_program == "WezTerm" {
+        Some(Protocol::Iterm)
+    } else if term.contains("sixel") || term == "foot" || term == "mlterm" {
+        Some(Protocol::Sixel)
+    } else {
+        None
+    }
+}
+
+// Draws the image in the terminal if it supports it. Otherwise the image is written to a temp
+// file (when it isn't on disk already) and its path is printed.
+pub fn display_image(bytes: &[u8], saved_path: Option<&Path>, preference: &str) {
+    let shown = atty::is(Stream::Stdout)
+        && match detect_protocol(preference) {
+            Some(Protocol::Kitty) => write_kitty(bytes).is_ok(),
+            Some(Protocol::Iterm) => write_iterm(bytes).is_ok(),
+            Some(Protocol::Sixel) => write_sixel(bytes),
+            None => false,
+        };
+
+    if shown {
+        return;
+    }
+
+    // Images already on disk have had their path printed by the caller
+    if saved_path.is_none() {
+        let path = env::temp_dir().join(format!("ask_image_{}.png", std::process::id()));
+        match fs::write(&path, bytes) {
+            Ok(_) => println!("Image available at {}", path.display()),
+            Err(e) => eprintln!("Could not write {}: {}", path.display(), e),
+        }
+    }
+}
+
+// Shows any base64 data-URL images found in a multi-part message content
+pub fn display_content_images(content: &Value, preference: &str) {
+    let parts = match content.as_array() {
+        Some(parts) => parts,
+        None => return,
+    };
+
+    for part in parts {
+        let url = part
+            .get("image_url")
+            .and_then(|image| image.get("url"))
+            .and_then(|url| url.as_str())
+            .unwrap_or("");
+        if let Some((_, data)) = url.split_once(";base64,") {
+            if let Ok(bytes) = base64::engine::general_purpose::STANDARD.decode(data) {
+                display_image(&bytes, None, preference);
+            }
+        }
+    }
+}
+
+// https://sw.kovidgoyal.net/kitty/graphics-protocol/ (PNG payload sent in 4096 byte chunks)
+fn write_kitty(bytes: &[u8]) -> io::Result<()> {
+    let encoded = base64::engine::general_purpose::STANDARD.encode(bytes);
+    let chunks: Vec<&[u8]> = encoded.as_bytes().chunks(4096).collect();
+    let mut stdout = io::stdout().lock();
+
+    for (index, chunk) in chunks.iter().enumerate() {
+        let more = if index + 1 < chunks.len() { 1 } else { 0 };
+        if index == 0 {
+            write!(stdout, "\x1b_Gf=100,a=T,m={};", more)?;
+        } else {
+            write!(stdout, "\x1b_Gm={};", more)?;
+        }
+        stdout.write_all(chunk)?;
+        write!(stdout, "\x1b\\")?;
+    }
+    writeln!(stdout)?;
+    stdout.flush()
+}
+
+// https://iterm2.com/documentation-images.html
+fn write_iterm(bytes: &[u8]) -> io::Result<()> {
+    let encoded = base64::engine::general_purpose::STANDARD.encode(bytes);
+    let mut stdout = io::stdout().lock();
+    write!(
+        stdout,
+        "\x1b]1337;File=inline=1;size={};preserveAspectRatio=1:{}\x07",
+        bytes.len(),
+        encoded
+    )?;
+    writeln!(stdout)?;
+    stdout.flush()
+}
+
+// Sixel encoding needs an image decoder, so it is delegated to libsixel's img2sixel
+fn write_sixel(bytes: &[u8]) -> bool {
+    let path = env::temp_dir().join(format!("ask_sixel_{}.png", std::process::id()));
+    if fs::write(&path, bytes).is_err() {
+        return false;
+    }
+    let shown = Command::new("img2sixel")
+        .arg(&path)
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(false);
+    let _ = fs::remove_file(&path);
+    shown
+}
@@ -0,0 +1,95 @@
+use crate::conversation::Message;
+use crate::response::ChatResponse;
+use crate::style::{epaint, Style};
+use crate::Settings;
+use serde_json::Value;
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+use std::thread;
+
+// Runs a hook with `input` on stdin. Hooks see the model and host in ASK_MODEL and ASK_HOST.
+fn run(hook: &str, input: &str, model: &str, settings: &Settings) -> Result<Output, String> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(hook)
+        .env("ASK_MODEL", model)
+        .env("ASK_HOST", &settings.host)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .map_err(|e| format!("Could not run hook '{}': {}", hook, e))?;
+
+    // Written from another thread so a hook that answers before reading everything can't deadlock
+    let mut stdin = child.stdin.take().unwrap();
+    let input = input.to_string();
+    let writer = thread::spawn(move || {
+        let _ = stdin.write_all(input.as_bytes());
+    });
+    let output = child
+        .wait_with_output()
+        .map_err(|e| format!("Hook '{}' failed: {}", hook, e))?;
+    let _ = writer.join();
+    Ok(output)
+}
+
+fn rewrite(text: &str, model: &str, settings: &Settings) -> Result<String, String> {
+    let output = run(&settings.pre_request_hook, text, model, settings)?;
+    if !output.status.success() {
+        return Err(format!(
+            "The request was blocked by pre_request_hook ({})",
+            output.status
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .trim_end_matches('\n')
+        .to_string())
+}
+
+// Passes the prompt through pre_request_hook, which prints the text to send instead. The
+// message is rewritten in place, so the transcript (and every later request) only holds the
+// rewritten text. A failing hook cancels the request.
+pub fn before_request(message: &mut Message, model: &str, settings: &Settings) -> Result<(), String> {
+    if settings.pre_request_hook.is_empty() || message.role != "user" {
+        return Ok(());
+    }
+
+    match &mut message.content {
+        Value::String(text) => *text = rewrite(text, model, settings)?,
+        // Vision messages: rewrite the text parts, leave the images alone
+        Value::Array(parts) => {
+            for part in parts {
+                if let Some(text) = part["text"].as_str() {
+                    part["text"] = Value::String(rewrite(text, model, settings)?);
+                }
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+// Hands the reply to post_request_hook, for logging or notifications. Its output is ignored.
+pub fn after_response(response: &ChatResponse, model: &str, settings: &Settings) {
+    if settings.post_request_hook.is_empty() {
+        return;
+    }
+    let reply = response
+        .message
+        .content
+        .as_str()
+        .map(str::to_string)
+        .unwrap_or_else(|| serde_json::to_string(&response.message).unwrap());
+
+    match run(&settings.post_request_hook, &reply, model, settings) {
+        Ok(output) if !output.status.success() => eprintln!(
+            "{}",
+            epaint(
+                Style::Warning,
+                &format!("post_request_hook exited with {}", output.status)
+            )
+        ),
+        Ok(_) => {}
+        Err(e) => eprintln!("{}", epaint(Style::Warning, &e)),
+    }
+}
@@ -0,0 +1,84 @@
+use crate::{graphics, http_client, post_json, Settings};
+use base64::Engine;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+// Generates an image through the OpenAI Images API and writes it to disk
+pub fn generate_image(prompt: &str, output: Option<&String>, preview: bool, settings: &Settings) {
+    let body = serde_json::json!({
+        "model": settings.image_model,
+        "prompt": prompt,
+        "size": settings.image_size,
+        "n": 1,
+        "response_format": "b64_json",
+        "user": whoami::username(),
+    });
+
+    let data = match post_json(&settings.image_endpoint, &body, settings) {
+        Some(data) => data,
+        None => return,
+    };
+
+    let image = match data.get("data").and_then(|d| d.get(0)) {
+        Some(image) => image,
+        None => {
+            eprintln!(
+                "Error processing API return. Full response ahead:\n{}\n",
+                data
+            );
+            return;
+        }
+    };
+
+    let bytes = if let Some(b64) = image.get("b64_json").and_then(|v| v.as_str()) {
+        match base64::engine::general_purpose::STANDARD.decode(b64) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                eprintln!("Could not decode image: {}", e);
+                return;
+            }
+        }
+    } else if let Some(url) = image.get("url").and_then(|v| v.as_str()) {
+        match http_client()
+            .get(url)
+            .send()
+            .and_then(|response| response.bytes())
+        {
+            Ok(bytes) => bytes.to_vec(),
+            Err(e) => {
+                eprintln!("Could not download image: {}", e);
+                return;
+            }
+        }
+    } else {
+        eprintln!("The response contained no image data.");
+        return;
+    };
+
+    let path = output.map(PathBuf::from).unwrap_or_else(|| {
+        let stamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        PathBuf::from(format!("ask_image_{}.png", stamp))
+    });
+
+    if let Err(e) = fs::write(&path, &bytes) {
+        eprintln!("Could not write {}: {}", path.display(), e);
+        return;
+    }
+    println!("Saved image to {}", path.display());
+    graphics::display_image(&bytes, Some(&path), &settings.image_protocol);
+
+    // The revised prompt shows how the model interpreted the request
+    if let Some(revised) = image.get("revised_prompt").and_then(|v| v.as_str()) {
+        println!("{}", revised);
+    }
+
+    if preview {
+        if let Err(e) = Command::new(&settings.image_viewer).arg(&path).status() {
+            eprintln!("Could not open image viewer '{}': {}", settings.image_viewer, e);
+        }
+    }
+}
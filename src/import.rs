@@ -0,0 +1,173 @@
+use crate::conversation::{self, Message};
+use crate::{list_transcripts, new_conversation, Settings};
+use serde_json::Value;
+use std::env;
+use std::fs;
+use std::path::Path;
+
+// Converts ChatGPT and Claude data exports (conversations.json) into native transcripts.
+// Imported conversations land in the active list, ready to be copied into a session with -o.
+pub fn import_export(file: &Path, settings: &Settings) {
+    let data = match fs::read_to_string(file) {
+        Ok(data) => data,
+        Err(e) => {
+            eprintln!("Could not read {}: {}", file.display(), e);
+            return;
+        }
+    };
+    let export: Value = match serde_json::from_str(&data) {
+        Ok(export) => export,
+        Err(e) => {
+            eprintln!("Could not parse {}: {}", file.display(), e);
+            return;
+        }
+    };
+
+    let conversations = match export.as_array() {
+        Some(conversations) => conversations,
+        None => {
+            eprintln!("Unrecognized export format: expected a list of conversations.");
+            return;
+        }
+    };
+
+    let folder = env::temp_dir();
+    let mut index = list_transcripts(&folder, settings).len();
+    let mut imported = 0;
+
+    for conversation in conversations {
+        let parsed = if conversation.get("mapping").is_some() {
+            parse_chatgpt(conversation)
+        } else if conversation.get("chat_messages").is_some() {
+            parse_claude(conversation)
+        } else {
+            None
+        };
+
+        let (title, messages) = match parsed {
+            Some(parsed) if !parsed.1.is_empty() => parsed,
+            _ => continue,
+        };
+
+        let mut state = new_conversation(settings);
+        state.messages.extend(messages);
+        state.title = title;
+
+        // Never clobber an earlier import
+        let mut path = folder.join(format!("{}import-{}", settings.transcript_name, index));
+        while path.exists() {
+            index += 1;
+            path = folder.join(format!("{}import-{}", settings.transcript_name, index));
+        }
+        index += 1;
+
+        conversation::save(&mut state, &path);
+        imported += 1;
+    }
+
+    println!("Imported {} conversation(s).", imported);
+}
+
+fn text_message(role: &str, text: String) -> Message {
+    Message::new(role, Value::String(text))
+}
+
+// ChatGPT stores each conversation as a tree of nodes. The visible thread is the
+// path from current_node back up to the root.
+fn parse_chatgpt(conversation: &Value) -> Option<(Option<String>, Vec<Message>)> {
+    let mapping = conversation.get("mapping")?.as_object()?;
+    let title = conversation
+        .get("title")
+        .and_then(|v| v.as_str())
+        .map(|s| s.to_string());
+
+    let mut node_id = conversation
+        .get("current_node")
+        .and_then(|v| v.as_str())
+        .map(|s| s.to_string());
+    let mut messages = vec![];
+
+    while let Some(id) = node_id {
+        let node = mapping.get(&id)?;
+        if let Some(message) = node.get("message").filter(|m| !m.is_null()) {
+            let role = message
+                .get("author")
+                .and_then(|a| a.get("role"))
+                .and_then(|r| r.as_str())
+                .unwrap_or("");
+            let text = message
+                .get("content")
+                .and_then(|c| c.get("parts"))
+                .and_then(|p| p.as_array())
+                .map(|parts| {
+                    parts
+                        .iter()
+                        .filter_map(|part| part.as_str())
+                        .collect::<Vec<&str>>()
+                        .join("\n")
+                })
+                .unwrap_or_default();
+
+            // System and tool nodes are ChatGPT internals, not part of the dialogue
+            if (role == "user" || role == "assistant") && !text.trim().is_empty() {
+                messages.push(text_message(role, text));
+            }
+        }
+        node_id = node
+            .get("parent")
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string());
+    }
+
+    messages.reverse();
+    Some((title, messages))
+}
+
+fn parse_claude(conversation: &Value) -> Option<(Option<String>, Vec<Message>)> {
+    let title = conversation
+        .get("name")
+        .and_then(|v| v.as_str())
+        .filter(|s| !s.is_empty())
+        .map(|s| s.to_string());
+
+    let messages = conversation
+        .get("chat_messages")?
+        .as_array()?
+        .iter()
+        .filter_map(|message| {
+            let role = match message.get("sender").and_then(|v| v.as_str())? {
+                "human" => "user",
+                "assistant" => "assistant",
+                _ => return None,
+            };
+
+            // Newer exports split text into typed content blocks
+            let text = message
+                .get("content")
+                .and_then(|c| c.as_array())
+                .map(|blocks| {
+                    blocks
+                        .iter()
+                        .filter(|b| b.get("type").and_then(|t| t.as_str()) == Some("text"))
+                        .filter_map(|b| b.get("text").and_then(|t| t.as_str()))
+                        .collect::<Vec<&str>>()
+                        .join("\n")
+                })
+                .filter(|text| !text.is_empty())
+                .or_else(|| {
+                    message
+                        .get("text")
+                        .and_then(|t| t.as_str())
+                        .map(|t| t.to_string())
+                })?;
+
+            if text.trim().is_empty() {
+                None
+            } else {
+                Some(text_message(role, text))
+            }
+        })
+        .collect();
+
+    Some((title, messages))
+}
@@ -0,0 +1,194 @@
+use std::collections::HashMap;
+use std::io::Read;
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// How long a stopped process gets to exit on SIGTERM before the group is killed
+const STOP_GRACE: Duration = Duration::from_secs(2);
+
+// Long-running processes the agent started (dev servers, watchers...). Output is collected
+// in the background and handed out incrementally on each poll.
+struct Job {
+    child: Child,
+    cleanup: Option<Command>, //Stops what the process group doesn't cover (a container)
+    output: Arc<Mutex<String>>,
+    read_offset: usize,
+}
+
+impl Job {
+    // Each job leads its own process group, so the shell and everything it started go together
+    fn terminate(&mut self) {
+        let group = self.child.id() as libc::pid_t;
+        unsafe { libc::killpg(group, libc::SIGTERM) };
+        let deadline = Instant::now() + STOP_GRACE;
+        while matches!(self.child.try_wait(), Ok(None)) && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(50));
+        }
+        // Children that ignored SIGTERM, or outlived the shell
+        unsafe { libc::killpg(group, libc::SIGKILL) };
+        let _ = self.child.wait();
+        if let Some(cleanup) = &mut self.cleanup {
+            let _ = cleanup
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status();
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Jobs {
+    jobs: HashMap<String, Job>,
+}
+
+fn collect<R: Read + Send + 'static>(mut reader: R, output: Arc<Mutex<String>>) {
+    thread::spawn(move || {
+        let mut buffer = [0u8; 4096];
+        while let Ok(read) = reader.read(&mut buffer) {
+            if read == 0 {
+                break;
+            }
+            output
+                .lock()
+                .unwrap()
+                .push_str(&String::from_utf8_lossy(&buffer[..read]));
+        }
+    });
+}
+
+impl Jobs {
+    pub fn start(
+        &mut self,
+        name: &str,
+        mut command: Command,
+        cleanup: Option<Command>,
+    ) -> Result<(), String> {
+        if self.jobs.contains_key(name) {
+            return Err(format!("a process named '{}' is already running", name));
+        }
+
+        let mut child = command
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .process_group(0)
+            .spawn()
+            .map_err(|e| e.to_string())?;
+
+        let output = Arc::new(Mutex::new(String::new()));
+        if let Some(stdout) = child.stdout.take() {
+            collect(stdout, output.clone());
+        }
+        if let Some(stderr) = child.stderr.take() {
+            collect(stderr, output.clone());
+        }
+
+        self.jobs.insert(
+            name.to_string(),
+            Job {
+                child,
+                cleanup,
+                output,
+                read_offset: 0,
+            },
+        );
+        Ok(())
+    }
+
+    // Output produced since the last poll, plus whether the process is still alive
+    pub fn poll(&mut self, name: &str) -> Result<String, String> {
+        let job = self
+            .jobs
+            .get_mut(name)
+            .ok_or_else(|| format!("no background process named '{}'", name))?;
+
+        let status = match job.child.try_wait() {
+            Ok(Some(status)) => format!("exited ({})", status),
+            Ok(None) => "running".to_string(),
+            Err(e) => format!("unknown ({})", e),
+        };
+
+        let output = job.output.lock().unwrap();
+        let new_output = output[job.read_offset..].to_string();
+        drop(output);
+        job.read_offset += new_output.len();
+
+        Ok(format!("Process '{}' is {}. New output:\n{}", name, status, new_output))
+    }
+
+    pub fn stop(&mut self, name: &str) -> Result<String, String> {
+        let mut job = self
+            .jobs
+            .remove(name)
+            .ok_or_else(|| format!("no background process named '{}'", name))?;
+        job.terminate();
+
+        let output = job.output.lock().unwrap();
+        Ok(format!(
+            "Process '{}' stopped. Remaining output:\n{}",
+            name,
+            &output[job.read_offset..]
+        ))
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.jobs.keys().cloned().collect()
+    }
+}
+
+// Nothing the agent started outlives the session
+impl Drop for Jobs {
+    fn drop(&mut self) {
+        for job in self.jobs.values_mut() {
+            job.terminate();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stop_kills_the_whole_process_tree() {
+        let mut jobs = Jobs::default();
+        let mut command = Command::new("sh");
+        command.args(["-c", "sleep 30 & echo $!; wait"]);
+        jobs.start("tree", command, None).unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let grandchild = loop {
+            let output = jobs.poll("tree").unwrap();
+            if let Some(pid) = output
+                .lines()
+                .last()
+                .and_then(|line| line.trim().parse::<u32>().ok())
+            {
+                break pid;
+            }
+            assert!(Instant::now() < deadline, "no pid in {:?}", output);
+            thread::sleep(Duration::from_millis(20));
+        };
+
+        jobs.stop("tree").unwrap();
+        // The sleep was reparented and is reaped by init, give it a moment
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while std::path::Path::new(&format!("/proc/{}", grandchild)).exists()
+            && Instant::now() < deadline
+        {
+            thread::sleep(Duration::from_millis(20));
+        }
+        let state =
+            std::fs::read_to_string(format!("/proc/{}/stat", grandchild)).unwrap_or_default();
+        assert!(
+            state.is_empty() || state.contains(") Z "),
+            "{} survived: {}",
+            grandchild,
+            state
+        );
+    }
+}
@@ -0,0 +1,123 @@
+use crate::Settings;
+use std::process::Command as ProcessCommand;
+
+// Flags that only narrow or format what is read. Anything else (exec, -f files, --context...)
+// is refused, and the context and namespace always come from the settings.
+const VALUE_FLAGS: &[&str] = &[
+    "-l",
+    "--selector",
+    "-c",
+    "--container",
+    "--tail",
+    "--since",
+    "-o",
+    "--output",
+    "--field-selector",
+    "--sort-by",
+];
+const SWITCHES: &[&str] = &["-p", "--previous", "--timestamps", "--show-labels", "-w"];
+const OUTPUTS: &[&str] = &["wide", "yaml", "json", "name"];
+
+const DEFAULT_TAIL: &str = "200";
+
+// Runs a read-only kubectl query for KUBECTL: directives:
+//   get <kind> [name], describe <kind> <name>, logs <pod>, top pods|nodes, events
+// An optional `-n <namespace>` has to be one of kube_namespaces, the first being the default.
+pub fn run(query: &str, settings: &Settings) -> Result<String, String> {
+    if settings.kube_namespaces.is_empty() {
+        return Err("KUBECTL: is not enabled. Set kube_namespaces in ask.json to the namespaces the agent may read.".to_string());
+    }
+
+    let mut words = query.split_whitespace();
+    let verb = words
+        .next()
+        .ok_or("Expected get, describe, logs, top or events")?;
+    let mut namespace = settings.kube_namespaces[0].clone();
+    let mut args: Vec<String> = match verb {
+        "get" | "describe" | "logs" | "top" => vec![verb.to_string()],
+        "events" => vec![
+            "get".to_string(),
+            "events".to_string(),
+            "--sort-by=.lastTimestamp".to_string(),
+        ],
+        _ => {
+            return Err(format!(
+                "'{}' is not allowed, only get, describe, logs, top and events",
+                verb
+            ))
+        }
+    };
+    let mut has_tail = false;
+
+    while let Some(word) = words.next() {
+        let (flag, inline_value) = match word.split_once('=') {
+            Some((flag, value)) if flag.starts_with('-') => (flag, Some(value.to_string())),
+            _ => (word, None),
+        };
+        if flag == "-n" || flag == "--namespace" {
+            let value = inline_value
+                .or_else(|| words.next().map(str::to_string))
+                .ok_or("-n needs a namespace")?;
+            if !settings.kube_namespaces.contains(&value) {
+                return Err(format!(
+                    "Namespace '{}' is off limits. Allowed: {}",
+                    value,
+                    settings.kube_namespaces.join(", ")
+                ));
+            }
+            namespace = value;
+        } else if VALUE_FLAGS.contains(&flag) {
+            let value = inline_value
+                .or_else(|| words.next().map(str::to_string))
+                .ok_or_else(|| format!("{} needs a value", flag))?;
+            if (flag == "-o" || flag == "--output") && !OUTPUTS.contains(&value.as_str()) {
+                return Err(format!(
+                    "Output '{}' is not allowed, use one of {}",
+                    value,
+                    OUTPUTS.join(", ")
+                ));
+            }
+            has_tail |= flag == "--tail";
+            args.push(format!("{}={}", flag, value));
+        } else if SWITCHES.contains(&flag) && inline_value.is_none() {
+            if flag == "-w" {
+                return Err("Watching never ends, run the query again instead".to_string());
+            }
+            args.push(flag.to_string());
+        } else if flag.starts_with('-') {
+            return Err(format!("Flag '{}' is not allowed", flag));
+        } else if flag
+            .to_lowercase()
+            .split([',', '/'])
+            .any(|kind| kind.starts_with("secret"))
+        {
+            return Err("Secrets are off limits".to_string());
+        } else {
+            args.push(word.to_string());
+        }
+    }
+    if verb == "logs" && !has_tail {
+        args.push(format!("--tail={}", DEFAULT_TAIL));
+    }
+
+    let mut process = ProcessCommand::new("kubectl");
+    if !settings.kube_context.is_empty() {
+        process.args(["--context", &settings.kube_context]);
+    }
+    process.args(["--namespace", &namespace]).args(&args);
+
+    let output = process
+        .output()
+        .map_err(|e| format!("Could not run kubectl: {}", e))?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() {
+        return Err(format!("kubectl failed: {}", stderr.trim()));
+    }
+    Ok(format!(
+        "kubectl {} (namespace {}):\n{}",
+        args.join(" "),
+        namespace,
+        stdout
+    ))
+}
@@ -0,0 +1,133 @@
+// Scripts used (almost) by a single language, checked per character
+const SCRIPTS: &[(char, char, &str)] = &[
+    ('\u{0400}', '\u{04FF}', "Russian"),
+    ('\u{0370}', '\u{03FF}', "Greek"),
+    ('\u{0590}', '\u{05FF}', "Hebrew"),
+    ('\u{0600}', '\u{06FF}', "Arabic"),
+    ('\u{0900}', '\u{097F}', "Hindi"),
+    ('\u{0E00}', '\u{0E7F}', "Thai"),
+    ('\u{3040}', '\u{30FF}', "Japanese"),
+    ('\u{AC00}', '\u{D7AF}', "Korean"),
+    ('\u{4E00}', '\u{9FFF}', "Chinese"),
+];
+
+// Frequent short words that rarely appear in the other languages listed
+const STOPWORDS: &[(&str, &[&str])] = &[
+    (
+        "English",
+        &[
+            "the", "and", "is", "are", "what", "how", "with", "this", "that", "of", "to", "why",
+            "can", "you",
+        ],
+    ),
+    (
+        "French",
+        &[
+            "le", "la", "les", "et", "est", "une", "des", "pour", "que", "qui", "dans", "pas",
+            "avec", "comment", "pourquoi", "je", "vous",
+        ],
+    ),
+    (
+        "Spanish",
+        &[
+            "el", "los", "las", "es", "una", "para", "que", "por", "con", "cómo", "qué", "pero",
+            "del", "yo", "está",
+        ],
+    ),
+    (
+        "Portuguese",
+        &[
+            "o", "os", "as", "é", "uma", "para", "que", "com", "não", "como", "eu", "você", "isso",
+            "do", "da",
+        ],
+    ),
+    (
+        "German",
+        &[
+            "der", "die", "das", "und", "ist", "nicht", "ein", "eine", "mit", "wie", "warum",
+            "ich", "sie", "für", "auf",
+        ],
+    ),
+    (
+        "Italian",
+        &[
+            "il", "gli", "è", "una", "per", "che", "non", "con", "come", "perché", "sono", "della",
+            "io", "questo",
+        ],
+    ),
+    (
+        "Dutch",
+        &[
+            "de", "het", "en", "een", "van", "niet", "met", "hoe", "waarom", "ik", "je", "dat",
+            "voor", "zijn",
+        ],
+    ),
+];
+
+const JAPANESE_KANA: usize = 6;
+
+// Best guess at the language of a message, or None when there is too little to go on
+pub fn detect(text: &str) -> Option<&'static str> {
+    let mut script_counts = [0usize; SCRIPTS.len()];
+    let mut letters = 0;
+    for c in text.chars().filter(|c| c.is_alphabetic()) {
+        letters += 1;
+        if let Some(index) = SCRIPTS
+            .iter()
+            .position(|(from, to, _)| (*from..=*to).contains(&c))
+        {
+            script_counts[index] += 1;
+        }
+    }
+
+    let non_latin: usize = script_counts.iter().sum();
+    if non_latin > 0 && non_latin * 3 >= letters {
+        // Kanji are shared with Chinese, kana are not
+        if script_counts[JAPANESE_KANA] > 0 {
+            return Some("Japanese");
+        }
+        let (index, _) = script_counts
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, count)| **count)?;
+        if SCRIPTS[index].2 == "Russian" && text.contains(['і', 'ї', 'є', 'ґ']) {
+            return Some("Ukrainian");
+        }
+        return Some(SCRIPTS[index].2);
+    }
+
+    let words: Vec<String> = text
+        .split(|c: char| !c.is_alphabetic())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+        .collect();
+    let (language, hits) = STOPWORDS
+        .iter()
+        .map(|(language, stopwords)| {
+            let hits = words
+                .iter()
+                .filter(|word| stopwords.contains(&word.as_str()))
+                .count();
+            (*language, hits)
+        })
+        .max_by_key(|(_, hits)| *hits)?;
+    (hits >= 2).then_some(language)
+}
+
+// What to add to the system prompt for `reply_language`: a language name, or "auto" to
+// answer in the language of the message
+pub fn instruction(reply_language: &str, message: &str) -> Option<String> {
+    match reply_language.trim() {
+        "" => None,
+        "auto" => detect(message).map(|language| {
+            format!(
+                "Reply in {}, the language the user writes in, unless asked otherwise.",
+                language
+            )
+        }),
+        language => Some(format!(
+            "Always reply in {}, unless asked otherwise.",
+            language
+        )),
+    }
+}
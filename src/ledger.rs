@@ -0,0 +1,213 @@
+use crate::calc;
+use crate::response;
+use crate::{data_dir, Settings};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Which conversation the requests of this process belong to
+static SESSION: OnceLock<String> = OnceLock::new();
+
+pub fn set_session(session: &str) {
+    let _ = SESSION.set(session.to_string());
+}
+
+// One line of usage.jsonl
+#[derive(Serialize, Deserialize)]
+pub struct Entry {
+    pub timestamp: u64,
+    pub date: String, //UTC, YYYY-MM-DD
+    pub session: String,
+    pub host: String,
+    pub model: String,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cost: Option<f64>, //USD, when the model is in `pricing`
+}
+
+fn ledger_path() -> PathBuf {
+    data_dir().join("usage.jsonl")
+}
+
+fn cost(
+    model: &str,
+    prompt_tokens: u64,
+    completion_tokens: u64,
+    settings: &Settings,
+) -> Option<f64> {
+    settings.pricing.get(model).map(|pricing| {
+        (prompt_tokens as f64 * pricing.input + completion_tokens as f64 * pricing.output)
+            / 1_000_000.0
+    })
+}
+
+// Appends the request to the ledger. Every ask process appends under an exclusive lock, so
+// lines from concurrent processes never interleave.
+pub fn record(body: &Value, data: &Value, settings: &Settings) {
+    let Some(usage) = response::usage(data) else {
+        return;
+    };
+    let model = data["model"]
+        .as_str()
+        .or(body["model"].as_str())
+        .unwrap_or_default();
+    let (prompt_tokens, completion_tokens) = (usage.prompt_tokens, usage.completion_tokens);
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let (year, month, day) = calc::civil_from_days((timestamp / 86400) as i64);
+
+    let entry = Entry {
+        timestamp,
+        date: format!("{:04}-{:02}-{:02}", year, month, day),
+        session: SESSION.get().cloned().unwrap_or_else(|| "-".to_string()),
+        host: settings.host.clone(),
+        // Pricing is keyed by the requested model, providers often answer with a dated variant
+        cost: cost(
+            body["model"].as_str().unwrap_or(model),
+            prompt_tokens,
+            completion_tokens,
+            settings,
+        ),
+        model: model.to_string(),
+        prompt_tokens,
+        completion_tokens,
+    };
+
+    let path = ledger_path();
+    if let Some(parent) = path.parent() {
+        let _ = fs::create_dir_all(parent);
+    }
+    let appended = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut file| {
+            file.lock()?;
+            writeln!(file, "{}", serde_json::to_string(&entry).unwrap())
+        });
+    if let Err(e) = appended {
+        eprintln!("Could not write to {}: {}", path.display(), e);
+    }
+}
+
+pub fn entries() -> Vec<Entry> {
+    let Ok(file) = File::open(ledger_path()) else {
+        return vec![];
+    };
+    if file.lock_shared().is_err() {
+        return vec![];
+    }
+    fs::read_to_string(ledger_path())
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
+
+fn spent(entries: &[Entry], date_prefix: &str) -> f64 {
+    entries
+        .iter()
+        .filter(|entry| entry.date.starts_with(date_prefix))
+        .filter_map(|entry| entry.cost)
+        .sum()
+}
+
+// Refuses to send once daily_budget or monthly_budget (USD, across all ask processes) is spent
+pub fn check_budget(settings: &Settings) -> Result<(), String> {
+    if settings.daily_budget <= 0.0 && settings.monthly_budget <= 0.0 {
+        return Ok(());
+    }
+    let entries = entries();
+    let today = calc::today();
+    let checks = [
+        ("daily", settings.daily_budget, spent(&entries, &today)),
+        (
+            "monthly",
+            settings.monthly_budget,
+            spent(&entries, &today[..7]),
+        ),
+    ];
+    for (name, budget, spent) in checks {
+        if budget > 0.0 && spent >= budget {
+            return Err(format!(
+                "The {} budget of ${:.2} is used up (${:.2} spent). Raise {}_budget in ask.json to continue.",
+                name, budget, spent, name
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+pub struct Totals {
+    pub requests: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub cost: f64,
+}
+
+impl Totals {
+    pub fn add(&mut self, entry: &Entry) {
+        self.requests += 1;
+        self.prompt_tokens += entry.prompt_tokens;
+        self.completion_tokens += entry.completion_tokens;
+        self.cost += entry.cost.unwrap_or(0.0);
+    }
+
+    fn line(&self, label: &str) -> String {
+        format!(
+            "{:<24} {:>8} requests {:>12} in {:>12} out   ${:.4}",
+            label, self.requests, self.prompt_tokens, self.completion_tokens, self.cost
+        )
+    }
+}
+
+// `--usage`: totals for today, this month and overall, then this month per model
+pub fn print_usage(settings: &Settings) {
+    let entries = entries();
+    if entries.is_empty() {
+        println!("No requests recorded yet ({}).", ledger_path().display());
+        return;
+    }
+    let today = calc::today();
+    let month = &today[..7];
+
+    let mut periods = [Totals::default(), Totals::default(), Totals::default()];
+    let mut models: BTreeMap<&str, Totals> = BTreeMap::new();
+    for entry in &entries {
+        if entry.date == today {
+            periods[0].add(entry);
+        }
+        if entry.date.starts_with(month) {
+            periods[1].add(entry);
+            models.entry(&entry.model).or_default().add(entry);
+        }
+        periods[2].add(entry);
+    }
+
+    println!("{}", periods[0].line("Today"));
+    println!("{}", periods[1].line("This month"));
+    println!("{}", periods[2].line("All time"));
+    if !models.is_empty() {
+        println!("\nThis month by model:");
+        for (model, totals) in &models {
+            println!("{}", totals.line(&format!("  {}", model)));
+        }
+    }
+    for (name, budget, spent) in [
+        ("Daily", settings.daily_budget, periods[0].cost),
+        ("Monthly", settings.monthly_budget, periods[1].cost),
+    ] {
+        if budget > 0.0 {
+            println!("\n{} budget: ${:.2} of ${:.2} used", name, spent, budget);
+        }
+    }
+}
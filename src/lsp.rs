@@ -0,0 +1,483 @@
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+const DIAGNOSTICS_TIMEOUT: Duration = Duration::from_secs(15);
+const IDLE_PERIOD: Duration = Duration::from_secs(1);
+// Servers answer with this while they are still catching up, so it's worth retrying
+const CONTENT_MODIFIED: i64 = -32801;
+const CONTENT_MODIFIED_MESSAGE: &str = "the server is still processing changes";
+const RETRIES: usize = 3;
+
+// A language server spoken to over stdio, started on first use and kept for the agent session
+struct LanguageServer {
+    child: Child,
+    stdin: ChildStdin,
+    messages: Receiver<Value>,
+    next_id: u64,
+    opened: HashMap<String, Document>,
+    diagnostics: HashMap<String, Value>,
+    progress: Vec<String>, //Work the server reported as still running (indexing...)
+}
+
+// A file as the server was last told about it
+struct Document {
+    path: PathBuf,
+    version: i64,
+    text: String,
+}
+
+#[derive(Default)]
+pub struct LanguageServers {
+    servers: HashMap<String, LanguageServer>,
+    without_project_code: bool, //Where no one approves what runs: read-only mode, --serve
+}
+
+fn uri_for(path: &Path) -> String {
+    format!("file://{}", path.display())
+        .replace('%', "%25")
+        .replace(' ', "%20")
+}
+
+fn path_for(uri: &str) -> String {
+    uri.trim_start_matches("file://")
+        .replace("%20", " ")
+        .replace("%25", "%")
+}
+
+fn language_id(path: &Path) -> &'static str {
+    match path.extension().and_then(|e| e.to_str()).unwrap_or("") {
+        "rs" => "rust",
+        "py" => "python",
+        "go" => "go",
+        "ts" | "tsx" => "typescript",
+        "c" | "h" => "c",
+        "cpp" | "cc" | "hpp" => "cpp",
+        _ => "javascript",
+    }
+}
+
+// Reads Content-Length framed JSON-RPC messages until the server goes away
+fn read_messages<R: Read + Send + 'static>(stdout: R) -> Receiver<Value> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut reader = BufReader::new(stdout);
+        loop {
+            let mut length = 0;
+            loop {
+                let mut header = String::new();
+                if reader.read_line(&mut header).unwrap_or(0) == 0 {
+                    return;
+                }
+                let header = header.trim();
+                if header.is_empty() {
+                    break;
+                }
+                if let Some(value) = header.strip_prefix("Content-Length:") {
+                    length = value.trim().parse().unwrap_or(0);
+                }
+            }
+
+            let mut body = vec![0; length];
+            if reader.read_exact(&mut body).is_err() {
+                return;
+            }
+            if let Ok(message) = serde_json::from_slice(&body) {
+                if sender.send(message).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+    receiver
+}
+
+impl LanguageServer {
+    fn start(command: &str, without_project_code: bool) -> Result<LanguageServer, String> {
+        let mut words = command.split_whitespace();
+        let program = words.next().ok_or("empty language server command")?;
+        // rust-analyzer builds the project's build scripts and proc macros and runs them
+        let is_rust_analyzer = Path::new(program)
+            .file_name()
+            .is_some_and(|name| name == "rust-analyzer");
+        let options = if without_project_code && is_rust_analyzer {
+            json!({
+                "cargo": {"buildScripts": {"enable": false}},
+                "procMacro": {"enable": false},
+            })
+        } else {
+            Value::Null
+        };
+        let mut child = Command::new(program)
+            .args(words)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| format!("could not start '{}': {}", command, e))?;
+
+        let stdin = child.stdin.take().unwrap();
+        let messages = read_messages(child.stdout.take().unwrap());
+        let mut server = LanguageServer {
+            child,
+            stdin,
+            messages,
+            next_id: 1,
+            opened: HashMap::new(),
+            diagnostics: HashMap::new(),
+            progress: vec![],
+        };
+
+        let root = env::current_dir().unwrap_or_default();
+        server.request(
+            "initialize",
+            json!({
+                "processId": std::process::id(),
+                "rootUri": uri_for(&root),
+                "initializationOptions": options,
+                "capabilities": {
+                    "textDocument": {
+                        "definition": {"linkSupport": true},
+                        "references": {},
+                        "publishDiagnostics": {"versionSupport": true},
+                    },
+                    "window": {"workDoneProgress": true},
+                },
+            }),
+        )?;
+        server.notify("initialized", json!({}))?;
+        Ok(server)
+    }
+
+    fn send(&mut self, message: Value) -> Result<(), String> {
+        let body = message.to_string();
+        write!(self.stdin, "Content-Length: {}\r\n\r\n{}", body.len(), body)
+            .and_then(|_| self.stdin.flush())
+            .map_err(|e| format!("language server closed its input: {}", e))
+    }
+
+    fn notify(&mut self, method: &str, params: Value) -> Result<(), String> {
+        self.send(json!({"jsonrpc": "2.0", "method": method, "params": params}))
+    }
+
+    // Handles whatever the server sends unprompted: diagnostics are kept, requests get an
+    // empty answer so the server doesn't stall waiting on us
+    fn handle(&mut self, message: Value) -> Result<(), String> {
+        match message["method"].as_str() {
+            Some("textDocument/publishDiagnostics") => {
+                let uri = message["params"]["uri"].as_str().unwrap_or("").to_string();
+                // Late diagnostics for text that has changed since are of no use
+                let version = message["params"]["version"].as_i64();
+                let current = self.opened.get(&uri).map(|document| document.version);
+                if version.is_some() && current.is_some() && version < current {
+                    return Ok(());
+                }
+                self.diagnostics
+                    .insert(uri, message["params"]["diagnostics"].clone());
+            }
+            Some("$/progress") => {
+                let token = message["params"]["token"].to_string();
+                match message["params"]["value"]["kind"].as_str() {
+                    Some("begin") => self.progress.push(token),
+                    Some("end") => self.progress.retain(|t| *t != token),
+                    _ => {}
+                }
+            }
+            Some(_) if !message["id"].is_null() => {
+                self.send(json!({"jsonrpc": "2.0", "id": message["id"], "result": null}))?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn request(&mut self, method: &str, params: Value) -> Result<Value, String> {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.send(json!({"jsonrpc": "2.0", "id": id, "method": method, "params": params}))?;
+
+        let deadline = Instant::now() + REQUEST_TIMEOUT;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let message = self.messages.recv_timeout(remaining).map_err(|e| match e {
+                RecvTimeoutError::Timeout => {
+                    format!("no answer to {} from the language server", method)
+                }
+                RecvTimeoutError::Disconnected => "the language server exited".to_string(),
+            })?;
+
+            if message["id"].as_u64() == Some(id) && message["method"].is_null() {
+                if let Some(error) = message.get("error") {
+                    return Err(if error["code"] == CONTENT_MODIFIED {
+                        CONTENT_MODIFIED_MESSAGE.to_string()
+                    } else {
+                        format!("{} failed: {}", method, error["message"])
+                    });
+                }
+                return Ok(message["result"].clone());
+            }
+            self.handle(message)?;
+        }
+    }
+
+    // Answers given mid-indexing come back empty, so let the server settle first
+    fn wait_until_idle(&mut self) {
+        let deadline = Instant::now() + REQUEST_TIMEOUT;
+        while Instant::now() < deadline {
+            match self.messages.recv_timeout(IDLE_PERIOD) {
+                Ok(message) => {
+                    if self.handle(message).is_err() {
+                        return;
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) if self.progress.is_empty() => return,
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+        }
+    }
+
+    fn open(&mut self, path: &Path) -> Result<String, String> {
+        let uri = uri_for(path);
+        if !self.opened.contains_key(&uri) {
+            let text = fs::read_to_string(path)
+                .map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+            self.notify(
+                "textDocument/didOpen",
+                json!({"textDocument": {"uri": uri, "languageId": language_id(path), "version": 1, "text": text}}),
+            )?;
+            let document = Document {
+                path: path.to_path_buf(),
+                version: 1,
+                text,
+            };
+            self.opened.insert(uri.clone(), document);
+        }
+        Ok(uri)
+    }
+
+    // The agent edits files between queries, and answers about the old text would send it the
+    // wrong way. Sends the full new text of each opened file that changed on disk and forgets
+    // its diagnostics until the server publishes them for the new version. True if any changed.
+    fn sync_opened(&mut self) -> Result<bool, String> {
+        let mut changes = vec![];
+        for (uri, document) in self.opened.iter_mut() {
+            // A deleted file keeps its last text, the server is only told about edits
+            let Ok(text) = fs::read_to_string(&document.path) else {
+                continue;
+            };
+            if text != document.text {
+                document.version += 1;
+                document.text = text;
+                changes.push((uri.clone(), document.version, document.text.clone()));
+            }
+        }
+        let changed = !changes.is_empty();
+        for (uri, version, text) in changes {
+            self.diagnostics.remove(&uri);
+            self.notify(
+                "textDocument/didChange",
+                json!({"textDocument": {"uri": uri, "version": version}, "contentChanges": [{"text": text}]}),
+            )?;
+        }
+        Ok(changed)
+    }
+
+    fn wait_for_diagnostics(&mut self, uri: &str) -> Value {
+        let deadline = Instant::now() + DIAGNOSTICS_TIMEOUT;
+        while !self.diagnostics.contains_key(uri) {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match self.messages.recv_timeout(remaining) {
+                Ok(message) => {
+                    if self.handle(message).is_err() {
+                        break;
+                    }
+                }
+                Err(_) => break,
+            }
+        }
+        self.diagnostics.get(uri).cloned().unwrap_or(json!([]))
+    }
+}
+
+impl Drop for LanguageServer {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+// "path:line:column" (1-based) plus the source line it points at
+fn describe_location(location: &Value) -> String {
+    let uri = location["uri"]
+        .as_str()
+        .or(location["targetUri"].as_str())
+        .unwrap_or("");
+    let range = if location["range"].is_null() {
+        &location["targetSelectionRange"]
+    } else {
+        &location["range"]
+    };
+    let line = range["start"]["line"].as_u64().unwrap_or(0) as usize;
+    let column = range["start"]["character"].as_u64().unwrap_or(0) + 1;
+
+    let path = path_for(uri);
+    let source = fs::read_to_string(&path)
+        .ok()
+        .and_then(|text| text.lines().nth(line).map(|l| l.trim().to_string()))
+        .unwrap_or_default();
+    format!("{}:{}:{}  {}", path, line + 1, column, source)
+}
+
+// Parses "<file>:<line>:<column>", 1-based like compiler output
+fn parse_position(target: &str) -> Result<(PathBuf, u64, u64), String> {
+    let mut parts = target.rsplitn(3, ':');
+    let column = parts.next().and_then(|c| c.trim().parse::<u64>().ok());
+    let line = parts.next().and_then(|l| l.trim().parse::<u64>().ok());
+    match (parts.next(), line, column) {
+        (Some(file), Some(line), Some(column)) if line > 0 && column > 0 => {
+            Ok((PathBuf::from(file.trim()), line - 1, column - 1))
+        }
+        _ => Err(format!("expected <file>:<line>:<column>, got '{}'", target)),
+    }
+}
+
+impl LanguageServers {
+    // Servers started here don't run code from the project
+    pub fn without_project_code() -> LanguageServers {
+        LanguageServers {
+            without_project_code: true,
+            ..Default::default()
+        }
+    }
+
+    fn server_for(
+        &mut self,
+        path: &Path,
+        commands: &HashMap<String, String>,
+    ) -> Result<&mut LanguageServer, String> {
+        let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+        let command = commands
+            .get(extension)
+            .ok_or_else(|| format!("no language server configured for .{} files", extension))?
+            .clone();
+
+        if !self.servers.contains_key(&command) {
+            let server = LanguageServer::start(&command, self.without_project_code)?;
+            self.servers.insert(command.clone(), server);
+        }
+        Ok(self.servers.get_mut(&command).unwrap())
+    }
+
+    // `definition <file>:<line>:<col>`, `references <file>:<line>:<col>` or `diagnostics <file>`
+    pub fn run(
+        &mut self,
+        query: &str,
+        commands: &HashMap<String, String>,
+    ) -> Result<String, String> {
+        let (action, target) = query
+            .split_once(char::is_whitespace)
+            .ok_or("usage: definition|references <file>:<line>:<column>, or diagnostics <file>")?;
+        let target = target.trim();
+
+        match action {
+            "definition" | "references" => {
+                let (file, line, column) = parse_position(target)?;
+                let absolute =
+                    fs::canonicalize(&file).map_err(|e| format!("{}: {}", file.display(), e))?;
+                let server = self.server_for(&absolute, commands)?;
+                server.sync_opened()?;
+                let uri = server.open(&absolute)?;
+                server.wait_until_idle();
+
+                let mut params = json!({
+                    "textDocument": {"uri": uri},
+                    "position": {"line": line, "character": column},
+                });
+                let method = if action == "definition" {
+                    "textDocument/definition"
+                } else {
+                    params["context"] = json!({"includeDeclaration": false});
+                    "textDocument/references"
+                };
+
+                let mut answer = server.request(method, params.clone());
+                for _ in 0..RETRIES {
+                    match &answer {
+                        Err(e) if e == CONTENT_MODIFIED_MESSAGE => {
+                            server.wait_until_idle();
+                            answer = server.request(method, params.clone());
+                        }
+                        _ => break,
+                    }
+                }
+
+                let locations = match answer? {
+                    Value::Array(locations) => locations,
+                    Value::Null => vec![],
+                    location => vec![location],
+                };
+                if locations.is_empty() {
+                    return Ok(format!("No {} found for {}", action, target));
+                }
+                let lines: Vec<String> = locations.iter().map(describe_location).collect();
+                Ok(format!("{} of {}:\n{}", action, target, lines.join("\n")))
+            }
+            "diagnostics" => {
+                let absolute =
+                    fs::canonicalize(target).map_err(|e| format!("{}: {}", target, e))?;
+                let server = self.server_for(&absolute, commands)?;
+                let changed = server.sync_opened()?;
+                let uri = server.open(&absolute)?;
+                // An edit elsewhere can change this file's diagnostics too, which the server
+                // publishes once it has caught up
+                if changed {
+                    server.wait_until_idle();
+                }
+
+                let diagnostics = server.wait_for_diagnostics(&uri);
+                let lines: Vec<String> = diagnostics
+                    .as_array()
+                    .map(|list| {
+                        list.iter()
+                            .map(|diagnostic| {
+                                let severity = match diagnostic["severity"].as_u64() {
+                                    Some(1) => "error",
+                                    Some(2) => "warning",
+                                    Some(3) => "info",
+                                    _ => "hint",
+                                };
+                                format!(
+                                    "{}:{}:{} {}: {}",
+                                    target,
+                                    diagnostic["range"]["start"]["line"].as_u64().unwrap_or(0) + 1,
+                                    diagnostic["range"]["start"]["character"]
+                                        .as_u64()
+                                        .unwrap_or(0)
+                                        + 1,
+                                    severity,
+                                    diagnostic["message"].as_str().unwrap_or("")
+                                )
+                            })
+                            .collect()
+                    })
+                    .unwrap_or_default();
+
+                if lines.is_empty() {
+                    Ok(format!("No diagnostics for {}", target))
+                } else {
+                    Ok(format!("Diagnostics for {}:\n{}", target, lines.join("\n")))
+                }
+            }
+            _ => Err(format!("unknown LSP action '{}'", action)),
+        }
+    }
+}
@@ -1,32 +1,99 @@
 use atty::Stream;
 use clap::{Arg, ArgAction, Command};
-use dialoguer::{theme::ColorfulTheme, Select};
+use dialoguer::{FuzzySelect, Select};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::collections::HashMap;
 use std::env;
 use std::fs;
-use std::io::{self, Read};
+use std::io::{self, Read, Write};
 use std::os::unix::process;
-use std::path::PathBuf;
-use std::process::Command as ProcessCommand;
-
-#[derive(Serialize, Deserialize, Debug, Clone)] // Added Clone here
-struct Message {
-    role: String,
-    content: Value,
+use std::path::{Path, PathBuf};
+use std::process::{Command as ProcessCommand, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+mod agent;
+mod approvals;
+mod artifacts;
+mod attach;
+mod audit;
+mod bot;
+mod calc;
+mod capabilities;
+mod chunked;
+mod commit;
+mod compare;
+mod conversation;
+mod count;
+mod dashboard;
+mod diagrams;
+mod explain;
+mod filters;
+mod fixbuild;
+mod graphics;
+mod hooks;
+mod imagine;
+mod import;
+mod jobs;
+mod kube;
+mod language;
+mod ledger;
+mod lsp;
+mod memory;
+mod mirror;
+mod mock;
+mod outline;
+mod ping;
+mod preview;
+mod prompts;
+mod reload;
+mod response;
+mod run;
+mod script;
+mod serve;
+mod share;
+mod slash;
+mod style;
+mod summarize;
+mod sysinfo;
+mod tables;
+mod testrun;
+mod traffic;
+mod wtf;
+use capabilities::ModelCapabilities;
+use conversation::{ConversationMetadata, ConversationState, Message};
+use response::ChatResponse;
+use style::{epaint, Style};
+
+// Index of the API key currently in use. Advanced whenever a key gets rate limited.
+static API_KEY_INDEX: AtomicUsize = AtomicUsize::new(0);
+// Resolved keys per credential configuration, since --compare may talk to several providers
+static API_KEYS: OnceLock<Mutex<HashMap<String, &'static [String]>>> = OnceLock::new();
+// One client for the whole process, so agent loops and multi-turn runs reuse its pooled
+// connections (one pool per host) instead of a new TLS handshake for every request
+static HTTP_CLIENT: OnceLock<reqwest::blocking::Client> = OnceLock::new();
+
+fn http_client() -> &'static reqwest::blocking::Client {
+    HTTP_CLIENT.get_or_init(reqwest::blocking::Client::new)
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-struct ConversationState {
-    model: String,
-    messages: Vec<Message>,
-}
+// What --diff asks when no question is given
+const DIFF_QUESTION: &str = "Explain what this change does, then review it: point out bugs, risky or unintended changes and anything missing.";
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
 struct Settings {
     api_key_variable: String,
+    api_key_variables: Vec<String>, //Extra key variables, rotated through on rate limit/quota errors
+    api_keys: Vec<String>,
+    api_key_command: String, //e.g. "pass show openai"
+    api_key_keyring: String, //Account name stored under the "ask" service in the OS keyring
     model: String,
-    host: String,
+    host: String, //"mock" serves replies from mock_cassette without network or keys
+    mock_cassette: String, //JSON list of canned replies for the mock host
     endpoint: String,
     max_tokens: u32,
     temperature: f64,
@@ -36,32 +103,206 @@ struct Settings {
     clipboard_command_xorg: String,
     clipboard_command_wayland: String,
     clipboard_command_unsupported: String,
-    startup_message: String
+    clipboard_text_command_xorg: String,
+    clipboard_text_command_wayland: String,
+    display_server: String, //"xorg", "wayland" or "none" picks the clipboard commands, empty detects it
+    startup_message: String, //Supports {date}, {cwd}, {os} and {shell}
+    reply_language: String, //e.g. "French", or "auto" to answer in the language of the question
+    startup_message_is_command: bool, //Run startup_message with sh and use its output instead
+    stdin_role: String, //"context" (piped input is context for the arguments) or "question"
+    chunk_chars: usize, //Piped input above this size is split up (map-reduce)
+    attachment_chars: usize, //Budget for all --file attachments together; bigger files are excerpted
+    auto_chunk: bool, //Split oversized input without --chunked
+    output_filters: Vec<String>, //Applied in order to every reply, see filters.rs
+    use_pager: bool,
+    render_tables: bool, //Aligns markdown tables and JSON arrays of objects on the terminal
+    prompt_history: usize, //Prompts kept for --reuse; 0 keeps none
+    auto_continue: bool, //Continue replies cut off at max_tokens without asking
+    notify_after_seconds: u64, //0 disables completion notifications
+    notify_command: String,
+    pre_request_hook: String, //Gets the prompt on stdin, prints the prompt to send. Failing cancels.
+    post_request_hook: String, //Gets the reply on stdin
+    show_spinner: bool,
+    show_stats: bool,
+    build_command: String, //For --fix-build; guessed from the project files when empty
+    fix_build_iterations: usize, //Patches --fix-build tries before giving up
+    context_gauge: bool, //Before each request: messages, estimated tokens, share of the context window, cost
+    theme: String, //"default", "light", "mono" or "none"
+    archive_dir: String, //Defaults to $XDG_DATA_HOME/ask/archive
+    session_scope: String, //"shell", "directory" or "global"
+    commit_template: String,
+    summarize_template: String,
+    memory: bool, //Remember stable facts about the user across conversations
+    memory_file: String, //Defaults to $XDG_DATA_HOME/ask/memory.md
+    memory_update_turns: usize, //Fold the conversation into the memory every N questions, 0 = only on --memory update
+    memory_template: String,
+    templates: HashMap<String, String>, //Named prompts for --run, with {placeholders}
+    personas: HashMap<String, Persona>, //Conversation scaffolds for --persona
+    protected_paths: Vec<String>, //Agent commands touching these are always refused
+    persist_approvals: bool, //Save "always allow" decisions per project, under $XDG_DATA_HOME/ask/approvals
+    audit_log: bool, //Record every agent command and its outcome in agent_audit.jsonl
+    sandbox: String, //"none", "docker", "podman" or "bwrap"
+    sandbox_image: String,
+    sandbox_network: bool,
+    image_model: String,
+    image_endpoint: String,
+    image_size: String,
+    image_viewer: String,
+    image_protocol: String, //"auto", "kitty", "iterm", "sixel" or "none"
+    diagram_commands: HashMap<String, String>, //Code block language => command with {input}/{output}
+    lsp_servers: HashMap<String, String>, //File extension => language server command (stdio)
+    pricing: HashMap<String, ModelPricing>, //USD per million tokens, keyed by model
+    kube_context: String, //kubectl context for KUBECTL:, empty for the current one
+    kube_namespaces: Vec<String>, //Namespaces KUBECTL: may read, the first is the default. Empty disables it.
+    daily_budget: f64, //USD across all ask processes, 0 = unlimited. Needs pricing.
+    monthly_budget: f64,
+    providers: HashMap<String, ProviderSettings>, //Named targets for --compare
+    judge_criteria: String, //Default criteria for --judge
+    model_capabilities: HashMap<String, ModelCapabilities>, //Model name or `prefix*` => capabilities
+    pager: String, //Falls back to $PAGER, then "less -R"
+    bot_webhook_url: String, //Where --bot posts replies; empty answers in the webhook response
+    bot_signing_secret: String, //Slack signing secret; when set, --bot refuses unsigned deliveries
+    serve_tools: bool, //Let --serve answer approval-free agent directives (CALC:, LSP:...) itself
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct ModelPricing {
+    input: f64,
+    output: f64,
+}
+
+// An OpenAI-compatible endpoint. Unset fields fall back to the top-level settings.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct ProviderSettings {
+    model: String,
+    host: Option<String>,
+    endpoint: Option<String>,
+    api_key_variable: Option<String>,
+}
+
+// A conversation to start from: its own system prompt and model, plus seeded (few-shot) turns
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+struct Persona {
+    system: String, //Replaces startup_message when set
+    model: String,
+    messages: Vec<Message>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            model: "o1-mini".to_string(),
+            host: "api.openai.com".to_string(),
+            mock_cassette: "".to_string(),
+            endpoint: "/v1/chat/completions".to_string(),
+            max_tokens: 2048,
+            temperature: 0.6,
+            vision_detail: "high".to_string(),
+            transcript_name: "gpt_transcript-".to_string(),
+            editor: "more".to_string(), //Generally available.
+            clipboard_command_xorg: "xclip -selection clipboard -t image/png -o".to_string(),
+            clipboard_command_wayland: "wl-paste".to_string(),
+            clipboard_command_unsupported: "UNSUPPORTED".to_string(),
+            clipboard_text_command_xorg: "xclip -selection clipboard -o".to_string(),
+            clipboard_text_command_wayland: "wl-paste --no-newline".to_string(),
+            display_server: "".to_string(),
+            startup_message_is_command: false,
+            reply_language: "".to_string(),
+            stdin_role: "context".to_string(),
+            chunk_chars: 200_000,
+            attachment_chars: 100_000,
+            auto_chunk: false,
+            api_key_variable: "OPENAI_API_KEY".to_string(),
+            api_key_variables: vec![],
+            api_keys: vec![],
+            api_key_command: "".to_string(),
+            api_key_keyring: "".to_string(),
+            output_filters: vec![],
+            use_pager: false,
+            render_tables: true,
+            prompt_history: 1000,
+            auto_continue: false,
+            notify_after_seconds: 0,
+            notify_command: "notify-send".to_string(),
+            pre_request_hook: "".to_string(),
+            post_request_hook: "".to_string(),
+            show_spinner: true,
+            show_stats: false,
+            build_command: "".to_string(),
+            fix_build_iterations: 5,
+            context_gauge: false,
+            theme: "default".to_string(),
+            archive_dir: "".to_string(),
+            session_scope: "shell".to_string(),
+            image_model: "dall-e-3".to_string(),
+            image_endpoint: "/v1/images/generations".to_string(),
+            image_size: "1024x1024".to_string(),
+            image_viewer: "xdg-open".to_string(),
+            image_protocol: "auto".to_string(),
+            diagram_commands: HashMap::from([
+                ("mermaid".to_string(), "mmdc -i {input} -o {output}".to_string()),
+                ("dot".to_string(), "dot -Tpng {input} -o {output}".to_string()),
+                ("graphviz".to_string(), "dot -Tpng {input} -o {output}".to_string()),
+            ]),
+            lsp_servers: HashMap::from([
+                ("rs".to_string(), "rust-analyzer".to_string()),
+                ("py".to_string(), "pyright-langserver --stdio".to_string()),
+                ("go".to_string(), "gopls".to_string()),
+                ("js".to_string(), "typescript-language-server --stdio".to_string()),
+                ("ts".to_string(), "typescript-language-server --stdio".to_string()),
+                ("c".to_string(), "clangd".to_string()),
+                ("cpp".to_string(), "clangd".to_string()),
+            ]),
+            persist_approvals: false,
+            audit_log: true,
+            sandbox: "none".to_string(),
+            sandbox_image: "debian:stable-slim".to_string(),
+            sandbox_network: false,
+            protected_paths: vec![
+                "~/.ssh".to_string(),
+                "~/.gnupg".to_string(),
+                "/etc".to_string(),
+                "~/.config/ask.json".to_string(),
+            ],
+            templates: HashMap::new(),
+            personas: HashMap::new(),
+            memory: false,
+            memory_file: "".to_string(),
+            memory_update_turns: 10,
+            memory_template: "Maintain a short list of stable facts about the user: preferences, environment (OS, editor, shell, languages, tools), ongoing projects and how they like answers. Merge what the conversation below reveals into the currently remembered list. Drop anything temporary, one-off or sensitive (passwords, keys, personal data they didn't volunteer as a preference). Reply with the updated list only, one fact per line starting with \"- \".".to_string(),
+            summarize_template: "Summarize the conversation below concisely for someone picking it up later. List the decisions made, the open questions and any code or commands produced (keep the important code verbatim). Reply with the summary only.".to_string(),
+            commit_template: "Write a commit message for the staged diff below, following the Conventional Commits format: a `type(scope): summary` line of at most 72 characters, a blank line, then a short body explaining what changed and why. Reply with the commit message only, without markdown or code fences.".to_string(),
+            pricing: HashMap::new(),
+            kube_context: "".to_string(),
+            kube_namespaces: vec![],
+            daily_budget: 0.0,
+            monthly_budget: 0.0,
+            providers: HashMap::new(),
+            judge_criteria: "correctness, completeness and concision".to_string(),
+            model_capabilities: HashMap::new(),
+            pager: "".to_string(),
+            serve_tools: false,
+            bot_webhook_url: "".to_string(),
+            bot_signing_secret: "".to_string(),
+            startup_message: "You are ChatConcise, a very advanced LLM designed for experienced users. As ChatConcise you oblige to adhere to the following directives UNLESS overridden by the user:\nBe concise, proactive, helpful and efficient. Do not say anything more than what needed, but also, DON'T BE LAZY. Provide ONLY code when an implementation is needed. DO NOT USE MARKDOWN.".to_string(),
+        }
+    }
+}
+
+fn settings_path() -> String {
+    env::var("HOME")
+        .map(|home| format!("{}/.config/ask.json", home))
+        .unwrap_or_else(|_| ".config/ask.json".to_string())
 }
 
 fn get_settings() -> Settings {
     //Define default constants
-    let default_settings = Settings {
-        model: "o1-mini".to_string(),
-        host: "api.openai.com".to_string(),
-        endpoint: "/v1/chat/completions".to_string(),
-        max_tokens: 2048,
-        temperature: 0.6,
-        vision_detail: "high".to_string(),
-        transcript_name: "gpt_transcript-".to_string(),
-        editor: "more".to_string(), //Generally available.
-        clipboard_command_xorg: "xclip -selection clipboard -t image/png -o".to_string(),
-        clipboard_command_wayland: "wl-paste".to_string(),
-        clipboard_command_unsupported: "UNSUPPORTED".to_string(),
-        api_key_variable: "OPENAI_API_KEY".to_string(),
-        startup_message: "You are ChatConcise, a very advanced LLM designed for experienced users. As ChatConcise you oblige to adhere to the following directives UNLESS overridden by the user:\nBe concise, proactive, helpful and efficient. Do not say anything more than what needed, but also, DON'T BE LAZY. Provide ONLY code when an implementation is needed. DO NOT USE MARKDOWN.".to_string(),
-    };
+    let default_settings = Settings::default();
 
     //Try reading constants from file
-    let settings_path = env::var("HOME")
-        .map(|home| format!("{}/.config/ask.json", home))
-        .unwrap_or_else(|_| ".config/ask.json".to_string());
-    
+    let settings_path = settings_path();
+
     match fs::read_to_string(&settings_path)
         .map_err(|e| format!("Could not read file: {}", e))
         .and_then(|contents| {
@@ -93,6 +334,34 @@ fn main() {
                 .help("Push image from clipboard into pipeline")
                 .action(ArgAction::SetTrue),
         )
+        .arg(
+            Arg::new("file")
+                .short('f')
+                .long("file")
+                .value_name("PATH")
+                .help("Attach a file to the prompt (repeatable). Large files are excerpted to fit attachment_chars")
+                .action(ArgAction::Append),
+        )
+        .arg(
+            Arg::new("diff")
+                .long("diff")
+                .value_names(["OLD", "NEW"])
+                .help("Send the unified diff of two files or directories, reviewed unless a question is given")
+                .num_args(2),
+        )
+        .arg(
+            Arg::new("quote")
+                .long("quote")
+                .value_name("ID")
+                .help("Include a previous message verbatim in the prompt, by the id --history shows for it")
+                .action(ArgAction::Append),
+        )
+        .arg(
+            Arg::new("paste")
+                .long("paste")
+                .help("Append the clipboard's text to the prompt")
+                .action(ArgAction::SetTrue),
+        )
         .arg(
             Arg::new("manage")
                 .short('o')
@@ -108,7 +377,71 @@ fn main() {
         .arg(
             Arg::new("last")
                 .short('l')
-                .help("Get last message")
+                .long("last")
+                .value_name("N")
+                .help("Print the last message, or the last N (--last=N)")
+                .num_args(0..=1)
+                // With a separate value, `ask -l hello` would read "hello" as N
+                .require_equals(true)
+                .default_missing_value("1")
+                .value_parser(clap::value_parser!(usize)),
+        )
+        .arg(
+            Arg::new("history")
+                .long("history")
+                .help("Print the current conversation to stdout")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("share_html")
+                .long("share-html")
+                .value_name("FILE")
+                .help("Save the conversation as a self-contained HTML page, with images, highlighted code and collapsible tool calls")
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("artifacts")
+                .long("artifacts")
+                .help("Offer to write each code block from the conversation to a file, named after its info string or the text around it")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("note")
+                .long("note")
+                .value_name("INSTRUCTION")
+                .help("Add a system instruction to this request only, without keeping it in the conversation")
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("reuse")
+                .long("reuse")
+                .help("Pick a previous prompt from the history, edit it and send it again")
+                .conflicts_with("input")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("auto_continue")
+                .long("auto-continue")
+                .help("When a reply is cut off at max_tokens, ask for the rest without asking first")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("markdown")
+                .long("markdown")
+                .help("Format --history as markdown")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("role")
+                .long("role")
+                .help("Only consider messages from this role with --last")
+                .value_parser(["user", "assistant", "system", "tool"])
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("raw")
+                .long("raw")
+                .help("Print message text as-is instead of JSON with --last")
                 .action(ArgAction::SetTrue),
         )
         .arg(
@@ -123,274 +456,1528 @@ fn main() {
                 .help("Interactive agent mode")
                 .action(ArgAction::SetTrue),
         )
+        .arg(
+            Arg::new("fix_build")
+                .long("fix-build")
+                .value_name("COMMAND")
+                .help("Run the build, have the model patch the errors (applied after approval) and repeat until it passes")
+                .conflicts_with_all(["recursive", "explain"])
+                .num_args(0..=1)
+                .default_missing_value(""),
+        )
+        .arg(
+            Arg::new("rename")
+                .long("rename")
+                .value_name("TITLE")
+                .help("Give the current conversation a human-readable title")
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("import")
+                .long("import")
+                .value_name("FILE")
+                .help("Import a ChatGPT or Claude conversations.json export")
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("commit")
+                .long("commit")
+                .help("Generate a commit message for the staged changes")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("wtf")
+                .long("wtf")
+                .help("Explain why the last shell command failed")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("explain")
+                .long("explain")
+                .help("Explain piped command output (stack traces, compiler errors, JSON, logs) as cause, relevant lines and fix")
+                .conflicts_with_all(["wtf", "recursive", "chunked"])
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("shell_hook")
+                .long("shell-hook")
+                .value_name("SHELL")
+                .help("Print the bash/zsh hook that records commands for --wtf")
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("imagine")
+                .long("imagine")
+                .value_name("PROMPT")
+                .help("Generate an image from a prompt")
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("out")
+                .long("out")
+                .value_name("FILE")
+                .help("Where --imagine saves the image")
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("preview")
+                .long("preview")
+                .help("Open the generated image once saved")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("count")
+                .long("count")
+                .value_name("DIR")
+                .help("Show lines of code per language and the largest files of a directory, without asking the model")
+                .num_args(0..=1)
+                .default_missing_value("."),
+        )
+        .arg(
+            Arg::new("stream_to")
+                .long("stream-to")
+                .value_name("PATH")
+                .help("Also write every reply to a named pipe or unix socket, e.g. for an editor plugin")
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("ping")
+                .long("ping")
+                .value_name("PROVIDER")
+                .help("Check that the default provider and those in providers (or only PROVIDER) answer, accept the key, and how fast")
+                .num_args(0..=1)
+                .default_missing_value(""),
+        )
+        .arg(
+            Arg::new("usage")
+                .long("usage")
+                .help("Show requests, tokens and cost for today, this month and overall")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("memory")
+                .long("memory")
+                .value_name("ACTION")
+                .help("Show, edit or clear what ask remembers about you, or update it from this conversation")
+                .value_parser(["show", "edit", "clear", "update"])
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("archived")
+                .long("archived")
+                .help("Browse and restore archived conversations")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("read_only")
+                .long("read-only")
+                .help("Agent mode may only run commands that inspect, never modify")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("sandbox")
+                .long("sandbox")
+                .value_name("ENGINE")
+                .help("Run agent commands inside docker, podman or bwrap")
+                .value_parser(["none", "docker", "podman", "bwrap"])
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("target")
+                .long("target")
+                .value_name("URL")
+                .help("Run agent commands on a remote machine (ssh://user@host[:port])")
+                .requires("recursive")
+                .conflicts_with("sandbox")
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("compare")
+                .long("compare")
+                .value_name("TARGETS")
+                .help("Ask several comma-separated providers or models the same question")
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("sweep")
+                .long("sweep")
+                .value_name("PARAMETER=VALUES")
+                .help("Ask the same question at each comma-separated temperature or max_tokens value, e.g. temperature=0.0,0.5,1.0")
+                .conflicts_with("compare")
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("judge")
+                .long("judge")
+                .value_name("TARGET")
+                .help("Have this provider or model score the --compare answers as JSON")
+                .requires("compare")
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("criteria")
+                .long("criteria")
+                .value_name("TEXT")
+                .help("What --judge should score the answers on")
+                .requires("judge")
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("summarize")
+                .long("summarize")
+                .help("Summarize the current conversation")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("compact")
+                .long("compact")
+                .help("Summarize, then replace the history with the summary (the original is archived)")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("run")
+                .long("run")
+                .value_name("TEMPLATE")
+                .help("Fill a template and print the answer as JSON, without a transcript (for cron)")
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("filter")
+                .long("filter")
+                .value_name("INSTRUCTION")
+                .help("Rewrite the text on stdin as instructed and print only the result, for editor range filters like :'<,'>!ask --filter \"...\"")
+                .conflicts_with_all(["run", "recursive", "persona"])
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("script")
+                .long("script")
+                .value_name("FILE")
+                .help("Run the prompts of a YAML or JSON script file in a new conversation, saving replies as variables")
+                .conflicts_with_all(["run", "recursive", "persona"])
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("var")
+                .long("var")
+                .value_name("KEY=VALUE")
+                .help("Template variable for --run (KEY=@FILE inserts the file's contents). Otherwise stored in the conversation and expanded wherever {{KEY}} appears in later prompts and agent commands")
+                .action(ArgAction::Append),
+        )
+        .arg(
+            Arg::new("ephemeral")
+                .long("ephemeral")
+                .help("Don't read or write any transcript")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("stdin_role")
+                .long("stdin-role")
+                .value_name("ROLE")
+                .help("With both piped input and arguments, which one is the context")
+                .value_parser(["context", "question"])
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("chunked")
+                .long("chunked")
+                .help("Process piped input larger than chunk_chars in chunks, then merge the answers")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("serve")
+                .long("serve")
+                .help("Serve an OpenAI-compatible /v1/chat/completions endpoint on localhost")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("dashboard")
+                .long("dashboard")
+                .help("Serve a read-only local page with usage, recent conversations and the agent's command log")
+                .conflicts_with_all(["serve", "bot"])
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("port")
+                .long("port")
+                .help("Port for --serve, --bot and --dashboard")
+                .value_parser(clap::value_parser!(u16))
+                .default_value("8080"),
+        )
+        .arg(
+            Arg::new("bot")
+                .long("bot")
+                .help("Run a chat bot answering webhook messages, one conversation per channel")
+                .conflicts_with("serve")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("record")
+                .long("record")
+                .value_name("FILE")
+                .help("Save the raw provider requests and responses to FILE, with API keys redacted")
+                .conflicts_with("replay")
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("replay")
+                .long("replay")
+                .value_name("FILE")
+                .help("Answer requests from a --record file instead of the provider")
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("persona")
+                .long("persona")
+                .value_name("NAME")
+                .help("Start a new conversation from one of the personas in ask.json")
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("switch_model")
+                .long("switch-model")
+                .value_name("MODEL")
+                .help("Continue the current conversation on another model, adapting its history")
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("set")
+                .long("set")
+                .value_name("KEY=VALUE")
+                .help("Change model, temperature, max_tokens or system (added to the system prompt) for this conversation only")
+                .action(ArgAction::Append),
+        )
+        .arg(
+            Arg::new("lang")
+                .long("lang")
+                .value_name("LANGUAGE")
+                .help("Language to reply in, or \"auto\" for the language of the question (overrides reply_language)")
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("output_filter")
+                .long("output-filter")
+                .value_name("FILTER")
+                .help("Post-process the reply: strip-markdown, code, json, jq:<filter> or sh:<command> (repeatable, replaces output_filters)")
+                .action(ArgAction::Append),
+        )
+        .arg(
+            Arg::new("notify")
+                .short('n')
+                .long("notify")
+                .help("Send a desktop notification when the request finishes")
+                .action(ArgAction::SetTrue),
+        )
         .get_matches();
 
-    let settings = get_settings();
-    let api_key = env::var(&settings.api_key_variable).expect("Missing API key!");
+    // Runs from shell rc files, so it must not depend on settings or keys
+    if let Some(shell) = matches.get_one::<String>("shell_hook") {
+        wtf::print_shell_hook(shell);
+        return;
+    }
 
-    if api_key.is_empty() {
-        eprintln!("Missing API key! Set the OPENAI_API_KEY environment variable and try again.");
+    let mut settings = get_settings();
+    if let Some(language) = matches.get_one::<String>("lang") {
+        settings.reply_language = language.clone();
+    }
+    if let Some(filters) = matches.get_many::<String>("output_filter") {
+        settings.output_filters = filters.cloned().collect();
+    }
+    if matches.get_flag("auto_continue") {
+        settings.auto_continue = true;
+    }
+    style::init(&settings.theme);
+    if let Some(dir) = matches.get_one::<String>("count") {
+        match count::count(Path::new(dir), |_| false) {
+            Ok(report) => print!("{}", report),
+            Err(e) => eprintln!("{}", epaint(Style::Error, &e)),
+        }
+        return;
+    }
+    if let Some(provider) = matches.get_one::<String>("ping") {
+        ping::ping(Some(provider.as_str()).filter(|name| !name.is_empty()), &settings);
+        return;
+    }
+    if let Some(path) = matches.get_one::<String>("stream_to") {
+        mirror::stream_to(path);
+    }
+    if let Some(path) = matches.get_one::<String>("record") {
+        traffic::record_to(path);
+    }
+    if let Some(path) = matches.get_one::<String>("replay") {
+        if let Err(e) = traffic::replay_from(path) {
+            eprintln!("{}", epaint(Style::Error, &e));
+            std::process::exit(1);
+        }
+    }
+    if settings.host != mock::HOST && !traffic::replaying() && get_api_keys(&settings).is_empty() {
+        eprintln!(
+            "{}",
+            epaint(
+                Style::Error,
+                &format!(
+                    "Missing API key! Set the {} environment variable and try again.",
+                    settings.api_key_variable
+                )
+            )
+        );
         std::process::exit(1);
     }
 
-    let temp_dir = env::temp_dir();
-    let transcript_path = temp_dir.join(format!("{}{}", settings.transcript_name, process::parent_id()));
+    if let Some(template) = matches.get_one::<String>("run") {
+        run::run_template(
+            template,
+            matches.get_many::<String>("var").map(|vars| vars.collect()).unwrap_or_default(),
+            &settings,
+        );
+        return;
+    }
+    if let Some(instruction) = matches.get_one::<String>("filter") {
+        run::filter_selection(instruction, &settings);
+        return;
+    }
+
+    if matches.get_flag("usage") {
+        ledger::print_usage(&settings);
+        return;
+    }
+
+    if matches.get_flag("dashboard") {
+        dashboard::dashboard(*matches.get_one::<u16>("port").unwrap(), &settings);
+        return;
+    }
+
+    if matches.get_flag("serve") {
+        ledger::set_session("serve");
+        serve::serve(*matches.get_one::<u16>("port").unwrap(), &settings);
+        return;
+    }
+    if matches.get_flag("bot") {
+        ledger::set_session("bot");
+        bot::run_bot(*matches.get_one::<u16>("port").unwrap(), &settings);
+        return;
+    }
+
+    let transcript_path = get_transcript_path(&settings);
+    ledger::set_session(&transcript_path.file_name().unwrap_or_default().to_string_lossy());
 
-    let mut conversation_state = if transcript_path.exists() {
-        let data = fs::read_to_string(&transcript_path).expect("Unable to read transcript file");
-        serde_json::from_str(&data).expect("Unable to parse transcript JSON")
+    let ephemeral = matches.get_flag("ephemeral");
+    if ephemeral {
+        conversation::set_ephemeral();
+    }
+    let persona = matches.get_one::<String>("persona");
+    let mut conversation_state = if let Some(name) = persona {
+        persona_conversation(name, &settings).map(|state| memory::inject(state, &settings)).unwrap_or_else(|e| {
+            eprintln!("{}", epaint(Style::Error, &e));
+            std::process::exit(1);
+        })
+    } else if ephemeral {
+        memory::inject(new_conversation(&settings), &settings)
+    } else if let (Some(count), None) = (matches.get_one::<usize>("last"), matches.get_one::<String>("input")) {
+        // Only the newest messages are needed, the transcript index can read them on their own
+        let role = matches.get_one::<String>("role").map(String::as_str);
+        print_last_messages(&conversation::load_tail(&transcript_path, *count, role), matches.get_flag("raw"));
+        return;
     } else {
-        let initial_message = Message {
-            role: if settings.model.contains("o1-") {
-                "user".to_string()
-            } else {
-                "system".to_string()
-            },
-            content: settings.startup_message.clone().into(),
-        };
-        ConversationState {
-            model: settings.model.to_string(),
-            messages: vec![initial_message],
-        }
+        conversation::load(&transcript_path).unwrap_or_else(|| memory::inject(new_conversation(&settings), &settings))
     };
+    if let Some(path) = matches.get_one::<String>("script") {
+        let assignments = matches.get_many::<String>("var").map(|vars| vars.collect()).unwrap_or_default();
+        if let Err(e) = script::run_script(path, assignments, &transcript_path, &settings) {
+            eprintln!("{}", epaint(Style::Error, &e));
+            std::process::exit(1);
+        }
+        return;
+    }
+    if let Some(model) = matches.get_one::<String>("switch_model") {
+        let capabilities = capabilities::for_model(model, &settings.model_capabilities);
+        let adapted = capabilities::fit_history(&mut conversation_state.messages, &capabilities);
+        conversation_state.model = model.clone();
+        conversation::save(&mut conversation_state, &transcript_path);
+        println!(
+            "This conversation now uses {}{}.",
+            model,
+            match adapted {
+                0 => String::new(),
+                1 => " (1 message adapted to it)".to_string(),
+                n => format!(" ({} messages adapted to it)", n),
+            }
+        );
+    }
+    if let Some(assignments) = matches.get_many::<String>("set") {
+        for assignment in assignments {
+            match conversation_state.apply_override(assignment) {
+                Ok(change) => println!("{}", change),
+                Err(e) => {
+                    eprintln!("{}", epaint(Style::Error, &e));
+                    std::process::exit(1);
+                }
+            }
+        }
+        conversation::save(&mut conversation_state, &transcript_path);
+    }
+    if let Some(assignments) = matches.get_many::<String>("var") {
+        for assignment in assignments {
+            match conversation_state.set_variable(assignment) {
+                Ok(change) => println!("{}", change),
+                Err(e) => {
+                    eprintln!("{}", epaint(Style::Error, &e));
+                    std::process::exit(1);
+                }
+            }
+        }
+        conversation::save(&mut conversation_state, &transcript_path);
+    }
+    if let (Some(name), None, true) = (persona, matches.get_one::<String>("input"), atty::is(Stream::Stdin)) {
+        conversation::save(&mut conversation_state, &transcript_path);
+        println!("New conversation started as {}.", name);
+        return;
+    }
+    if (matches.contains_id("set") || matches.contains_id("var") || matches.contains_id("switch_model"))
+        && matches.get_one::<String>("input").is_none()
+        && atty::is(Stream::Stdin)
+    {
+        return;
+    }
+
+    conversation_state.note = matches.get_one::<String>("note").cloned();
 
-    // Determine if input is being piped and get full input
-    let input = if !atty::is(Stream::Stdin) {
-        // Read from stdin
+    // Piped input and arguments can be combined: one becomes a fenced context block, the other
+    // the question (`git diff | ask "any bugs?"`)
+    let piped = if !atty::is(Stream::Stdin) {
         let mut buffer = String::new();
         io::stdin()
             .read_to_string(&mut buffer)
             .expect("Failed to read from stdin");
-        if buffer.trim().is_empty() {
-            Value::Null
-        } else {
-            Value::String(buffer)
-        }
-    } else if let Some(values) = matches.get_many::<String>("input") {
-        let input_str = values
-            .map(|s| s.as_str()) // Convert &String to &str
-            .collect::<Vec<&str>>() // Collect into Vec<&str>
-            .join(" "); // Join with spaces
-        if input_str.trim().is_empty() {
-            Value::Null
-        } else {
-            Value::String(input_str)
+        Some(buffer).filter(|buffer| !buffer.trim().is_empty())
+    } else {
+        None
+    };
+    let arguments = if matches.get_flag("reuse") {
+        match prompts::pick() {
+            Some(prompt) => Some(prompt),
+            None => return,
         }
     } else {
-        Value::Null
+        matches
+            .get_many::<String>("input")
+            .map(|values| values.map(|s| s.as_str()).collect::<Vec<&str>>().join(" "))
+            .filter(|input_str| !input_str.trim().is_empty())
+    };
+    if let Some(arguments) = &arguments {
+        prompts::record(arguments, &settings);
+    }
+    let arguments = match arguments {
+        Some(arguments) => {
+            let rest = slash::apply(&arguments, &mut conversation_state, &mut settings).unwrap_or_else(|e| {
+                eprintln!("{}", epaint(Style::Error, &e));
+                std::process::exit(1);
+            });
+            // Nothing but slash commands: keep the changes for the next question
+            if rest.trim().is_empty() && piped.is_none() {
+                conversation::save(&mut conversation_state, &transcript_path);
+                return;
+            }
+            Some(conversation_state.expand(&rest)).filter(|rest| !rest.trim().is_empty())
+        }
+        None => None,
+    };
+    let stdin_role = matches
+        .get_one::<String>("stdin_role")
+        .unwrap_or(&settings.stdin_role);
+
+    // Too big for one request: process it in chunks and merge the answers
+    if let Some(text) = &piped {
+        let oversized = text.chars().count() > settings.chunk_chars;
+        let explain = matches.get_flag("explain");
+        if oversized && !explain && stdin_role != "question" && (settings.auto_chunk || matches.get_flag("chunked")) {
+            chunked::map_reduce(text, arguments, &mut conversation_state, &transcript_path, &settings);
+            return;
+        }
+    }
+
+    let input = match (piped, arguments) {
+        (Some(output), question) if matches.get_flag("explain") => {
+            let (note, prompt) = explain::prepare(&output, question.as_deref(), settings.attachment_chars);
+            conversation_state.note = Some(match conversation_state.note.take() {
+                Some(extra) => format!("{}\n\n{}", note, extra),
+                None => note,
+            });
+            Value::String(prompt)
+        }
+        (None, _) if matches.get_flag("explain") => {
+            eprintln!("{}", epaint(Style::Error, "--explain reads the output to explain from stdin, e.g. make 2>&1 | ask --explain"));
+            std::process::exit(1);
+        }
+        (Some(piped), Some(arguments)) => {
+            let (context, question) = if stdin_role == "question" {
+                (arguments, piped)
+            } else {
+                (piped, arguments)
+            };
+            Value::String(format!("Context:\n```\n{}\n```\n\n{}", context.trim_end(), question))
+        }
+        (Some(text), None) | (None, Some(text)) => Value::String(text),
+        (None, None) => Value::Null,
     };
     let mut input = input;
+    if let Some(paths) = matches.get_many::<String>("file") {
+        let files = attach::attach(&paths.collect::<Vec<&String>>(), settings.attachment_chars).unwrap_or_else(|e| {
+            eprintln!("{}", epaint(Style::Error, &e));
+            std::process::exit(1);
+        });
+        input = Value::String(match input.as_str() {
+            Some(question) => format!("{}\n\n{}", files, question),
+            None => files,
+        });
+    }
+    if let Some(paths) = matches.get_many::<String>("diff") {
+        let paths: Vec<&String> = paths.collect();
+        let block = attach::diff(paths[0], paths[1], settings.attachment_chars).unwrap_or_else(|e| {
+            eprintln!("{}", epaint(Style::Error, &e));
+            std::process::exit(1);
+        });
+        let question = input.as_str().unwrap_or(DIFF_QUESTION);
+        input = Value::String(format!("{}\n\n{}", block, question));
+    }
+    if let Some(ids) = matches.get_many::<String>("quote") {
+        let quotes = quote_messages(&conversation_state, ids).unwrap_or_else(|e| {
+            eprintln!("{}", epaint(Style::Error, &e));
+            std::process::exit(1);
+        });
+        input = Value::String(match input.as_str() {
+            Some(question) => format!("{}\n\n{}", quotes, question),
+            None => quotes,
+        });
+    }
     let input_string = input.to_string();
 
-    if matches.get_flag("recursive") {
-        handle_recursive_mode(&mut conversation_state, &transcript_path, input_string, &settings);
-        return;
-    } else if matches.get_flag("clear_all") {
-        let transcript_folder = env::temp_dir();
-        let entries = fs::read_dir(&transcript_folder).unwrap();
-
-        let files: Vec<PathBuf> = entries
-            .filter_map(|e| e.ok())
-            .map(|e| e.path())
-            .filter(|p| {
-                p.file_name()
-                    .unwrap()
-                    .to_string_lossy()
-                    .starts_with(&settings.transcript_name)
-            })
-            .collect();
+    let start_time = Instant::now();
+    let force_notify = matches.get_flag("notify");
+
+    if let Some(command) = matches.get_one::<String>("fix_build") {
+        let command = Some(command.as_str()).filter(|command| !command.is_empty());
+        if let Err(e) = fixbuild::fix_build(command, &mut conversation_state, &transcript_path, &settings) {
+            eprintln!("{}", epaint(Style::Error, &e));
+            std::process::exit(1);
+        }
+        notify_completion(start_time, force_notify, "Build fixing finished", &settings);
+        return;
+    }
+    if matches.get_flag("recursive") {
+        let target = match matches.get_one::<String>("target").map(|url| agent::Target::parse(url)) {
+            Some(Ok(target)) => Some(target),
+            Some(Err(e)) => {
+                eprintln!("{}", epaint(Style::Error, &e));
+                return;
+            }
+            None => None,
+        };
+        let options = agent::AgentOptions {
+            read_only: matches.get_flag("read_only"),
+            sandbox: match target {
+                // The sandbox would wrap ssh, not the remote command
+                Some(_) => "none".to_string(),
+                None => matches
+                    .get_one::<String>("sandbox")
+                    .cloned()
+                    .unwrap_or_else(|| settings.sandbox.clone()),
+            },
+            target,
+        };
+        agent::handle_recursive_mode(
+            &mut conversation_state,
+            &transcript_path,
+            input_string,
+            &options,
+            &settings,
+        );
+        notify_completion(start_time, force_notify, "Agent run finished", &settings);
+        return;
+    } else if matches.get_flag("clear_all") {
+        let files = list_transcripts(&env::temp_dir(), &settings);
+        delete_all_files(files);
+        return;
+    } else if let Some(title) = matches.get_one::<String>("rename") {
+        rename_convo(&mut conversation_state, &transcript_path, title);
+        return;
+    } else if let Some(file) = matches.get_one::<String>("import") {
+        import::import_export(&PathBuf::from(file), &settings);
+        return;
+    } else if matches.get_flag("commit") {
+        commit::generate_commit_message(&settings);
+        return;
+    } else if let Some(prompt) = matches.get_one::<String>("imagine") {
+        imagine::generate_image(
+            prompt,
+            matches.get_one::<String>("out"),
+            matches.get_flag("preview"),
+            &settings,
+        );
+        return;
+    } else if let Some(targets) = matches.get_one::<String>("compare") {
+        if input.is_null() {
+            eprintln!("{}", epaint(Style::Error, "--compare needs a question."));
+            std::process::exit(1);
+        }
+        compare::compare(
+            targets,
+            message_text(&input),
+            matches.get_one::<String>("judge"),
+            matches.get_one::<String>("criteria"),
+            &settings,
+        );
+        notify_completion(start_time, force_notify, "Comparison ready", &settings);
+        return;
+    } else if let Some(spec) = matches.get_one::<String>("sweep") {
+        if input.is_null() {
+            eprintln!("{}", epaint(Style::Error, "--sweep needs a question."));
+            std::process::exit(1);
+        }
+        if let Err(e) = compare::sweep(spec, message_text(&input), &settings) {
+            eprintln!("{}", epaint(Style::Error, &e));
+            std::process::exit(1);
+        }
+        notify_completion(start_time, force_notify, "Sweep ready", &settings);
+        return;
+    } else if matches.get_flag("summarize") || matches.get_flag("compact") {
+        summarize::summarize(
+            &mut conversation_state,
+            &transcript_path,
+            matches.get_flag("compact"),
+            &settings,
+        );
+        return;
+    } else if let Some(action) = matches.get_one::<String>("memory") {
+        memory::manage(action, &conversation_state, &settings);
+        return;
+    } else if matches.get_flag("archived") {
+        manage_archived_convos(&settings);
+        return;
+    } else if matches.get_flag("manage") && matches.get_one::<String>("input").is_none() {
+        manage_ongoing_convos(&mut conversation_state, &transcript_path, &settings);
+        return;
+    } else if matches.get_flag("clear") && matches.get_one::<String>("input").is_none() {
+        clear_current_convo(&transcript_path);
+        return;
+    } else if let Some(path) = matches.get_one::<String>("share_html") {
+        match share::share_html(&conversation_state, Path::new(path)) {
+            Ok(()) => println!("Saved the conversation to {}", path),
+            Err(e) => {
+                eprintln!("{}", epaint(Style::Error, &e));
+                std::process::exit(1);
+            }
+        }
+        return;
+    } else if matches.get_flag("artifacts") {
+        artifacts::write_artifacts(&conversation_state, &settings);
+        return;
+    } else if matches.get_flag("history") {
+        if matches.get_flag("markdown") {
+            print!("{}", format_history_markdown(&conversation_state));
+        } else {
+            println!("{}", format_history(&conversation_state).trim_start());
+        }
+        return;
+    } else if let (Some(count), None) = (matches.get_one::<usize>("last"), matches.get_one::<String>("input")) {
+        // Personas and --ephemeral conversations aren't on disk
+        let role = matches.get_one::<String>("role");
+        let messages: Vec<Message> = conversation_state
+            .messages
+            .iter()
+            .filter(|message| role.is_none_or(|role| &message.role == role))
+            .cloned()
+            .collect();
+        print_last_messages(&messages[messages.len().saturating_sub(*count)..], matches.get_flag("raw"));
+        return;
+    }
+
+    if matches.get_flag("wtf") {
+        match wtf::build_prompt() {
+            Some(prompt) => input = prompt,
+            None => return,
+        }
+    }
+
+    if matches.get_flag("paste") {
+        add_clipboard_text_to_input(&mut input, &settings);
+    }
+
+    // Handle image mode
+    let clipboard_command = detect_clipboard_command(&settings);
+    if matches.get_flag("image") {
+        add_image_to_pipeline(&mut input, &clipboard_command, &settings);
+    }
+
+    if input.is_null() {
+        // No editor to open when the output goes to a pipe or file
+        if atty::is(Stream::Stdout) {
+            show_history(&conversation_state, settings.editor.clone());
+        } else {
+            println!("{}", format_history(&conversation_state).trim_start());
+        }
+        return;
+    }
+
+    // Default case: simple request
+    perform_request(
+        input,
+        &mut conversation_state,
+        &transcript_path,
+        &clipboard_command,
+        &settings,
+    );
+    memory::maybe_update(&conversation_state, &settings);
+    notify_completion(start_time, force_notify, "Response ready", &settings);
+}
+
+// Fires a desktop notification (or a terminal bell as fallback) for slow requests
+fn notify_completion(start_time: Instant, force: bool, message: &str, settings: &Settings) {
+    let elapsed = start_time.elapsed().as_secs();
+    let slow = settings.notify_after_seconds > 0 && elapsed >= settings.notify_after_seconds;
+    if !force && !slow {
+        return;
+    }
+
+    let body = format!("{} after {}s", message, elapsed);
+    let notified = ProcessCommand::new(&settings.notify_command)
+        .arg("ask")
+        .arg(&body)
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(false);
+
+    if !notified {
+        eprint!("\x07");
+    }
+}
+
+// The transcript a session reads and writes, according to session_scope:
+// "shell" keys on the parent shell's PID, "directory" on the git root (or cwd), "global" is shared.
+fn get_transcript_path(settings: &Settings) -> PathBuf {
+    let key = match settings.session_scope.as_str() {
+        "global" => "global".to_string(),
+        "directory" => {
+            let root = ProcessCommand::new("git")
+                .args(["rev-parse", "--show-toplevel"])
+                .stderr(Stdio::null())
+                .output()
+                .ok()
+                .filter(|output| output.status.success())
+                .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+                .or_else(|| env::current_dir().ok().map(|dir| dir.display().to_string()))
+                .unwrap_or_default();
+            format!("dir-{:016x}", fnv1a(root.as_bytes()))
+        }
+        _ => process::parent_id().to_string(),
+    };
+    env::temp_dir().join(format!("{}{}", settings.transcript_name, key))
+}
+
+// Stable across builds, unlike std's DefaultHasher
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+// Fills in the context placeholders. Shell commands only run when explicitly enabled.
+fn render_startup_message(settings: &Settings) -> String {
+    let mut message = settings.startup_message.clone();
+    if settings.startup_message_is_command {
+        match ProcessCommand::new("sh").arg("-c").arg(&settings.startup_message).output() {
+            Ok(output) if output.status.success() => {
+                message = String::from_utf8_lossy(&output.stdout).trim_end().to_string();
+            }
+            _ => eprintln!(
+                "{}",
+                epaint(Style::Warning, "startup_message command failed, using it as plain text.")
+            ),
+        }
+    }
+
+    let cwd = env::current_dir().map(|dir| dir.display().to_string()).unwrap_or_default();
+    let shell = env::var("SHELL").unwrap_or_default();
+    message
+        .replace("{date}", &calc::today())
+        .replace("{cwd}", &cwd)
+        .replace("{os}", env::consts::OS)
+        .replace("{shell}", &shell)
+}
+
+fn new_conversation(settings: &Settings) -> ConversationState {
+    let capabilities = capabilities::for_model(&settings.model, &settings.model_capabilities);
+    let role = if capabilities.system_role { "system" } else { "user" };
+    let initial_message = Message::new(role, render_startup_message(settings).into());
+    ConversationState {
+        version: conversation::TRANSCRIPT_VERSION,
+        model: settings.model.to_string(),
+        messages: vec![initial_message],
+        title: None,
+        metadata: Some(ConversationMetadata::capture()),
+        overrides: Default::default(),
+        variables: Default::default(),
+        note: None,
+    }
+}
+
+// A fresh conversation seeded from one of `personas`
+fn persona_conversation(name: &str, settings: &Settings) -> Result<ConversationState, String> {
+    let persona = settings.personas.get(name).ok_or_else(|| {
+        let mut names: Vec<&String> = settings.personas.keys().collect();
+        names.sort();
+        format!("No persona named '{}'. Configured personas: {:?}", name, names)
+    })?;
+
+    let mut persona_settings = settings.clone();
+    if !persona.system.is_empty() {
+        persona_settings.startup_message = persona.system.clone();
+    }
+    if !persona.model.is_empty() {
+        persona_settings.model = persona.model.clone();
+    }
+    let mut conversation_state = new_conversation(&persona_settings);
+    conversation_state.messages.extend(persona.messages.iter().cloned());
+    Ok(conversation_state)
+}
+
+enum DisplayServer {
+    Xorg,
+    Wayland,
+    Unsupported,
+}
+
+// The session's environment says which server the clipboard belongs to. Scanning the process
+// list is the last resort: it is slow and, inside containers, sees the host's processes.
+fn detect_display_server(settings: &Settings) -> DisplayServer {
+    match settings.display_server.to_lowercase().as_str() {
+        "xorg" | "x11" => return DisplayServer::Xorg,
+        "wayland" => return DisplayServer::Wayland,
+        "none" => return DisplayServer::Unsupported,
+        _ => {}
+    }
+    match env::var("XDG_SESSION_TYPE").unwrap_or_default().as_str() {
+        "wayland" => return DisplayServer::Wayland,
+        "x11" => return DisplayServer::Xorg,
+        _ => {}
+    }
+    // XWayland sets DISPLAY in Wayland sessions as well, so WAYLAND_DISPLAY goes first
+    let is_set = |name: &str| env::var_os(name).is_some_and(|value| !value.is_empty());
+    if is_set("WAYLAND_DISPLAY") {
+        return DisplayServer::Wayland;
+    }
+    if is_set("DISPLAY") {
+        return DisplayServer::Xorg;
+    }
+
+    let Ok(output) = ProcessCommand::new("ps").arg("-A").output() else {
+        return DisplayServer::Unsupported;
+    };
+    let os_out = String::from_utf8_lossy(&output.stdout).to_lowercase();
+
+    if os_out.contains("xorg") {
+        DisplayServer::Xorg
+    } else if os_out.contains("wayland") {
+        DisplayServer::Wayland
+    } else {
+        DisplayServer::Unsupported
+    }
+}
+
+fn detect_clipboard_command(settings: &Settings) -> String {
+    match detect_display_server(settings) {
+        DisplayServer::Xorg => settings.clipboard_command_xorg.clone(),
+        DisplayServer::Wayland => settings.clipboard_command_wayland.clone(),
+        DisplayServer::Unsupported => settings.clipboard_command_unsupported.clone(),
+    }
+}
+
+// Appends the clipboard's text to the prompt, or uses it as the prompt when there is none
+fn add_clipboard_text_to_input(input: &mut Value, settings: &Settings) {
+    let command = match detect_display_server(settings) {
+        DisplayServer::Xorg => &settings.clipboard_text_command_xorg,
+        DisplayServer::Wayland => &settings.clipboard_text_command_wayland,
+        DisplayServer::Unsupported => {
+            panic!("Unsupported OS/DE combination. Only Xorg and Wayland are supported.")
+        }
+    };
+
+    let output = ProcessCommand::new("sh")
+        .arg("-c")
+        .arg(command)
+        .output()
+        .expect("Failed to execute clipboard command");
+    let pasted = String::from_utf8_lossy(&output.stdout).to_string();
+
+    *input = match input.as_str() {
+        Some(text) => Value::String(format!("{}\n\n{}", text, pasted)),
+        None => Value::String(pasted),
+    };
+}
+
+fn add_image_to_pipeline(input: &mut Value, clipboard_command: &str, settings: &Settings) {
+    if clipboard_command == settings.clipboard_command_unsupported {
+        panic!("Unsupported OS/DE combination. Only Xorg and Wayland are supported.");
+    }
+    if !capabilities::for_model(&settings.model, &settings.model_capabilities).vision {
+        eprintln!(
+            "{}",
+            epaint(Style::Warning, &format!("{} does not accept images. Sending the text only.", settings.model))
+        );
+        return;
+    }
+
+    let output = ProcessCommand::new("sh")
+        .arg("-c")
+        .arg(clipboard_command)
+        .output()
+        .expect("Failed to execute clipboard command");
+
+    use base64::Engine;
+    let image_buffer = base64::engine::general_purpose::STANDARD.encode(&output.stdout);
+
+    let user_text = input.as_str().unwrap_or("");
+    let new_input = serde_json::json!([
+        {
+            "type": "text",
+            "text": user_text,
+        },
+        {
+            "type": "image_url",
+            "image_url": {
+                "url": format!("data:image/png;base64,{}", image_buffer),
+                "detail": settings.vision_detail,
+            }
+        }
+    ]);
+
+    *input = new_input;
+}
+
+fn perform_request(
+    input: Value,
+    conversation_state: &mut ConversationState,
+    transcript_path: &Path,
+    _clipboard_command: &str,
+    settings: &Settings,
+) {
+    request_reply(input, conversation_state, transcript_path, &settings.output_filters, settings);
+}
+
+// For replies ask parses itself (agent directives, patches), which have to arrive exactly as
+// the model wrote them whatever `output_filters` says
+fn perform_unfiltered_request(
+    input: Value,
+    conversation_state: &mut ConversationState,
+    transcript_path: &Path,
+    settings: &Settings,
+) {
+    request_reply(input, conversation_state, transcript_path, &[], settings);
+}
+
+fn request_reply(
+    input: Value,
+    conversation_state: &mut ConversationState,
+    transcript_path: &Path,
+    output_filters: &[String],
+    settings: &Settings,
+) {
+    conversation_state.messages.push(Message::new("user", input));
+
+    if let Some(mut response) = send_request(conversation_state, settings) {
+        continue_truncated(&mut response, conversation_state, settings);
+        process_response(&response, conversation_state, transcript_path, output_filters, settings);
+    }
+}
+
+const MAX_CONTINUATIONS: usize = 4;
+const CONTINUE_PROMPT: &str =
+    "Continue exactly where you stopped, without repeating anything or adding a preamble.";
+
+// While the reply stopped at max_tokens, asks for the rest (without asking the user first with
+// auto_continue) and appends it to the reply, so the transcript gets one whole message
+fn continue_truncated(response: &mut ChatResponse, conversation_state: &mut ConversationState, settings: &Settings) {
+    for _ in 0..MAX_CONTINUATIONS {
+        let Some(text) = response.message.content.as_str() else {
+            return;
+        };
+        if response.finish_reason != "length" || response.message.tool_calls.is_some() {
+            return;
+        }
+        if !settings.auto_continue {
+            let notice = "The reply was cut off at max_tokens.";
+            if !atty::is(Stream::Stdin) || !atty::is(Stream::Stderr) {
+                eprintln!("{}", epaint(Style::Warning, &format!("{} Pass --auto-continue to get the rest.", notice)));
+                return;
+            }
+            let proceed = dialoguer::Confirm::with_theme(&*style::dialog_theme())
+                .with_prompt(epaint(Style::Warning, &format!("{} Continue it?", notice)))
+                .default(true)
+                .interact()
+                .unwrap_or(false);
+            if !proceed {
+                return;
+            }
+        }
+
+        // The partial reply and the request for more are only in flight, never saved
+        let text = text.to_string();
+        conversation_state.messages.push(Message::new("assistant", Value::String(text.clone())));
+        conversation_state.messages.push(Message::new("user", Value::String(CONTINUE_PROMPT.to_string())));
+        let more = send_request(conversation_state, settings);
+        conversation_state.messages.truncate(conversation_state.messages.len() - 2);
+
+        let Some(more) = more else {
+            return;
+        };
+        response.message.content = Value::String(text + more.text());
+        response.finish_reason = more.finish_reason;
+    }
+}
+
+// Sends the conversation to the provider and returns its reply
+fn send_request(conversation_state: &mut ConversationState, settings: &Settings) -> Option<ChatResponse> {
+    if let Some(message) = conversation_state.messages.last_mut() {
+        if let Err(e) = hooks::before_request(message, &conversation_state.model, settings) {
+            eprintln!("{}", epaint(Style::Error, &e));
+            return None;
+        }
+    }
+
+    // Covers conversations whose model changed (--switch-model, --set model, /model)
+    let capabilities = capabilities::for_model(&conversation_state.model, &settings.model_capabilities);
+    capabilities::fit_history(&mut conversation_state.messages, &capabilities);
+
+    let mut body = serde_json::json!({
+        "messages": conversation_state.messages,
+        "model": conversation_state.model,
+        "user": whoami::username(),
+    });
+
+    // Only added to the outgoing request, so changing the setting applies to old conversations too
+    let question = conversation_state.messages.last().map(|m| message_text(&m.content)).unwrap_or_default();
+    if let Some(instruction) = language::instruction(&settings.reply_language, question) {
+        if let Some(Value::String(startup_message)) = body["messages"].get_mut(0).map(|m| &mut m["content"]) {
+            startup_message.push_str(&format!("\n\n{}", instruction));
+        }
+    }
+
+    let overrides = &conversation_state.overrides;
+    for addendum in [&overrides.system_addendum, &conversation_state.note].into_iter().flatten() {
+        if let Some(Value::String(startup_message)) = body["messages"].get_mut(0).map(|m| &mut m["content"]) {
+            startup_message.push_str(&format!("\n\n{}", addendum));
+        }
+    }
+
+    if capabilities.sampling_params {
+        body["max_tokens"] = serde_json::json!(overrides.max_tokens.unwrap_or(settings.max_tokens));
+        body["temperature"] = serde_json::json!(overrides.temperature.unwrap_or(settings.temperature));
+    }
+
+    if settings.context_gauge {
+        print_gauge(&body, &capabilities, settings);
+    }
+
+    let request_start = Instant::now();
+    let data = post_json(&settings.endpoint, &body, settings)?;
+    let response = response::normalize(&data).map_err(|e| eprintln!("{}", epaint(Style::Error, &e))).ok()?;
+    if settings.show_stats {
+        print_stats(&response, request_start.elapsed(), &conversation_state.model, settings);
+    }
+    hooks::after_response(&response, &conversation_state.model, settings);
+    Some(response)
+}
+
+// POSTs to the provider, rotating API keys on rate limits. Returns the parsed response body.
+fn post_json(endpoint: &str, body: &Value, settings: &Settings) -> Option<Value> {
+    if settings.host == mock::HOST {
+        return mock::respond(endpoint, body, settings)
+            .map_err(|e| eprintln!("{}", epaint(Style::Error, &e)))
+            .ok();
+    }
+    if let Some(exchange) = traffic::next_replayed(endpoint) {
+        return exchange
+            .map_err(|e| eprintln!("{}", epaint(Style::Error, &e)))
+            .ok()
+            .and_then(|exchange| parse_response(&exchange.response));
+    }
+    if let Err(e) = ledger::check_budget(settings) {
+        eprintln!("{}", epaint(Style::Error, &e));
+        return None;
+    }
+    let api_keys = get_api_keys(settings);
+    if api_keys.is_empty() {
+        eprintln!(
+            "{}",
+            epaint(Style::Error, &format!("Missing API key for {} ({}).", settings.host, settings.api_key_variable))
+        );
+        return None;
+    }
+    let client = http_client();
+    let mut attempts = 0;
+
+    loop {
+        let key_index = API_KEY_INDEX.load(Ordering::Relaxed) % api_keys.len();
+        let spinner = Spinner::start(settings.show_spinner);
+        let res = client
+            .post(format!("https://{}{}", settings.host, endpoint))
+            .header("Authorization", format!("Bearer {}", api_keys[key_index]))
+            .json(body)
+            .send();
+        spinner.stop();
+
+        match res {
+            Ok(response)
+                if response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS
+                    && attempts + 1 < api_keys.len() =>
+            {
+                // Rate limited or out of quota, move on to the next key
+                eprintln!(
+                    "{}",
+                    epaint(
+                        Style::Warning,
+                        &format!(
+                            "WARNING: API key #{} was rate limited. Rotating to the next key.",
+                            key_index + 1
+                        )
+                    )
+                );
+                API_KEY_INDEX.store(key_index + 1, Ordering::Relaxed);
+                attempts += 1;
+            }
+            Ok(response) => {
+                let status = response.status().as_u16();
+                let text = match response.text() {
+                    Ok(text) => text,
+                    Err(e) => {
+                        eprintln!("{}", epaint(Style::Error, &format!("Invalid API response: {}", e)));
+                        return None;
+                    }
+                };
+                traffic::record(
+                    traffic::Exchange {
+                        host: settings.host.clone(),
+                        endpoint: endpoint.to_string(),
+                        request: body.clone(),
+                        status,
+                        response: text.clone(),
+                    },
+                    api_keys,
+                );
+                let data = parse_response(&text)?;
+                ledger::record(body, &data, settings);
+                return Some(data);
+            }
+            Err(e) => {
+                eprintln!("{}", epaint(Style::Error, &format!("HTTP request error: {}", e)));
+                return None;
+            }
+        }
+    }
+}
+
+fn parse_response(text: &str) -> Option<Value> {
+    serde_json::from_str(text)
+        .map_err(|e| eprintln!("{}", epaint(Style::Error, &format!("Invalid API response: {}", e))))
+        .ok()
+}
+
+// One-off request outside of any transcript. Returns the reply text.
+fn complete(prompt: String, settings: &Settings) -> Option<String> {
+    let mut conversation_state = new_conversation(settings);
+    conversation_state.messages.push(Message::new("user", Value::String(prompt)));
+
+    let response = send_request(&mut conversation_state, settings)?;
+    let reply = response.message.content.as_str().map(|content| content.to_string());
+
+    if reply.is_none() {
+        eprintln!(
+            "The reply has no text. Full message ahead:\n{}\n",
+            serde_json::to_string(&response.message).unwrap()
+        );
+    }
+    reply
+}
+
+// Shows an animated spinner with elapsed time on stderr until stopped
+struct Spinner {
+    running: Arc<AtomicBool>,
+    handle: Option<thread::JoinHandle<()>>,
+}
 
-        delete_all_files(files);
-        return;
-    } else if matches.get_flag("manage") && !matches.get_one::<String>("input").is_some() {
-        manage_ongoing_convos(&mut conversation_state, &transcript_path, &settings);
-        return;
-    } else if matches.get_flag("clear") && !matches.get_one::<String>("input").is_some() {
-        clear_current_convo(&transcript_path);
-        return;
-    } else if matches.get_flag("last") && !matches.get_one::<String>("input").is_some() {
-        if let Some(last_message) = conversation_state.messages.last() {
-            println!("{}", serde_json::to_string(&last_message.content).unwrap());
+impl Spinner {
+    fn start(enabled: bool) -> Spinner {
+        let running = Arc::new(AtomicBool::new(true));
+        if !enabled || !atty::is(Stream::Stderr) {
+            return Spinner { running, handle: None };
         }
-        return;
-    }
 
-    // Handle image mode
-    let clipboard_command = detect_clipboard_command(&settings);
-    if matches.get_flag("image") {
-        add_image_to_pipeline(&mut input, &clipboard_command, &settings);
+        let flag = running.clone();
+        let handle = thread::spawn(move || {
+            let frames = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+            let start_time = Instant::now();
+            let mut frame = 0;
+            while flag.load(Ordering::Relaxed) {
+                let status = format!(
+                    "{} waiting for response… {:.1}s",
+                    frames[frame % frames.len()],
+                    start_time.elapsed().as_secs_f64()
+                );
+                eprint!("\r{}", epaint(Style::Dim, &status));
+                let _ = io::stderr().flush();
+                frame += 1;
+                thread::sleep(Duration::from_millis(100));
+            }
+            // Clear the spinner line before any output gets printed
+            eprint!("\r\x1b[2K");
+            let _ = io::stderr().flush();
+        });
+
+        Spinner { running, handle: Some(handle) }
     }
 
-    if input.is_null() {
-        show_history(&conversation_state, settings.editor.clone());
-        return;
+    fn stop(mut self) {
+        self.running.store(false, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
     }
+}
 
-    // Default case: simple request
-    perform_request(
-        input,
-        &mut conversation_state,
-        &transcript_path,
-        &clipboard_command,
-        &settings,
+// Collects every configured key, in rotation order. Unset or empty sources are skipped.
+// Resolved once per process so credential helpers don't run on every request.
+fn get_api_keys(settings: &Settings) -> &'static [String] {
+    let cache_key = format!(
+        "{}|{:?}|{:?}|{}|{}",
+        settings.api_key_variable,
+        settings.api_key_variables,
+        settings.api_keys,
+        settings.api_key_command,
+        settings.api_key_keyring
     );
+    let mut cache = API_KEYS.get_or_init(Default::default).lock().unwrap();
+    cache
+        .entry(cache_key)
+        .or_insert_with(|| Vec::leak(resolve_api_keys(settings)))
 }
 
-fn detect_clipboard_command(settings: &Settings) -> String {
-    let output = ProcessCommand::new("ps")
-        .arg("-A")
-        .output()
-        .expect("Failed to execute ps command");
-    let os_out = String::from_utf8_lossy(&output.stdout);
+fn resolve_api_keys(settings: &Settings) -> Vec<String> {
+    let mut keys: Vec<String> = std::iter::once(&settings.api_key_variable)
+        .chain(settings.api_key_variables.iter())
+        .filter_map(|variable| env::var(variable).ok())
+        .collect();
 
-    if os_out.to_lowercase().contains("xorg") {
-        settings.clipboard_command_xorg.clone()
-    } else if os_out.to_lowercase().contains("wayland") {
-        settings.clipboard_command_wayland.clone()
-    } else {
-        settings.clipboard_command_unsupported.clone()
+    if !settings.api_key_command.is_empty() {
+        keys.extend(run_key_command(&settings.api_key_command));
     }
-}
-
-fn add_image_to_pipeline(input: &mut Value, clipboard_command: &str, settings: &Settings) {
-    if clipboard_command == settings.clipboard_command_unsupported {
-        panic!("Unsupported OS/DE combination. Only Xorg and Wayland are supported.");
+    if !settings.api_key_keyring.is_empty() {
+        keys.extend(keyring_lookup(&settings.api_key_keyring));
     }
 
-    let output = ProcessCommand::new("sh")
-        .arg("-c")
-        .arg(clipboard_command)
-        .output()
-        .expect("Failed to execute clipboard command");
-
-    use base64::Engine;
-    let image_buffer = base64::engine::general_purpose::STANDARD.encode(&output.stdout);
+    keys.extend(settings.api_keys.iter().cloned());
+    keys.retain(|key| !key.trim().is_empty());
+    keys
+}
 
-    let user_text = input.as_str().unwrap_or("");
-    let new_input = serde_json::json!([
-        {
-            "type": "text",
-            "text": user_text,
-        },
-        {
-            "type": "image_url",
-            "image_url": {
-                "url": format!("data:image/png;base64,{}", image_buffer),
-                "detail": settings.vision_detail,
-            }
+// Runs a credential helper and takes the first line of its output as the key
+fn run_key_command(command: &str) -> Option<String> {
+    match ProcessCommand::new("sh").arg("-c").arg(command).output() {
+        Ok(output) if output.status.success() => String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .next()
+            .map(|line| line.trim().to_string()),
+        Ok(output) => {
+            let message = format!(
+                "WARNING: API key command failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+            eprintln!("{}", epaint(Style::Warning, &message));
+            None
         }
-    ]);
+        Err(e) => {
+            let message = format!("WARNING: Could not run API key command: {}", e);
+            eprintln!("{}", epaint(Style::Warning, &message));
+            None
+        }
+    }
+}
 
-    *input = new_input;
+// Looks the key up in the OS keyring (Secret Service on Linux, Keychain on macOS)
+fn keyring_lookup(account: &str) -> Option<String> {
+    let command = if cfg!(target_os = "macos") {
+        format!("security find-generic-password -s ask -a '{}' -w", account)
+    } else {
+        format!("secret-tool lookup service ask account '{}'", account)
+    };
+    run_key_command(&command)
 }
 
-fn perform_request(
-    input: Value,
+fn process_response(
+    response: &ChatResponse,
     conversation_state: &mut ConversationState,
-    transcript_path: &PathBuf,
-    _clipboard_command: &str,
+    transcript_path: &Path,
+    output_filters: &[String],
     settings: &Settings,
 ) {
-    conversation_state.messages.push(Message {
-        role: "user".to_string(),
-        content: input,
-    });
-
-    let mut body = serde_json::json!({
-        "messages": conversation_state.messages,
-        "model": conversation_state.model,
-        "user": whoami::username(),
-    });
+    let mut assistant_message = response.message.clone();
+    if let (Value::String(text), false) = (&assistant_message.content, output_filters.is_empty()) {
+        match filters::apply(text, output_filters) {
+            Ok(filtered) => assistant_message.content = Value::String(filtered),
+            Err(e) => eprintln!("{}", epaint(Style::Warning, &format!("{}. Showing the unfiltered reply.", e))),
+        }
+    }
 
-    if !conversation_state.model.contains("o1-") {
-        body["max_tokens"] = serde_json::json!(settings.max_tokens);
-        body["temperature"] = serde_json::json!(settings.temperature);
+    // Tables are aligned for the terminal only, the transcript keeps the markdown
+    let content = &assistant_message.content;
+    let text = message_text(content);
+    if settings.render_tables && atty::is(Stream::Stdout) {
+        let width = term_size::dimensions_stdout().map(|(width, _)| width).unwrap_or(80);
+        print_response(&tables::render(text, width), settings);
+    } else {
+        print_response(text, settings);
     }
+    mirror::mirror(text);
+    graphics::display_content_images(content, &settings.image_protocol);
+    diagrams::offer_rendering(text, settings);
+
+    conversation_state.messages.push(assistant_message);
+
+    conversation::save(conversation_state, transcript_path);
+}
 
-    let client = reqwest::blocking::Client::new();
-    let res = client
-        .post(&format!("https://{}{}", settings.host, settings.endpoint))
-        .header("Authorization", format!("Bearer {}", env::var(&settings.api_key_variable).unwrap()))
-        .json(&body)
-        .send();
+// Rough, at about four characters per token, but enough to see a full context coming
+fn estimate_tokens(messages: &[Value]) -> u64 {
+    messages
+        .iter()
+        .map(|message| {
+            let text = match &message["content"] {
+                Value::String(text) => text.len(),
+                Value::Array(parts) => parts.iter().map(|part| part["text"].as_str().unwrap_or("").len()).sum(),
+                _ => 0,
+            };
+            let calls = message.get("tool_calls").map(|calls| calls.to_string().len()).unwrap_or(0);
+            // Each message costs a few tokens of framing
+            4 + (text + calls) as u64 / 4
+        })
+        .sum()
+}
 
-    match res {
-        Ok(response) => {
-            let data: Value = response.json().unwrap();
-            process_response(&data, conversation_state, transcript_path);
+// One line on stderr before a request, e.g. `12 messages · ~3.4k tokens · 3% of 128k · ~$0.0085`
+fn print_gauge(body: &Value, capabilities: &ModelCapabilities, settings: &Settings) {
+    let messages = body["messages"].as_array().map(Vec::as_slice).unwrap_or_default();
+    let tokens = estimate_tokens(messages);
+    let thousands = |count: u64| {
+        if count >= 1000 {
+            format!("{:.1}k", count as f64 / 1000.0)
+        } else {
+            count.to_string()
         }
-        Err(e) => {
-            eprintln!("HTTP request error: {}", e);
+    };
+    let mut gauge = format!("{} messages · ~{} tokens", messages.len(), thousands(tokens));
+
+    let mut style = Style::Dim;
+    if capabilities.context_window > 0 {
+        let share = tokens as f64 * 100.0 / capabilities.context_window as f64;
+        gauge.push_str(&format!(" · {:.0}% of {}k", share, capabilities.context_window / 1000));
+        if share >= 100.0 {
+            style = Style::Error;
+        } else if share >= 80.0 {
+            style = Style::Warning;
         }
     }
+    if let Some(pricing) = body["model"].as_str().and_then(|model| settings.pricing.get(model)) {
+        gauge.push_str(&format!(" · ~${:.4}", tokens as f64 * pricing.input / 1_000_000.0));
+    }
+    eprintln!("{}", epaint(style, &gauge));
 }
 
-fn process_response(
-    data: &Value,
-    conversation_state: &mut ConversationState,
-    transcript_path: &PathBuf,
-) {
-    if let Some(choices) = data.get("choices") {
-        if let Some(choice) = choices.get(0) {
-            if let Some(message) = choice.get("message") {
-                let content = message.get("content").unwrap_or(&Value::Null).clone();
-                let role = message
-                    .get("role")
-                    .and_then(|v| v.as_str())
-                    .unwrap_or("")
-                    .to_string();
+// One-line timing/usage footer. Responses arrive whole, so time to first token equals total time.
+fn print_stats(response: &ChatResponse, elapsed: Duration, model: &str, settings: &Settings) {
+    let usage = response.usage.unwrap_or_default();
+    let (prompt_tokens, completion_tokens) = (usage.prompt_tokens, usage.completion_tokens);
+
+    let seconds = elapsed.as_secs_f64();
+    let mut footer = format!(
+        "{:.2}s · {} in / {} out tokens · {:.1} tok/s",
+        seconds,
+        prompt_tokens,
+        completion_tokens,
+        completion_tokens as f64 / seconds.max(0.001)
+    );
+
+    if let Some(pricing) = settings.pricing.get(model) {
+        let cost = (prompt_tokens as f64 * pricing.input + completion_tokens as f64 * pricing.output)
+            / 1_000_000.0;
+        footer.push_str(&format!(" · ${:.4}", cost));
+    }
+
+    eprintln!("{}", epaint(Style::Dim, &footer));
+}
 
-                println!("{}", content.as_str().unwrap_or(""));
+// Prints the response, handing it over to a pager when it doesn't fit on screen
+fn print_response(text: &str, settings: &Settings) {
+    let fits_on_screen = term_size::dimensions_stdout()
+        .map(|(_, rows)| text.lines().count() < rows)
+        .unwrap_or(true);
 
-                let assistant_message = Message { role, content };
+    if !settings.use_pager || fits_on_screen || !atty::is(Stream::Stdout) {
+        println!("{}", text);
+        return;
+    }
 
-                conversation_state.messages.push(assistant_message);
+    let pager = if !settings.pager.is_empty() {
+        settings.pager.clone()
+    } else {
+        env::var("PAGER").unwrap_or_else(|_| "less -R".to_string())
+    };
 
-                let conversation_json = serde_json::to_string(&conversation_state).unwrap();
-                fs::write(transcript_path, conversation_json)
-                    .expect("Unable to write transcript file");
+    let spawned = ProcessCommand::new("sh")
+        .arg("-c")
+        .arg(&pager)
+        .stdin(Stdio::piped())
+        .spawn();
+
+    match spawned {
+        Ok(mut child) => {
+            if let Some(mut stdin) = child.stdin.take() {
+                let _ = writeln!(stdin, "{}", text);
             }
+            let _ = child.wait();
+        }
+        Err(e) => {
+            let message = format!("WARNING: Could not start pager '{}': {}", pager, e);
+            eprintln!("{}", epaint(Style::Warning, &message));
+            println!("{}", text);
         }
-    } else {
-        eprintln!(
-            "Error processing API return. Full response ahead:\n{}\n",
-            data
-        );
     }
 }
 
-fn clear_current_convo(transcript_path: &PathBuf) {
-    match fs::remove_file(transcript_path) {
+fn clear_current_convo(transcript_path: &Path) {
+    match conversation::remove(transcript_path) {
         Ok(_) => println!("Conversation cleared."),
         Err(e) => println!("Error clearing conversation: {}", e),
     }
 }
 
-fn show_history(conversation_state: &ConversationState, editor_command: String) {
-    let tmp_dir = env::temp_dir();
-    let tmp_path = tmp_dir.join("ask_hist");
-
-    let mut content = String::new();
-
-    for message in &conversation_state.messages {
-        content.push_str("\n\n");
-        content.push_str(&horizontal_line('▃'));
-        content.push_str(&format!("▍{} ▐\n", message.role));
-        content.push_str(&horizontal_line('▀'));
-        content.push_str("\n");
-
-        if let Some(text) = message.content.as_str() {
-            content.push_str(text);
-        } else if let Some(array) = message.content.as_array() {
-            if let Some(first_item) = array.get(0) {
-                if let Some(text) = first_item.get("text").and_then(|v| v.as_str()) {
-                    content.push_str(text);
-                }
+// The last `count` messages, oldest first, optionally only those from one role
+fn print_last_messages(messages: &[Message], raw: bool) {
+    for (index, message) in messages.iter().enumerate() {
+        if raw {
+            if index > 0 {
+                println!();
             }
+            println!("{}", message_text(&message.content));
+        } else {
+            println!("{}", serde_json::to_string(&message.content).unwrap());
         }
     }
+}
+
+fn show_history(conversation_state: &ConversationState, editor_command: String) {
+    let tmp_dir = env::temp_dir();
+    let tmp_path = tmp_dir.join("ask_hist");
 
-    fs::write(&tmp_path, content).expect("Unable to write history file");
+    fs::write(&tmp_path, format_history(conversation_state)).expect("Unable to write history file");
     ProcessCommand::new(editor_command)
         .arg(&tmp_path)
         .status()
@@ -399,98 +1986,159 @@ fn show_history(conversation_state: &ConversationState, editor_command: String)
     fs::remove_file(&tmp_path).expect("Unable to delete temporary history file");
 }
 
-fn horizontal_line(ch: char) -> String {
-    let columns = term_size::dimensions_stdout().map(|(w, _)| w).unwrap_or(80);
-    ch.to_string().repeat(columns)
+// A short id for --history and --quote. It hashes the role and text, so it stays the same
+// for as long as the message does, compaction included, without storing anything.
+fn message_id(message: &Message) -> String {
+    let text = format!("{}\0{}", message.role, message_text(&message.content));
+    format!("{:06x}", fnv1a(text.as_bytes()) & 0xffffff)
 }
 
-fn handle_recursive_mode(
-    conversation_state: &mut ConversationState,
-    transcript_path: &PathBuf,
-    user_input: String,
-    settings: &Settings,
-) {
-    let input = Value::String(format!("You are entering 'recursive agent mode' with the following instruction: {}. Suggest the next command to run. Format your response as: COMMAND: <command> followed by an explanation. Or say DONE if the task is complete.", user_input));
-    perform_request(input, conversation_state, transcript_path, "", settings);
+// The quoted messages as blockquotes, in the order given
+fn quote_messages<'a>(
+    conversation_state: &ConversationState,
+    ids: impl Iterator<Item = &'a String>,
+) -> Result<String, String> {
+    let mut quotes = vec![];
+    for id in ids {
+        let id = id.trim().trim_start_matches('#').to_lowercase();
+        let message = conversation_state
+            .messages
+            .iter()
+            .rev()
+            .find(|message| message_id(message) == id)
+            .ok_or_else(|| format!("No message with id {} in this conversation (see --history).", id))?;
+        let text = message_text(&message.content)
+            .lines()
+            .map(|line| format!("> {}", line).trim_end().to_string())
+            .collect::<Vec<String>>()
+            .join("\n");
+        quotes.push(format!("Quoting an earlier {} message ({}):\n{}", message.role, id, text));
+    }
+    Ok(quotes.join("\n\n"))
+}
 
-    loop {
-        // Get last AI message to check if it's already a command
-        let mut last_message = conversation_state.messages.last().unwrap();
-        let mut response = last_message.content.as_str().unwrap_or("");
+// The transcript as shown in the editor view
+fn format_history(conversation_state: &ConversationState) -> String {
+    let mut content = String::new();
 
-        // Check if task is complete
-        if response.contains("DONE") {
-            println!("Task completed!");
-            break;
+    for message in &conversation_state.messages {
+        let header = match (&message.name, message.role.as_str()) {
+            (Some(name), "tool") => format!("tool: {}", name),
+            _ => message.role.clone(),
+        };
+        let header = format!("{} · {}", header, message_id(message));
+        content.push_str("\n\n");
+        content.push_str(&horizontal_line('▃'));
+        content.push_str(&format!("▍{} ▐\n", header));
+        content.push_str(&horizontal_line('▀'));
+        content.push('\n');
+
+        if message.role == "tool" {
+            content.push_str(&preview(message_text(&message.content), TOOL_RESULT_PREVIEW_LINES));
+        } else {
+            content.push_str(message_text(&message.content));
+        }
+
+        for call in message.tool_calls.iter().flat_map(|calls| calls.as_array()).flatten() {
+            content.push_str(&format!("\n⚙ {}\n", describe_tool_call(call)));
         }
+    }
 
-        // If the last message wasn't a command suggestion, steer the LLM towards it;
-        if !response.contains("COMMAND:") {
-            let input = Value::String(format!("Remember the original task: {}. Format your response ONLY as: COMMAND: <command> followed by an explanation. Or say DONE if the task is complete.", user_input));
-            perform_request(input, conversation_state, transcript_path, "", settings);
+    content
+}
 
-            // Update response with new AI message
-            last_message = conversation_state.messages.last().unwrap();
-            response = last_message.content.as_str().unwrap_or("");
+// The transcript as a markdown document, one section per message
+fn format_history_markdown(conversation_state: &ConversationState) -> String {
+    let mut content = format!(
+        "# {}\n",
+        conversation_state.title.as_deref().unwrap_or("Conversation")
+    );
 
-            // If response is updated, we need to check for completion again
-            if response.contains("DONE") {
-                println!("Task completed!");
-                break;
+    for message in &conversation_state.messages {
+        match (&message.name, message.role.as_str()) {
+            (Some(name), "tool") => {
+                content.push_str(&format!("\n## tool: {} ({})\n\n", name, message_id(message)));
+                content.push_str(&format!(
+                    "```\n{}\n```\n",
+                    preview(message_text(&message.content), TOOL_RESULT_PREVIEW_LINES)
+                ));
+                continue;
             }
+            _ => content.push_str(&format!("\n## {} ({})\n\n", message.role, message_id(message))),
+        }
+
+        let text = message_text(&message.content);
+        if !text.is_empty() {
+            content.push_str(text);
+            content.push('\n');
         }
+        for call in message.tool_calls.iter().flat_map(|calls| calls.as_array()).flatten() {
+            content.push_str(&format!("\n- tool call: `{}`\n", describe_tool_call(call)));
+        }
+    }
 
-        // Extract command
-        if let Some(cmd_start) = response.find("COMMAND:") {
-            let cmd_text = response[cmd_start..].lines().next().unwrap();
-            let command = cmd_text.trim_start_matches("COMMAND:").trim();
+    content
+}
 
-            // Get user approval
-            let confirm = dialoguer::Confirm::new()
-                .with_prompt(format!("\n\nRun command: {}", command))
-                .default(false)
-                .interact()
-                .unwrap_or(false);
+const TOOL_RESULT_PREVIEW_LINES: usize = 10;
+const TOOL_ARGUMENTS_PREVIEW_CHARS: usize = 120;
+
+// `name(arguments)` with the arguments squashed onto one line and cut short
+fn describe_tool_call(call: &Value) -> String {
+    let function = &call["function"];
+    let name = function["name"].as_str().unwrap_or("?");
+    let arguments = match &function["arguments"] {
+        // OpenAI sends arguments as a JSON string
+        Value::String(text) => serde_json::from_str::<Value>(text)
+            .map(|parsed| parsed.to_string())
+            .unwrap_or_else(|_| text.split_whitespace().collect::<Vec<_>>().join(" ")),
+        other => other.to_string(),
+    };
 
-            if confirm {
-                // Execute command and capture output
-                match ProcessCommand::new("sh").arg("-c").arg(command).output() {
-                    Ok(output) => {
-                        let stdout = String::from_utf8_lossy(&output.stdout);
-                        let stderr = String::from_utf8_lossy(&output.stderr);
-                        let result =
-                            format!("Command output:\nstdout:\n{}\nstderr:\n{}", stdout, stderr);
-                        println!("{}", result);
-
-                        // Pass result back to AI
-                        let input = Value::String(result);
-                        perform_request(input, conversation_state, transcript_path, "", &settings);
-                    }
-                    Err(e) => {
-                        println!("Failed to execute command: {}", e);
-                        let input = Value::String(format!("Command failed: {}", e));
-                        perform_request(input, conversation_state, transcript_path, "", &settings);
-                    }
-                }
-            } else {
-                let comment = dialoguer::Input::<String>::new()
-                    .with_prompt("Comment on the provided code")
-                    .interact()
-                    .unwrap_or_default();
+    let mut shown: String = arguments.chars().take(TOOL_ARGUMENTS_PREVIEW_CHARS).collect();
+    if shown.len() < arguments.len() {
+        shown.push('…');
+    }
+    format!("{}({})", name, shown)
+}
 
-                let input = Value::String(
-                    format!("Command was rejected by user.\nFEEDBACK: {}\n\nPlease suggest an alternative.", comment).to_string(),
-                );
-                perform_request(input, conversation_state, transcript_path, "", &settings);
-            }
-        }
+// The first lines of long text, with a note of how much was left out
+fn preview(text: &str, max_lines: usize) -> String {
+    let lines: Vec<&str> = text.lines().collect();
+    if lines.len() <= max_lines {
+        return text.to_string();
+    }
+    format!(
+        "{}\n… ({} more lines)",
+        lines[..max_lines].join("\n"),
+        lines.len() - max_lines
+    )
+}
+
+// Text of a message, whether plain or multi-part (text + image)
+fn message_text(content: &Value) -> &str {
+    if let Some(text) = content.as_str() {
+        text
+    } else if let Some(array) = content.as_array() {
+        array
+            .first()
+            .and_then(|item| item.get("text"))
+            .and_then(|v| v.as_str())
+            .unwrap_or("")
+    } else {
+        ""
     }
 }
 
+fn horizontal_line(ch: char) -> String {
+    let columns = term_size::dimensions_stdout().map(|(w, _)| w).unwrap_or(80);
+    ch.to_string().repeat(columns)
+}
+
 fn delete_all_files(files: Vec<PathBuf>) {
     let mut deleted_count = 0;
     for file in &files {
-        if let Err(e) = fs::remove_file(file) {
+        if let Err(e) = conversation::remove(file) {
             eprintln!("Failed to delete {}: {}", file.display(), e);
         } else {
             deleted_count += 1;
@@ -499,20 +2147,124 @@ fn delete_all_files(files: Vec<PathBuf>) {
     println!("Deleted {} conversation(s).", deleted_count);
 }
 
-fn manage_ongoing_convos(current_convo: &mut ConversationState, current_transcript_path: &PathBuf, settings: &Settings) {
-    let transcript_folder = env::temp_dir();
-    let entries = fs::read_dir(&transcript_folder).unwrap();
+// Lists transcripts in a folder, as identified by the configured name prefix
+fn list_transcripts(folder: &Path, settings: &Settings) -> Vec<PathBuf> {
+    let entries = match fs::read_dir(folder) {
+        Ok(entries) => entries,
+        Err(_) => return vec![],
+    };
 
-    let files: Vec<PathBuf> = entries
+    entries
         .filter_map(|e| e.ok())
         .map(|e| e.path())
         .filter(|p| {
-            p.file_name()
-                .unwrap()
-                .to_string_lossy()
-                .starts_with(&settings.transcript_name)
+            let name = p.file_name().unwrap().to_string_lossy();
+            name.starts_with(&settings.transcript_name) && !conversation::is_auxiliary_file(&name)
         })
-        .collect();
+        .collect()
+}
+
+// Archived transcripts live outside the temp dir so they survive reboots
+// $XDG_DATA_HOME/ask
+fn data_dir() -> PathBuf {
+    env::var("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|_| env::var("HOME").map(|home| PathBuf::from(home).join(".local/share")))
+        .unwrap_or_else(|_| env::temp_dir())
+        .join("ask")
+}
+
+fn archive_dir(settings: &Settings) -> PathBuf {
+    if !settings.archive_dir.is_empty() {
+        return PathBuf::from(&settings.archive_dir);
+    }
+    data_dir().join("archive")
+}
+
+// "<title or file name> => <first line of the first user message>"
+fn describe_transcript(file: &Path) -> String {
+    let header = conversation::header(file);
+    let name = header
+        .title
+        .clone()
+        .unwrap_or_else(|| file.file_name().unwrap().to_string_lossy().to_string());
+    let content = &header.preview;
+    let origin = match &header.metadata {
+        Some(metadata) => {
+            let branch = metadata
+                .git_branch
+                .as_ref()
+                .map(|branch| format!(" ({})", branch))
+                .unwrap_or_default();
+            format!("  [{}:{}{}]", metadata.hostname, metadata.cwd, branch)
+        }
+        None => "".to_string(),
+    };
+    format!(
+        "{} => {}{}",
+        name,
+        content
+            .lines()
+            .next()
+            .unwrap_or("")
+            .chars()
+            .take(64)
+            .collect::<String>(),
+        origin
+    )
+}
+
+// Prints the tail of a conversation so it can be recognized before acting on it
+fn preview_transcript(file: &Path) {
+    // Never preview the startup message
+    let count = conversation::header(file).message_count().saturating_sub(1).min(4);
+
+    for message in conversation::load_tail(file, count, None) {
+        let text = message_text(&message.content);
+        let mut lines = text.lines();
+        let preview: Vec<&str> = lines.by_ref().take(3).collect();
+        let ellipsis = if lines.next().is_some() { " …" } else { "" };
+        eprintln!("{}", epaint(Style::Prompt, &format!("{}:", message.role)));
+        eprintln!("  {}{}", preview.join("\n  "), ellipsis);
+    }
+}
+
+// Moves a transcript between folders, copying when a plain rename can't cross filesystems
+// Never replaces a transcript already at the destination: the moved one gets a numeric suffix
+// instead. Linking (or creating the copy) fails when the name is taken, so nothing is lost even
+// if another process writes the same name meanwhile.
+fn move_transcript(file: &Path, destination_folder: &Path) -> io::Result<PathBuf> {
+    fs::create_dir_all(destination_folder)?;
+    let name = file.file_name().unwrap().to_string_lossy();
+    for attempt in 1.. {
+        let destination = match attempt {
+            1 => destination_folder.join(&*name),
+            n => destination_folder.join(format!("{}-{}", name, n)),
+        };
+        let moved = fs::hard_link(file, &destination).or_else(|e| match e.kind() {
+            io::ErrorKind::AlreadyExists => Err(e),
+            // Another file system
+            _ => fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&destination)
+                .and_then(|mut copy| io::copy(&mut fs::File::open(file)?, &mut copy).map(|_| ())),
+        });
+        match moved {
+            Ok(()) => {
+                fs::remove_file(file)?;
+                conversation::remove_auxiliary_files(file);
+                return Ok(destination);
+            }
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    unreachable!()
+}
+
+fn manage_ongoing_convos(current_convo: &mut ConversationState, current_transcript_path: &Path, settings: &Settings) {
+    let files = list_transcripts(&env::temp_dir(), settings);
 
     if files.is_empty() {
         println!("No conversations to manage!");
@@ -520,40 +2272,13 @@ fn manage_ongoing_convos(current_convo: &mut ConversationState, current_transcri
     }
 
     // Prepare options for dialoguer
-    let mut options: Vec<String> = files
-        .iter()
-        .map(|file| {
-            let data = fs::read_to_string(file).unwrap_or_default();
-            let convo: ConversationState =
-                serde_json::from_str(&data).unwrap_or_else(|_| ConversationState {
-                    model: "".to_string(),
-                    messages: vec![],
-                });
-            let first_message = convo.messages.get(1); // Use get to avoid panicking
-            let content = if let Some(msg) = first_message {
-                msg.content.as_str().unwrap_or("")
-            } else {
-                ""
-            };
-            format!(
-                "{} => {}",
-                file.file_name().unwrap().to_string_lossy(),
-                content
-                    .lines()
-                    .next()
-                    .unwrap_or("")
-                    .chars()
-                    .take(64)
-                    .collect::<String>()
-            )
-        })
-        .collect();
+    let mut options: Vec<String> = files.iter().map(|file| describe_transcript(file)).collect();
 
     //Add special helper option
     options.insert(0, ">>> Delete All Conversations".to_string());
 
-    let selection = Select::with_theme(&ColorfulTheme::default())
-        .with_prompt("Select an option to manage")
+    let selection = FuzzySelect::with_theme(&*style::dialog_theme())
+        .with_prompt("Select an option to manage (type to filter)")
         .default(0)
         .items(&options)
         .interact();
@@ -565,41 +2290,60 @@ fn manage_ongoing_convos(current_convo: &mut ConversationState, current_transcri
         }
 
         let selected_file = &files[index - 1]; //First option is the special helper
-        let action = Select::with_theme(&ColorfulTheme::default())
+        preview_transcript(selected_file);
+        let action = Select::with_theme(&*style::dialog_theme())
             .with_prompt("Choose an action")
             .default(0)
-            .items(&["Delete", "Copy to Current Conversation", "Cancel"])
+            .items(&["View", "Rename", "Delete", "Archive", "Copy to Current Conversation", "Cancel"])
             .interact();
 
         match action {
             Ok(0) => {
+                show_history(&conversation::load_or_empty(selected_file), settings.editor.clone());
+            }
+            Ok(1) => {
+                let title = dialoguer::Input::<String>::with_theme(&*style::dialog_theme())
+                    .with_prompt("New title")
+                    .interact_text()
+                    .unwrap_or_default();
+                // An unreadable transcript must not be replaced by an empty one with a title
+                match conversation::load(selected_file) {
+                    Some(mut convo) => rename_convo(&mut convo, selected_file, &title),
+                    None => eprintln!("{}", epaint(Style::Error, "Could not load the conversation, it was not renamed.")),
+                }
+            }
+            Ok(2) => {
                 // Delete the selected conversation
-                if let Err(e) = fs::remove_file(selected_file) {
+                if let Err(e) = conversation::remove(selected_file) {
                     println!("Failed to delete conversation: {}", e);
                 } else {
                     println!("Conversation deleted successfully.");
                 }
             }
-            Ok(1) => {
+            Ok(3) => {
+                // Move the selected conversation out of the active list
+                let archive = archive_dir(settings);
+                match move_transcript(selected_file, &archive) {
+                    Ok(destination) => println!("Conversation archived to {}.", destination.display()),
+                    Err(e) => println!("Failed to archive conversation: {}", e),
+                }
+            }
+            Ok(4) => {
                 // Copy the selected conversation to current conversation
-                let data = fs::read_to_string(selected_file).unwrap_or_default();
-                let convo_to_copy: ConversationState =
-                    serde_json::from_str(&data).unwrap_or_else(|_| ConversationState {
-                        model: "".to_string(),
-                        messages: vec![],
-                    });
+                let convo_to_copy = conversation::load_or_empty(selected_file);
 
                 if convo_to_copy.model != current_convo.model {
-                    println!("Cannot copy conversation: Model mismatch.");
+                    println!(
+                        "Cannot copy conversation: Model mismatch ({} vs {}). Use --switch-model on one of them first.",
+                        convo_to_copy.model, current_convo.model
+                    );
                     return;
                 }
 
                 current_convo
                     .messages
                     .extend(convo_to_copy.messages.iter().skip(1).cloned()); // Skip initial message
-                let conversation_json = serde_json::to_string(&current_convo).unwrap();
-                fs::write(current_transcript_path, conversation_json)
-                    .expect("Unable to write transcript file");
+                conversation::save(current_convo, current_transcript_path);
                 println!("Conversation copied successfully.");
             }
             _ => {
@@ -609,3 +2353,61 @@ fn manage_ongoing_convos(current_convo: &mut ConversationState, current_transcri
         }
     }
 }
+
+// Titles show up in the manage menus in place of the PID-based file name
+fn rename_convo(convo: &mut ConversationState, transcript_path: &Path, title: &str) {
+    if title.trim().is_empty() {
+        println!("Title cannot be empty.");
+        return;
+    }
+    convo.title = Some(title.trim().to_string());
+    conversation::save(convo, transcript_path);
+    println!("Conversation renamed to '{}'.", title.trim());
+}
+
+fn manage_archived_convos(settings: &Settings) {
+    let archive = archive_dir(settings);
+    let files = list_transcripts(&archive, settings);
+
+    if files.is_empty() {
+        println!("No archived conversations!");
+        return;
+    }
+
+    let options: Vec<String> = files.iter().map(|file| describe_transcript(file)).collect();
+    let selection = FuzzySelect::with_theme(&*style::dialog_theme())
+        .with_prompt("Select an archived conversation (type to filter)")
+        .default(0)
+        .items(&options)
+        .interact();
+
+    if let Ok(index) = selection {
+        let selected_file = &files[index];
+        preview_transcript(selected_file);
+        let action = Select::with_theme(&*style::dialog_theme())
+            .with_prompt("Choose an action")
+            .default(0)
+            .items(&["View", "Restore", "Delete", "Cancel"])
+            .interact();
+
+        match action {
+            Ok(0) => {
+                show_history(&conversation::load_or_empty(selected_file), settings.editor.clone());
+            }
+            Ok(1) => match move_transcript(selected_file, &env::temp_dir()) {
+                Ok(destination) => println!("Conversation restored as {}.", destination.display()),
+                Err(e) => println!("Failed to restore conversation: {}", e),
+            },
+            Ok(2) => {
+                if let Err(e) = conversation::remove(selected_file) {
+                    println!("Failed to delete conversation: {}", e);
+                } else {
+                    println!("Conversation deleted successfully.");
+                }
+            }
+            _ => {
+                println!("Action cancelled.");
+            }
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use crate::conversation::ConversationState;
+use crate::style::{epaint, Style};
+use crate::{complete, data_dir, message_text, Settings};
+use std::fs;
+use std::path::PathBuf;
+
+fn memory_path(settings: &Settings) -> PathBuf {
+    if settings.memory_file.is_empty() {
+        data_dir().join("memory.md")
+    } else {
+        PathBuf::from(&settings.memory_file)
+    }
+}
+
+fn load(settings: &Settings) -> String {
+    fs::read_to_string(memory_path(settings)).unwrap_or_default()
+}
+
+fn store(text: &str, settings: &Settings) -> Result<(), String> {
+    let path = memory_path(settings);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Could not create {}: {}", parent.display(), e))?;
+    }
+    fs::write(&path, text).map_err(|e| format!("Could not write {}: {}", path.display(), e))
+}
+
+// Adds what is remembered about the user to a new conversation's system prompt
+pub fn inject(mut state: ConversationState, settings: &Settings) -> ConversationState {
+    let memory = load(settings);
+    if settings.memory && !memory.trim().is_empty() {
+        if let Some(serde_json::Value::String(startup_message)) =
+            state.messages.first_mut().map(|m| &mut m.content)
+        {
+            startup_message.push_str(&format!(
+                "\n\nWhat you know about the user from earlier conversations:\n{}",
+                memory.trim()
+            ));
+        }
+    }
+    state
+}
+
+// Merges the stable facts from this conversation into the memory file
+pub fn update(state: &ConversationState, settings: &Settings) -> Result<(), String> {
+    let history: Vec<String> = state
+        .messages
+        .iter()
+        .skip(1) //The system prompt, which may already hold the memory
+        .filter(|message| message.role == "user" || message.role == "assistant")
+        .map(|message| format!("{}: {}", message.role, message_text(&message.content)))
+        .collect();
+    if history.is_empty() {
+        return Ok(());
+    }
+
+    let memory = load(settings);
+    let prompt = format!(
+        "{}\n\nCurrently remembered:\n{}\n\nConversation:\n{}",
+        settings.memory_template,
+        if memory.trim().is_empty() {
+            "(nothing yet)"
+        } else {
+            memory.trim()
+        },
+        history.join("\n\n")
+    );
+    let updated = complete(prompt, settings).ok_or("The memory update request failed")?;
+    store(&format!("{}\n", updated.trim()), settings)
+}
+
+// Every memory_update_turns user messages, fold the conversation into the memory
+pub fn maybe_update(state: &ConversationState, settings: &Settings) {
+    let turns = state.messages.iter().skip(1).filter(|m| m.role == "user").count();
+    if !settings.memory
+        || settings.memory_update_turns == 0
+        || turns % settings.memory_update_turns != 0
+    {
+        return;
+    }
+    let quiet = Settings {
+        show_spinner: false,
+        show_stats: false,
+        ..settings.clone()
+    };
+    match update(state, &quiet) {
+        Ok(_) => eprintln!("{}", epaint(Style::Dim, "Memory updated.")),
+        Err(e) => eprintln!("{}", epaint(Style::Warning, &e)),
+    }
+}
+
+// `--memory show|edit|clear|update`
+pub fn manage(action: &str, state: &ConversationState, settings: &Settings) {
+    let path = memory_path(settings);
+    let result = match action {
+        "show" => {
+            let memory = load(settings);
+            if memory.trim().is_empty() {
+                println!("Nothing remembered yet ({}).", path.display());
+            } else {
+                print!("{}", memory);
+            }
+            Ok(())
+        }
+        "edit" => match dialoguer::Editor::new()
+            .extension(".md")
+            .edit(&load(settings))
+        {
+            Ok(Some(edited)) => store(&edited, settings),
+            Ok(None) => Ok(()),
+            Err(e) => Err(format!("Could not open editor: {}", e)),
+        },
+        "clear" => match fs::remove_file(&path) {
+            Ok(_) => {
+                println!("Memory cleared.");
+                Ok(())
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(format!("Could not remove {}: {}", path.display(), e)),
+        },
+        _ => update(state, settings).map(|_| print!("{}", load(settings))),
+    };
+    if let Err(e) = result {
+        eprintln!("{}", epaint(Style::Error, &e));
+    }
+}
@@ -0,0 +1,57 @@
+use crate::style::{epaint, Style};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, OnceLock};
+use std::thread;
+use std::time::Duration;
+
+// How long a reply waits for a FIFO reader before ask gives up on it
+const READER_TIMEOUT: Duration = Duration::from_secs(2);
+
+// Set by --stream-to: a named pipe or unix socket that gets a copy of every reply
+static TARGET: OnceLock<PathBuf> = OnceLock::new();
+
+pub fn stream_to(path: &str) {
+    let _ = TARGET.set(PathBuf::from(path));
+}
+
+// Writes the reply to the --stream-to target, one connection (or FIFO open) per reply, so
+// readers see each reply end with EOF. Replies arrive whole, so they are mirrored whole too.
+pub fn mirror(text: &str) {
+    let Some(path) = TARGET.get() else {
+        return;
+    };
+    let (done, finished) = mpsc::channel();
+    let path_for_writer = path.clone();
+    let text = text.to_string();
+    // Opening a FIFO blocks until someone reads it, so it happens off the main thread
+    thread::spawn(move || {
+        let _ = done.send(write(&path_for_writer, &text));
+    });
+    let result = finished
+        .recv_timeout(READER_TIMEOUT)
+        .unwrap_or_else(|_| Err("nobody is reading it".to_string()));
+    if let Err(e) = result {
+        let message = format!("WARNING: Could not stream to {}: {}", path.display(), e);
+        eprintln!("{}", epaint(Style::Warning, &message));
+    }
+}
+
+fn write(path: &Path, text: &str) -> Result<(), String> {
+    let is_socket = fs::metadata(path)
+        .map(|metadata| metadata.file_type().is_socket())
+        .unwrap_or(false);
+    let result = if is_socket {
+        UnixStream::connect(path).and_then(|mut stream| stream.write_all(text.as_bytes()))
+    } else {
+        // Appending, so a plain file collects the replies like a log
+        OpenOptions::new()
+            .append(true)
+            .open(path)
+            .and_then(|mut pipe| pipe.write_all(text.as_bytes()))
+    };
+    result.map_err(|e| e.to_string())
+}
@@ -0,0 +1,131 @@
+use crate::Settings;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::fs;
+
+// Host name that selects the built-in offline provider
+pub const HOST: &str = "mock";
+
+// One canned reply. Entries with `match` answer any request whose last message contains it,
+// the others are served in conversation order.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Entry {
+    Reply(String),
+    Matched {
+        #[serde(rename = "match", default)]
+        pattern: Option<String>,
+        #[serde(default)]
+        reply: String,
+        #[serde(default)]
+        tool_calls: Option<Value>,
+    },
+}
+
+impl Entry {
+    fn pattern(&self) -> Option<&str> {
+        match self {
+            Entry::Reply(_) => None,
+            Entry::Matched { pattern, .. } => pattern.as_deref(),
+        }
+    }
+
+    fn message(&self) -> Value {
+        match self {
+            Entry::Reply(reply) => json!({ "role": "assistant", "content": reply }),
+            Entry::Matched {
+                reply, tool_calls, ..
+            } => {
+                let mut message = json!({ "role": "assistant", "content": reply });
+                if let Some(tool_calls) = tool_calls {
+                    message["tool_calls"] = tool_calls.clone();
+                }
+                message
+            }
+        }
+    }
+}
+
+fn load_cassette(path: &str) -> Result<Vec<Entry>, String> {
+    let contents =
+        fs::read_to_string(path).map_err(|e| format!("Could not read {}: {}", path, e))?;
+    serde_json::from_str(&contents).map_err(|e| format!("Could not parse {}: {}", path, e))
+}
+
+fn text_of(message: &Value) -> String {
+    match &message["content"] {
+        Value::String(text) => text.clone(),
+        // Vision requests send a list of parts
+        Value::Array(parts) => parts
+            .iter()
+            .filter_map(|part| part["text"].as_str())
+            .collect::<Vec<&str>>()
+            .join("\n"),
+        _ => String::new(),
+    }
+}
+
+fn pick(entries: &[Entry], messages: &[Value]) -> Option<Value> {
+    let last = messages.last().map(text_of).unwrap_or_default();
+    if let Some(entry) = entries.iter().find(|entry| {
+        entry
+            .pattern()
+            .is_some_and(|pattern| last.contains(pattern))
+    }) {
+        return Some(entry.message());
+    }
+
+    // Counting the replies already in the request keeps the order across separate invocations
+    let turn = messages.iter().filter(|m| m["role"] == "assistant").count();
+    let sequence: Vec<&Entry> = entries
+        .iter()
+        .filter(|entry| entry.pattern().is_none())
+        .collect();
+    sequence
+        .get(turn)
+        .or(sequence.last())
+        .map(|entry| entry.message())
+}
+
+// Answers a chat completion request without touching the network
+pub fn respond(endpoint: &str, body: &Value, settings: &Settings) -> Result<Value, String> {
+    if endpoint != settings.endpoint {
+        return Err(format!(
+            "The mock provider only answers {}",
+            settings.endpoint
+        ));
+    }
+    let messages = body["messages"].as_array().cloned().unwrap_or_default();
+
+    let mut message = if settings.mock_cassette.is_empty() {
+        let last = messages.last().map(text_of).unwrap_or_default();
+        json!({ "role": "assistant", "content": format!("Mock reply to: {}", last) })
+    } else {
+        pick(&load_cassette(&settings.mock_cassette)?, &messages)
+            .ok_or_else(|| format!("{} has no reply for this request", settings.mock_cassette))?
+    };
+
+    // Cut off like a real provider would at max_tokens, so continuing replies can be tried out
+    let mut finish_reason = "stop";
+    if let (Some(max_tokens), Some(text)) = (body["max_tokens"].as_u64(), message["content"].as_str()) {
+        let limit = max_tokens as usize * 4;
+        if text.chars().count() > limit {
+            message["content"] = Value::String(text.chars().take(limit).collect());
+            finish_reason = "length";
+        }
+    }
+
+    let prompt_tokens: usize = messages.iter().map(|m| text_of(m).len() / 4).sum();
+    let completion_tokens = text_of(&message).len() / 4;
+    Ok(json!({
+        "id": "mock",
+        "object": "chat.completion",
+        "model": body["model"],
+        "choices": [{ "index": 0, "message": message, "finish_reason": finish_reason }],
+        "usage": {
+            "prompt_tokens": prompt_tokens,
+            "completion_tokens": completion_tokens,
+            "total_tokens": prompt_tokens + completion_tokens
+        }
+    }))
+}
@@ -0,0 +1,101 @@
+use std::fs;
+use std::path::Path;
+use tree_sitter::{Language, Node, Parser};
+
+// Declarations worth listing, per language. Anything nested in one of them (methods in an
+// impl or class) is listed indented below it.
+const RUST_ITEMS: &[&str] = &[
+    "function_item", "function_signature_item", "struct_item", "enum_item", "union_item",
+    "trait_item", "impl_item", "mod_item", "type_item", "const_item", "static_item",
+    "macro_definition",
+];
+const PYTHON_ITEMS: &[&str] = &["function_definition", "class_definition"];
+const JAVASCRIPT_ITEMS: &[&str] = &[
+    "function_declaration", "generator_function_declaration", "class_declaration",
+    "method_definition",
+];
+const GO_ITEMS: &[&str] = &["function_declaration", "method_declaration", "type_spec"];
+
+pub fn language_for(path: &Path) -> Option<(Language, &'static [&'static str])> {
+    let extension = path.extension()?.to_str()?;
+    match extension {
+        "rs" => Some((tree_sitter_rust::LANGUAGE.into(), RUST_ITEMS)),
+        "py" => Some((tree_sitter_python::LANGUAGE.into(), PYTHON_ITEMS)),
+        "js" | "mjs" | "cjs" | "jsx" => Some((tree_sitter_javascript::LANGUAGE.into(), JAVASCRIPT_ITEMS)),
+        "go" => Some((tree_sitter_go::LANGUAGE.into(), GO_ITEMS)),
+        _ => None,
+    }
+}
+
+// A readable label for a declaration, e.g. `fn main` or `impl Display for Foo`
+fn label(node: Node, source: &[u8]) -> String {
+    let text = |field: &str| {
+        node.child_by_field_name(field)
+            .and_then(|child| child.utf8_text(source).ok())
+            .map(|text| text.to_string())
+    };
+
+    let kind = node
+        .kind()
+        .trim_end_matches("_item")
+        .trim_end_matches("_definition")
+        .trim_end_matches("_declaration")
+        .trim_end_matches("_spec");
+
+    if node.kind() == "impl_item" {
+        return match (text("trait"), text("type")) {
+            (Some(trait_name), Some(type_name)) => format!("impl {} for {}", trait_name, type_name),
+            (None, Some(type_name)) => format!("impl {}", type_name),
+            _ => "impl".to_string(),
+        };
+    }
+
+    match text("name") {
+        Some(name) => format!("{} {}", kind, name),
+        None => {
+            // Fall back to the first line of the declaration itself
+            let first_line = node.utf8_text(source).unwrap_or("").lines().next().unwrap_or("");
+            first_line.chars().take(80).collect()
+        }
+    }
+}
+
+fn collect(node: Node, source: &[u8], items: &[&str], depth: usize, lines: &mut Vec<String>) {
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        if items.contains(&child.kind()) {
+            lines.push(format!(
+                "{}{} (lines {}-{})",
+                "  ".repeat(depth),
+                label(child, source),
+                child.start_position().row + 1,
+                child.end_position().row + 1
+            ));
+            collect(child, source, items, depth + 1, lines);
+        } else {
+            collect(child, source, items, depth, lines);
+        }
+    }
+}
+
+// Functions, types and impl blocks in a source file with their line ranges
+pub fn outline(path: &Path) -> Result<String, String> {
+    let (language, items) = language_for(path)
+        .ok_or_else(|| format!("no outline support for {} (supported: .rs, .py, .js, .go)", path.display()))?;
+    let source = fs::read(path).map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+
+    let mut parser = Parser::new();
+    parser.set_language(&language).map_err(|e| e.to_string())?;
+    let tree = parser
+        .parse(&source, None)
+        .ok_or_else(|| format!("could not parse {}", path.display()))?;
+
+    let mut lines = vec![];
+    collect(tree.root_node(), &source, items, 0, &mut lines);
+    if lines.is_empty() {
+        return Ok(format!("No declarations found in {}", path.display()));
+    }
+
+    let total = source.iter().filter(|&&byte| byte == b'\n').count();
+    Ok(format!("Outline of {} ({} lines):\n{}", path.display(), total, lines.join("\n")))
+}
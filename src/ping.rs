@@ -0,0 +1,129 @@
+use crate::compare::target_settings;
+use crate::style::{epaint, paint, Style};
+use crate::{get_api_keys, mock, Settings};
+use serde_json::{json, Value};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const TIMEOUT: Duration = Duration::from_secs(20);
+
+enum Outcome {
+    Ok,
+    Offline,
+    NoKey,
+    Unreachable(String),
+    KeyRejected(u16),
+    Failed(u16, String),
+}
+
+// `--ping [provider]`: a tiny request to the default provider and every one in `providers`
+// (or only the one named), telling network, key and provider problems apart
+pub fn ping(only: Option<&str>, settings: &Settings) {
+    let mut targets: Vec<(String, Settings)> = vec![];
+    if only.is_none_or(|name| name == "default") {
+        targets.push(("default".to_string(), settings.clone()));
+    }
+    let mut names: Vec<&String> = settings.providers.keys().collect();
+    names.sort();
+    for name in names {
+        if only.is_none_or(|only| only == name) {
+            targets.push((name.clone(), target_settings(name, settings)));
+        }
+    }
+    if targets.is_empty() {
+        eprintln!(
+            "{}",
+            epaint(
+                Style::Error,
+                &format!("No provider named '{}'.", only.unwrap_or(""))
+            )
+        );
+        return;
+    }
+
+    let results: Vec<(Outcome, Duration)> = thread::scope(|scope| {
+        let handles: Vec<_> = targets
+            .iter()
+            .map(|(_, target)| scope.spawn(move || probe(target)))
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect()
+    });
+
+    for ((name, target), (outcome, elapsed)) in targets.iter().zip(results) {
+        let label = format!("{:<12} {} ({})", name, target.host, target.model);
+        let milliseconds = format!("{} ms", elapsed.as_millis());
+        let line = match outcome {
+            Outcome::Ok => paint(Style::Success, &format!("ok, {}", milliseconds)),
+            Outcome::Offline => paint(Style::Dim, "mock provider, nothing to reach"),
+            Outcome::NoKey => paint(
+                Style::Warning,
+                &format!("no API key ({})", target.api_key_variable),
+            ),
+            Outcome::Unreachable(e) => paint(Style::Error, &format!("unreachable: {}", e)),
+            Outcome::KeyRejected(status) => paint(
+                Style::Error,
+                &format!(
+                    "reachable, but the key was rejected (HTTP {}), {}",
+                    status, milliseconds
+                ),
+            ),
+            Outcome::Failed(status, message) => paint(
+                Style::Warning,
+                &format!("reachable, HTTP {}: {}, {}", status, message, milliseconds),
+            ),
+        };
+        println!("{}  {}", label, line);
+    }
+}
+
+fn probe(settings: &Settings) -> (Outcome, Duration) {
+    if settings.host == mock::HOST {
+        return (Outcome::Offline, Duration::ZERO);
+    }
+    let Some(key) = get_api_keys(settings).first() else {
+        return (Outcome::NoKey, Duration::ZERO);
+    };
+
+    let client = match reqwest::blocking::Client::builder()
+        .timeout(TIMEOUT)
+        .build()
+    {
+        Ok(client) => client,
+        Err(e) => return (Outcome::Unreachable(e.to_string()), Duration::ZERO),
+    };
+    let body = json!({
+        "model": settings.model,
+        "messages": [{"role": "user", "content": "Reply with: pong"}],
+    });
+    let start = Instant::now();
+    let response = client
+        .post(format!("https://{}{}", settings.host, settings.endpoint))
+        .header("Authorization", format!("Bearer {}", key))
+        .json(&body)
+        .send();
+    let elapsed = start.elapsed();
+
+    let outcome = match response {
+        Err(e) if e.is_timeout() => {
+            Outcome::Unreachable(format!("no answer within {} s", TIMEOUT.as_secs()))
+        }
+        Err(e) => Outcome::Unreachable(e.to_string()),
+        Ok(response) if response.status().is_success() => Outcome::Ok,
+        Ok(response) if matches!(response.status().as_u16(), 401 | 403) => {
+            Outcome::KeyRejected(response.status().as_u16())
+        }
+        Ok(response) => {
+            let status = response.status().as_u16();
+            let text = response.text().unwrap_or_default();
+            let message = serde_json::from_str::<Value>(&text)
+                .ok()
+                .and_then(|data| data["error"]["message"].as_str().map(str::to_string))
+                .unwrap_or_else(|| text.chars().take(120).collect());
+            Outcome::Failed(status, message)
+        }
+    };
+    (outcome, elapsed)
+}
@@ -0,0 +1,126 @@
+use crate::style::{paint, Style};
+use crate::{print_response, Settings};
+
+// Commands longer than this get a numbered preview before the approval prompt
+pub const PREVIEW_LINES: usize = 3;
+
+// Shows a long command with line numbers and shell highlighting, paged when it doesn't fit
+pub fn show(command: &str, settings: &Settings) {
+    let lines: Vec<&str> = command.lines().collect();
+    let width = lines.len().to_string().len();
+    let mut heredoc_end: Option<String> = None;
+
+    let numbered: Vec<String> = lines
+        .iter()
+        .enumerate()
+        .map(|(index, line)| {
+            // Heredoc bodies are content, not shell, so they are left as written
+            let highlighted = match &heredoc_end {
+                Some(end) if line.trim() == end => {
+                    heredoc_end = None;
+                    paint(Style::Prompt, line)
+                }
+                Some(_) => line.to_string(),
+                None => {
+                    heredoc_end = heredoc_delimiter(line);
+                    highlight(line)
+                }
+            };
+            format!(
+                "{} {}",
+                paint(
+                    Style::Dim,
+                    &format!("{:>width$} │", index + 1, width = width)
+                ),
+                highlighted
+            )
+        })
+        .collect();
+
+    print_response(
+        &numbered.join("\n"),
+        &Settings {
+            use_pager: true,
+            ..settings.clone()
+        },
+    );
+}
+
+// The word ending a heredoc opened on this line (`<<EOF`, `<<-'EOF'`, `<< "EOF"`)
+fn heredoc_delimiter(line: &str) -> Option<String> {
+    let (_, after) = line.split_once("<<")?;
+    if after.starts_with('<') {
+        return None; //A here-string
+    }
+    let word = after
+        .trim_start_matches('-')
+        .trim_start()
+        .split(|c: char| c.is_whitespace() || c == ';' || c == '|' || c == '>')
+        .next()?
+        .trim_matches(|c| c == '\'' || c == '"');
+    (!word.is_empty()).then(|| word.to_string())
+}
+
+// Comments dim, program names and operators stand out, quoted strings get their own color
+fn highlight(line: &str) -> String {
+    if line.trim_start().starts_with('#') {
+        return paint(Style::Dim, line);
+    }
+
+    let mut out = String::new();
+    let mut word = String::new();
+    let mut expect_program = true;
+    let mut chars = line.chars().peekable();
+    let flush = |word: &mut String, out: &mut String, expect_program: &mut bool| {
+        if word.is_empty() {
+            return;
+        }
+        if *expect_program && !word.contains('=') {
+            out.push_str(&paint(Style::Command, word));
+            *expect_program = false;
+        } else {
+            out.push_str(word);
+        }
+        word.clear();
+    };
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' | '"' => {
+                flush(&mut word, &mut out, &mut expect_program);
+                let mut quoted = c.to_string();
+                for next in chars.by_ref() {
+                    quoted.push(next);
+                    if next == c {
+                        break;
+                    }
+                }
+                out.push_str(&paint(Style::Success, &quoted));
+            }
+            '|' | '&' | ';' | '>' | '<' => {
+                flush(&mut word, &mut out, &mut expect_program);
+                let mut operator = c.to_string();
+                while let Some(&next) = chars.peek() {
+                    if !"|&;><-".contains(next) {
+                        break;
+                    }
+                    operator.push(next);
+                    chars.next();
+                }
+                expect_program = !operator.starts_with(['>', '<']);
+                out.push_str(&paint(Style::Prompt, &operator));
+            }
+            '#' if word.is_empty() => {
+                let rest: String = std::iter::once(c).chain(chars.by_ref()).collect();
+                out.push_str(&paint(Style::Dim, &rest));
+            }
+            c if c.is_whitespace() => {
+                flush(&mut word, &mut out, &mut expect_program);
+                out.push(c);
+            }
+            c => word.push(c),
+        }
+    }
+    flush(&mut word, &mut out, &mut expect_program);
+    out
+}
@@ -0,0 +1,130 @@
+use crate::style::{self, epaint, Style};
+use crate::{calc, data_dir, Settings};
+use atty::Stream;
+use dialoguer::FuzzySelect;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// One line of prompts.jsonl
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    timestamp: u64,
+    prompt: String,
+}
+
+fn history_path() -> PathBuf {
+    data_dir().join("prompts.jsonl")
+}
+
+// Appends a submitted prompt, separately from the transcripts so it outlives them. The file is
+// cut back to the newest `prompt_history` lines once it holds twice as many.
+pub fn record(prompt: &str, settings: &Settings) {
+    if settings.prompt_history == 0 || prompt.trim().is_empty() {
+        return;
+    }
+    let entry = Entry {
+        timestamp: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0),
+        prompt: prompt.to_string(),
+    };
+
+    let path = history_path();
+    if let Some(parent) = path.parent() {
+        let _ = fs::create_dir_all(parent);
+    }
+    let appended = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut file| {
+            file.lock()?;
+            writeln!(file, "{}", serde_json::to_string(&entry).unwrap())?;
+            // Still under the lock, so no other process appends in between
+            let text = fs::read_to_string(&path)?;
+            let lines: Vec<&str> = text.lines().collect();
+            if lines.len() > 2 * settings.prompt_history {
+                let kept = &lines[lines.len() - settings.prompt_history..];
+                fs::write(&path, format!("{}\n", kept.join("\n")))?;
+            }
+            Ok(())
+        });
+    if let Err(e) = appended {
+        eprintln!("Could not write to {}: {}", path.display(), e);
+    }
+}
+
+// Newest first, each prompt only once
+fn entries() -> Vec<Entry> {
+    let Ok(file) = File::open(history_path()) else {
+        return vec![];
+    };
+    if file.lock_shared().is_err() {
+        return vec![];
+    }
+    let mut seen = HashSet::new();
+    fs::read_to_string(history_path())
+        .unwrap_or_default()
+        .lines()
+        .rev()
+        .filter_map(|line| serde_json::from_str::<Entry>(line).ok())
+        .filter(|entry| seen.insert(entry.prompt.clone()))
+        .collect()
+}
+
+// `--reuse`: fuzzy-picks a previous prompt and opens it for editing. None when cancelled.
+pub fn pick() -> Option<String> {
+    if !atty::is(Stream::Stdin) || !atty::is(Stream::Stderr) {
+        eprintln!("{}", epaint(Style::Error, "--reuse needs a terminal."));
+        return None;
+    }
+    let entries = entries();
+    if entries.is_empty() {
+        println!("No prompts in the history yet.");
+        return None;
+    }
+
+    let options: Vec<String> = entries
+        .iter()
+        .map(|entry| {
+            let (year, month, day) = calc::civil_from_days((entry.timestamp / 86400) as i64);
+            let first_line = entry.prompt.lines().next().unwrap_or("");
+            let mut shown: String = first_line.chars().take(100).collect();
+            if shown.len() < entry.prompt.trim_end().len() {
+                shown.push('…');
+            }
+            format!("{:04}-{:02}-{:02}  {}", year, month, day, shown)
+        })
+        .collect();
+    let index = FuzzySelect::with_theme(&*style::dialog_theme())
+        .with_prompt("Pick a prompt to reuse (type to filter)")
+        .default(0)
+        .items(&options)
+        .interact_opt()
+        .ok()
+        .flatten()?;
+
+    let prompt = &entries[index].prompt;
+    // Multi-line prompts are easier to change in the editor
+    let edited = if prompt.contains('\n')
+        && (std::env::var_os("VISUAL").is_some() || std::env::var_os("EDITOR").is_some())
+    {
+        dialoguer::Editor::new()
+            .extension(".md")
+            .edit(prompt)
+            .ok()
+            .flatten()
+    } else {
+        dialoguer::Input::<String>::with_theme(&*style::dialog_theme())
+            .with_prompt("Prompt")
+            .with_initial_text(prompt)
+            .interact_text()
+            .ok()
+    }?;
+    Some(edited).filter(|edited| !edited.trim().is_empty())
+}
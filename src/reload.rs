@@ -0,0 +1,142 @@
+use crate::style::{self, epaint, Style};
+use crate::{settings_path, Settings};
+use serde_json::{Map, Value};
+use std::fs;
+use std::time::SystemTime;
+
+// Settings that pick the provider or model. Changing them mid-conversation would send the
+// history somewhere it didn't come from, so they only apply to a new conversation.
+const CONVERSATION_KEYS: &[&str] = &[
+    "model",
+    "host",
+    "endpoint",
+    "api_key_variable",
+    "api_key_variables",
+    "api_keys",
+    "api_key_command",
+    "api_key_keyring",
+    "startup_message",
+    "transcript_name",
+    "session_scope",
+];
+
+// Watches ask.json during long-running sessions (agent mode) and applies edits between turns
+pub struct ConfigWatcher {
+    path: String,
+    modified: Option<SystemTime>,
+    loaded: Value, //The file as last read, which edits are told apart from
+    overrides: Map<String, Value>, //Values the session set itself (command line flags...), kept across reloads
+}
+
+fn modified_time(path: &str) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+fn load(path: &str) -> Result<Settings, String> {
+    fs::read_to_string(path)
+        .map_err(|e| e.to_string())
+        .and_then(|contents| serde_json::from_str::<Settings>(&contents).map_err(|e| e.to_string()))
+}
+
+impl ConfigWatcher {
+    // `settings` are the session's, so whatever differs from the file was set on top of it
+    pub fn new(settings: &Settings) -> ConfigWatcher {
+        let path = settings_path();
+        let modified = modified_time(&path);
+        let loaded = serde_json::to_value(load(&path).unwrap_or_default()).unwrap();
+        let mut overrides = Map::new();
+        if let (Value::Object(current), Value::Object(file)) = (serde_json::to_value(settings).unwrap(), &loaded) {
+            for (key, value) in current {
+                if file.get(&key) != Some(&value) {
+                    overrides.insert(key, value);
+                }
+            }
+        }
+        ConfigWatcher {
+            path,
+            modified,
+            loaded,
+            overrides,
+        }
+    }
+
+    // Reloads the settings if the file changed since the last check. Only the keys edited in
+    // the file change, and never those the session overrides.
+    pub fn check(&mut self, settings: &mut Settings) {
+        let modified = modified_time(&self.path);
+        if modified == self.modified {
+            return;
+        }
+        self.modified = modified;
+
+        let reloaded = match load(&self.path) {
+            Ok(reloaded) => serde_json::to_value(reloaded).unwrap(),
+            Err(e) => {
+                eprintln!(
+                    "{}",
+                    epaint(Style::Warning, &format!("Config changed but could not be loaded ({}). Keeping the current settings.", e))
+                );
+                return;
+            }
+        };
+        let previous = std::mem::replace(&mut self.loaded, reloaded);
+        let (Value::Object(previous), Value::Object(fields), Value::Object(mut current)) =
+            (previous, &self.loaded, serde_json::to_value(&*settings).unwrap())
+        else {
+            return;
+        };
+
+        let mut applied = vec![];
+        let mut rejected = vec![];
+        let mut kept = vec![];
+        for (key, value) in fields {
+            if previous.get(key) == Some(value) {
+                continue;
+            }
+            if CONVERSATION_KEYS.contains(&key.as_str()) {
+                rejected.push(key.clone());
+            } else if self.overrides.contains_key(key) {
+                kept.push(key.clone());
+            } else {
+                current.insert(key.clone(), value.clone());
+                applied.push(key.clone());
+            }
+        }
+
+        if !rejected.is_empty() {
+            eprintln!(
+                "{}",
+                epaint(
+                    Style::Warning,
+                    &format!("Config reload: {} can only change in a new conversation (ask -c). Ignored for now.", rejected.join(", "))
+                )
+            );
+        }
+        if !kept.is_empty() {
+            eprintln!(
+                "{}",
+                epaint(
+                    Style::Warning,
+                    &format!("Config reload: {} overridden for this session (command line flags...), keeping the session's value.", kept.join(", "))
+                )
+            );
+        }
+        if applied.is_empty() {
+            return;
+        }
+
+        *settings = serde_json::from_value(Value::Object(current)).unwrap();
+        if applied.iter().any(|key| key == "theme") {
+            style::init(&settings.theme);
+        }
+        eprintln!(
+            "{}",
+            epaint(
+                Style::Success,
+                &format!("Config reloaded: {}", applied.join(", "))
+            )
+        );
+    }
+}
@@ -0,0 +1,81 @@
+use crate::conversation::Message;
+use crate::message_text;
+use serde::Serialize;
+use serde_json::Value;
+
+// Token counts of one request
+#[derive(Serialize, Clone, Copy, Default)]
+pub struct Usage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+// A provider's reply in the one shape the rest of ask reads. Every wire format gets an adapter
+// below that converts into it, so a new provider doesn't touch the code handling replies.
+pub struct ChatResponse {
+    pub message: Message,
+    pub finish_reason: String, //"stop", "length", "tool_calls"..., empty when not given
+    pub usage: Option<Usage>,
+}
+
+impl ChatResponse {
+    pub fn text(&self) -> &str {
+        message_text(&self.message.content)
+    }
+}
+
+// Picks the adapter by the shape of the response. Errors the provider reports come back as Err.
+pub fn normalize(data: &Value) -> Result<ChatResponse, String> {
+    if let Some(error) = data.get("error").filter(|error| !error.is_null()) {
+        let message = error["message"]
+            .as_str()
+            .or(error.as_str())
+            .map(str::to_string)
+            .unwrap_or_else(|| error.to_string());
+        return Err(format!("The provider returned an error: {}", message));
+    }
+    if data.get("choices").is_some() {
+        return from_chat_completions(data);
+    }
+    Err(format!(
+        "Error processing API return. Full response ahead:\n{}\n",
+        data
+    ))
+}
+
+// Token counts of any response, including endpoints without a chat reply (images)
+pub fn usage(data: &Value) -> Option<Usage> {
+    let usage = data.get("usage").filter(|usage| usage.is_object())?;
+    Some(Usage {
+        prompt_tokens: usage["prompt_tokens"].as_u64().unwrap_or(0),
+        completion_tokens: usage["completion_tokens"].as_u64().unwrap_or(0),
+    })
+}
+
+// OpenAI's /chat/completions, which OpenRouter, Groq, Ollama, vLLM, llama.cpp and the mock host
+// answer with too
+fn from_chat_completions(data: &Value) -> Result<ChatResponse, String> {
+    let choice = &data["choices"][0];
+    let Some(message) = choice.get("message") else {
+        return Err(format!(
+            "Error processing API return. Full response ahead:\n{}\n",
+            data
+        ));
+    };
+    let mut normalized = Message::new(
+        message["role"].as_str().unwrap_or("assistant"),
+        message.get("content").cloned().unwrap_or(Value::Null),
+    );
+    normalized.tool_calls = message
+        .get("tool_calls")
+        .filter(|calls| !calls.is_null())
+        .cloned();
+    Ok(ChatResponse {
+        message: normalized,
+        finish_reason: choice["finish_reason"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string(),
+        usage: usage(data),
+    })
+}
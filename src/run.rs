@@ -0,0 +1,135 @@
+use crate::calc;
+use crate::conversation::Message;
+use crate::filters;
+use crate::{complete, message_text, new_conversation, send_request, Settings};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read};
+use std::process;
+
+// Non-interactive one-shot for cron jobs and scripts: fills a template, sends it outside of any
+// transcript and prints the result as JSON. Exits nonzero on any failure.
+pub fn run_template(template: &str, vars: Vec<&String>, settings: &Settings) {
+    let fail = |error: String| -> ! {
+        println!(
+            "{}",
+            json!({"ok": false, "template": template, "error": error})
+        );
+        process::exit(1);
+    };
+
+    let source = match settings.templates.get(template) {
+        Some(source) => source.clone(),
+        None => fs::read_to_string(template).unwrap_or_else(|_| {
+            fail(format!(
+                "no template named '{}' in templates, and no such file",
+                template
+            ))
+        }),
+    };
+
+    let mut values = HashMap::from([("date".to_string(), calc::today())]);
+    for var in vars {
+        let (key, value) = var
+            .split_once('=')
+            .unwrap_or_else(|| fail(format!("--var expects key=value, got '{}'", var)));
+        // key=@path substitutes the file's contents
+        let value = match value.strip_prefix('@') {
+            Some(path) => fs::read_to_string(path)
+                .unwrap_or_else(|e| fail(format!("could not read {}: {}", path, e))),
+            None => value.to_string(),
+        };
+        values.insert(key.to_string(), value);
+    }
+    let prompt = render(&source, &values).unwrap_or_else(|missing| {
+        fail(format!("no value for {{{}}}; pass it with --var", missing))
+    });
+
+    let mut quiet = settings.clone();
+    quiet.show_spinner = false;
+    quiet.show_stats = false;
+
+    let mut state = new_conversation(&quiet);
+    state
+        .messages
+        .push(Message::new("user", Value::String(prompt)));
+    let response = send_request(&mut state, &quiet).unwrap_or_else(|| fail("request failed".to_string()));
+
+    match &response.message.content {
+        content if !content.is_null() => println!(
+            "{}",
+            json!({
+                "ok": true,
+                "template": template,
+                "model": state.model,
+                "output": filters::apply(message_text(content), &settings.output_filters)
+                    .unwrap_or_else(|e| fail(e)),
+                "usage": response.usage,
+            })
+        ),
+        _ => fail("the reply has no text".to_string()),
+    }
+}
+
+// Replaces {name} placeholders in one pass, so substituted text is never expanded again.
+// Braces around anything but a plain identifier (JSON, code) are left alone.
+fn render(template: &str, values: &HashMap<String, String>) -> Result<String, String> {
+    let mut output = String::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        output.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let name_length = after
+            .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .unwrap_or(after.len());
+        let is_placeholder = name_length > 0 && after[name_length..].starts_with('}');
+
+        if is_placeholder {
+            let name = &after[..name_length];
+            output.push_str(values.get(name).ok_or_else(|| name.to_string())?);
+            rest = &after[name_length + 1..];
+        } else {
+            output.push('{');
+            rest = after;
+        }
+    }
+
+    output.push_str(rest);
+    Ok(output)
+}
+
+// `--filter`: an editor range filter (`:'<,'>!ask --filter "..."` in vim). Reads the selection
+// on stdin and prints only the rewritten text, outside of any transcript. On failure the
+// selection is printed back unchanged, so the editor buffer never loses it.
+pub fn filter_selection(instruction: &str, settings: &Settings) {
+    let mut selection = String::new();
+    if io::stdin().read_to_string(&mut selection).is_err() || selection.trim().is_empty() {
+        eprintln!("--filter expects the text to transform on stdin");
+        print!("{}", selection);
+        process::exit(1);
+    }
+
+    let mut quiet = settings.clone();
+    quiet.show_spinner = false;
+    quiet.show_stats = false;
+    let prompt = format!(
+        "{}\n\nApply this to the text below. Reply with only the resulting text in a single code \
+         block, keeping its indentation, and no explanation.\n\n```\n{}\n```",
+        instruction,
+        selection.trim_end_matches('\n')
+    );
+
+    let Some(reply) = complete(prompt, &quiet) else {
+        print!("{}", selection);
+        process::exit(1);
+    };
+    // The code filter falls back to the whole reply when it has no code block
+    let mut output = filters::apply(&reply, &["code".to_string()]).unwrap_or(reply);
+    output.truncate(output.trim_end_matches('\n').len());
+    if selection.ends_with('\n') {
+        output.push('\n');
+    }
+    print!("{}", output);
+}
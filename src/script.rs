@@ -0,0 +1,353 @@
+use crate::conversation::{self, ConversationState};
+use crate::filters;
+use crate::memory;
+use crate::style::{epaint, Style};
+use crate::{message_text, new_conversation, perform_request, Settings};
+use serde::Deserialize;
+use serde_json::{Map, Number, Value};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+// A --script file: prompts sent in order within one conversation. Replies can be saved as
+// variables ({{name}} in later prompts) and steps can depend on what was said before.
+//
+//   vars:
+//     feature: CSV export
+//   steps:
+//     - prompt: Write a short spec for {{feature}}
+//       save: spec
+//     - prompt: Implement it in Rust
+//       filter: code
+//       save: code
+//     - prompt: Write tests for the code
+//       when:
+//         var: code
+//         not_contains: todo!()
+#[derive(Deserialize)]
+struct Script {
+    #[serde(default)]
+    vars: BTreeMap<String, Value>,
+    steps: Vec<Step>,
+}
+
+#[derive(Deserialize)]
+struct Step {
+    prompt: String,
+    #[serde(default)]
+    save: Option<String>, //Variable receiving the reply
+    #[serde(default)]
+    filter: Option<String>, //An output filter applied to the saved reply, e.g. code or json
+    #[serde(default)]
+    model: Option<String>, //For this step only
+    #[serde(default)]
+    when: Option<Condition>,
+}
+
+// Holds on the previous reply, or on `var` when given
+#[derive(Deserialize)]
+struct Condition {
+    #[serde(default)]
+    var: Option<String>,
+    #[serde(default)]
+    contains: Option<String>,
+    #[serde(default)]
+    not_contains: Option<String>,
+}
+
+impl Condition {
+    fn holds(&self, state: &ConversationState, last_reply: &str) -> bool {
+        let text = match &self.var {
+            Some(name) => state.variables.get(name).map(String::as_str).unwrap_or(""),
+            None => last_reply,
+        };
+        self.contains
+            .as_ref()
+            .is_none_or(|needle| text.contains(needle.as_str()))
+            && self
+                .not_contains
+                .as_ref()
+                .is_none_or(|needle| !text.contains(needle.as_str()))
+    }
+}
+
+// Runs the script in a new conversation at `transcript_path`, so it can be followed up on
+// afterwards. `assignments` are --var NAME=value, overriding the script's vars.
+pub fn run_script(
+    path: &str,
+    assignments: Vec<&String>,
+    transcript_path: &Path,
+    settings: &Settings,
+) -> Result<(), String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("Could not read {}: {}", path, e))?;
+    let value = if path.ends_with(".json") {
+        serde_json::from_str(&text).map_err(|e| format!("{}: {}", path, e))?
+    } else {
+        parse_yaml(&text).map_err(|e| format!("{}: {}", path, e))?
+    };
+    let script: Script = serde_json::from_value(value).map_err(|e| format!("{}: {}", path, e))?;
+
+    let mut state = memory::inject(new_conversation(settings), settings);
+    for (name, value) in script.vars {
+        let value = match value {
+            Value::String(text) => text,
+            other => other.to_string(),
+        };
+        state.variables.insert(name, value);
+    }
+    for assignment in assignments {
+        state.set_variable(assignment)?;
+    }
+    conversation::save(&mut state, transcript_path);
+
+    let mut last_reply = String::new();
+    let count = script.steps.len();
+    for (index, step) in script.steps.iter().enumerate() {
+        let header = format!("Step {}/{}", index + 1, count);
+        if let Some(condition) = &step.when {
+            if !condition.holds(&state, &last_reply) {
+                eprintln!(
+                    "{}",
+                    epaint(
+                        Style::Dim,
+                        &format!("{}: skipped, its condition doesn't hold", header)
+                    )
+                );
+                continue;
+            }
+        }
+        let prompt = state.expand(&step.prompt);
+        eprintln!(
+            "{}",
+            epaint(Style::Prompt, &format!("{}: {}", header, prompt))
+        );
+
+        let model = state.model.clone();
+        if let Some(step_model) = &step.model {
+            state.model = step_model.clone();
+        }
+        let messages = state.messages.len();
+        perform_request(
+            Value::String(prompt),
+            &mut state,
+            transcript_path,
+            "",
+            settings,
+        );
+        state.model = model;
+        if state.messages.len() <= messages + 1 {
+            return Err(format!("{} failed, stopping the script", header));
+        }
+
+        last_reply = message_text(&state.messages.last().unwrap().content).to_string();
+        if let Some(name) = &step.save {
+            let value = match &step.filter {
+                Some(filter) => filters::apply(&last_reply, std::slice::from_ref(filter))
+                    .map_err(|e| format!("{}: {}", header, e))?,
+                None => last_reply.clone(),
+            };
+            state.variables.insert(name.clone(), value);
+        }
+    }
+    conversation::save(&mut state, transcript_path);
+    Ok(())
+}
+
+// Enough YAML for scripts: block mappings and sequences, plain and quoted scalars, | and >
+// block text, [flow] lists in JSON syntax and # comments. Anchors, tags and multi-document
+// files are not supported.
+fn parse_yaml(text: &str) -> Result<Value, String> {
+    let mut lines: Vec<(usize, usize, String)> = text
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+        .filter(|(_, line)| line.trim() != "---")
+        .map(|(number, line)| {
+            let indent = line.len() - line.trim_start().len();
+            (number + 1, indent, line.trim_start().to_string())
+        })
+        .collect();
+    let raw: Vec<&str> = text.lines().collect();
+    let mut position = 0;
+    let value = parse_node(&mut lines, &raw, &mut position, 0)?;
+    match lines.get(position) {
+        Some((number, _, _)) => Err(format!("line {}: unexpected indentation", number)),
+        None => Ok(value),
+    }
+}
+
+fn parse_node(
+    lines: &mut Vec<(usize, usize, String)>,
+    raw: &[&str],
+    position: &mut usize,
+    min_indent: usize,
+) -> Result<Value, String> {
+    let Some((_, indent, content)) = lines.get(*position).cloned() else {
+        return Ok(Value::Null);
+    };
+    if indent < min_indent {
+        return Ok(Value::Null);
+    }
+
+    if content == "-" || content.starts_with("- ") {
+        let mut items = vec![];
+        while let Some((_, item_indent, item)) = lines.get(*position).cloned() {
+            if item_indent != indent || !(item == "-" || item.starts_with("- ")) {
+                break;
+            }
+            let rest = item[1..].trim_start().to_string();
+            if rest.is_empty() {
+                *position += 1;
+                items.push(parse_node(lines, raw, position, indent + 1)?);
+            } else {
+                // `- key: value` starts a mapping indented past the dash
+                let column = indent + item.len() - rest.len();
+                lines[*position].1 = column;
+                lines[*position].2 = rest;
+                items.push(parse_node(lines, raw, position, column)?);
+            }
+        }
+        return Ok(Value::Array(items));
+    }
+
+    if key_value(&content).is_none() {
+        *position += 1;
+        return scalar(strip_comment(&content));
+    }
+
+    let mut map = Map::new();
+    while let Some((number, entry_indent, entry)) = lines.get(*position).cloned() {
+        if entry_indent != indent {
+            if entry_indent > indent {
+                return Err(format!("line {}: unexpected indentation", number));
+            }
+            break;
+        }
+        let (key, value) =
+            key_value(&entry).ok_or_else(|| format!("line {}: expected key: value", number))?;
+        *position += 1;
+        let value = strip_comment(value);
+        let parsed = if value.is_empty() {
+            // A nested block, or a sequence written at the key's own indentation
+            match lines.get(*position) {
+                Some((_, next, item))
+                    if *next > indent || (*next == indent && item.starts_with('-')) =>
+                {
+                    parse_node(lines, raw, position, indent)?
+                }
+                _ => Value::Null,
+            }
+        } else if value.starts_with('|') || value.starts_with('>') {
+            Value::String(block_text(lines, raw, position, indent, value))
+        } else {
+            scalar(value)?
+        };
+        map.insert(unquote(key), parsed);
+    }
+    Ok(Value::Object(map))
+}
+
+// `key: value` or `key:`, with the colon outside of quotes
+fn key_value(line: &str) -> Option<(&str, &str)> {
+    let mut quote = None;
+    for (index, c) in line.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(open), c) if c == open => quote = None,
+            (None, ':') => {
+                let rest = &line[index + 1..];
+                if rest.is_empty() || rest.starts_with(' ') {
+                    return Some((line[..index].trim(), rest.trim()));
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+fn strip_comment(value: &str) -> &str {
+    if value.starts_with('"') || value.starts_with('\'') {
+        return value;
+    }
+    match value.find(" #") {
+        Some(index) => value[..index].trim_end(),
+        None => value,
+    }
+}
+
+// The lines of a | (kept as written) or > (folded into one line) block, taken from the raw
+// text so blank lines and comment-looking lines inside it survive
+fn block_text(
+    lines: &[(usize, usize, String)],
+    raw: &[&str],
+    position: &mut usize,
+    indent: usize,
+    style: &str,
+) -> String {
+    let Some(&(first, block_indent, _)) = lines.get(*position).filter(|line| line.1 > indent)
+    else {
+        return String::new();
+    };
+    let mut body: Vec<&str> = vec![];
+    let mut number = first;
+    while let Some(line) = raw.get(number - 1) {
+        let line_indent = line.len() - line.trim_start().len();
+        if !line.trim().is_empty() && line_indent < block_indent {
+            break;
+        }
+        body.push(line.get(block_indent..).unwrap_or(""));
+        number += 1;
+    }
+    while body.last().is_some_and(|line| line.trim().is_empty()) {
+        body.pop();
+    }
+    while lines.get(*position).is_some_and(|line| line.0 < number) {
+        *position += 1;
+    }
+
+    let mut text = if style.starts_with('>') {
+        body.iter()
+            .map(|line| line.trim())
+            .collect::<Vec<&str>>()
+            .join(" ")
+    } else {
+        body.join("\n")
+    };
+    if !style.contains('-') {
+        text.push('\n');
+    }
+    text
+}
+
+fn unquote(text: &str) -> String {
+    match scalar(text) {
+        Ok(Value::String(text)) => text,
+        _ => text.to_string(),
+    }
+}
+
+fn scalar(text: &str) -> Result<Value, String> {
+    let text = text.trim();
+    if text.starts_with('"') {
+        return serde_json::from_str(text).map_err(|e| format!("bad string {}: {}", text, e));
+    }
+    if let Some(inner) = text.strip_prefix('\'').and_then(|t| t.strip_suffix('\'')) {
+        return Ok(Value::String(inner.replace("''", "'")));
+    }
+    if text.starts_with('[') || text.starts_with('{') {
+        return serde_json::from_str(text).map_err(|e| format!("bad flow value {}: {}", text, e));
+    }
+    Ok(match text {
+        "" | "~" | "null" => Value::Null,
+        "true" => Value::Bool(true),
+        "false" => Value::Bool(false),
+        _ => match text.parse::<f64>().ok().and_then(Number::from_f64) {
+            Some(number) if !text.starts_with('+') => match text.parse::<i64>() {
+                Ok(integer) => Value::from(integer),
+                Err(_) => Value::Number(number),
+            },
+            _ => Value::String(text.to_string()),
+        },
+    })
+}
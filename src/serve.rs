@@ -0,0 +1,339 @@
+use crate::agent;
+use crate::capabilities;
+use crate::lsp::LanguageServers;
+use crate::response;
+use crate::style::{epaint, Style};
+use crate::{post_json, Settings};
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+// How many tool results a single client request may feed back to the model
+const TOOL_ROUNDS: usize = 5;
+// Larger bodies are refused before anything is allocated for them
+const MAX_BODY: usize = 8 * 1024 * 1024;
+
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub host: String,
+    pub origin: Option<String>,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+// A request that can't be read, with the status to answer it with
+pub struct RequestError {
+    pub status: &'static str,
+    pub message: String,
+}
+
+impl From<String> for RequestError {
+    fn from(message: String) -> Self {
+        RequestError {
+            status: "400 Bad Request",
+            message,
+        }
+    }
+}
+
+impl From<&str> for RequestError {
+    fn from(message: &str) -> Self {
+        message.to_string().into()
+    }
+}
+
+pub fn read_request(stream: &TcpStream) -> Result<Request, RequestError> {
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader
+        .read_line(&mut request_line)
+        .map_err(|e| e.to_string())?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().ok_or("Empty request")?.to_string();
+    let path = parts.next().ok_or("Missing request path")?.to_string();
+
+    let mut length = 0;
+    let mut host = String::new();
+    let mut origin = None;
+    let mut headers = vec![];
+    loop {
+        let mut header = String::new();
+        reader.read_line(&mut header).map_err(|e| e.to_string())?;
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse().map_err(|_| "Invalid Content-Length")?;
+            } else if name.eq_ignore_ascii_case("host") {
+                host = value.trim().to_string();
+            } else if name.eq_ignore_ascii_case("origin") {
+                origin = Some(value.trim().to_string());
+            }
+            headers.push((name.trim().to_string(), value.trim().to_string()));
+        }
+    }
+
+    if length > MAX_BODY {
+        return Err(RequestError {
+            status: "413 Payload Too Large",
+            message: format!(
+                "Request bodies are limited to {} MB",
+                MAX_BODY / 1024 / 1024
+            ),
+        });
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).map_err(|e| e.to_string())?;
+    Ok(Request {
+        method,
+        path,
+        host,
+        origin,
+        headers,
+        body,
+    })
+}
+
+// A page on another site could point its own host name at 127.0.0.1 (DNS rebinding) and talk
+// to a local server as same-origin, so only local host names are answered
+pub fn is_local_host(host: &str, port: u16) -> bool {
+    [format!("127.0.0.1:{}", port), format!("localhost:{}", port)].contains(&host.to_string())
+}
+
+// Browsers send Origin with cross-site requests. Editors and scripts don't send one at all.
+pub fn is_local_origin(origin: Option<&str>, port: u16) -> bool {
+    origin.is_none_or(|origin| {
+        ["http://", "https://"].iter().any(|scheme| {
+            origin
+                .strip_prefix(scheme)
+                .is_some_and(|host| is_local_host(host, port))
+        })
+    })
+}
+
+pub fn write_response(mut stream: &TcpStream, status: &str, content_type: &str, body: &str) {
+    let _ = write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+}
+
+pub fn error_body(message: &str) -> String {
+    json!({ "error": { "message": message, "type": "ask_error" } }).to_string()
+}
+
+// Forwards the request to the configured provider. Replies that use one of the approval-free
+// agent directives are answered locally and the conversation continues, so the client only
+// ever sees the final answer.
+fn complete(mut body: Value, settings: &Settings) -> Result<Value, String> {
+    if !body["messages"].is_array() {
+        return Err("The request has no messages".to_string());
+    }
+    if body["model"].is_null() {
+        body["model"] = json!(settings.model);
+    }
+    // The provider is always asked for the whole answer at once
+    if let Some(fields) = body.as_object_mut() {
+        fields.remove("stream");
+        fields.remove("stream_options");
+    }
+
+    // Clients bringing their own function tools get them untouched
+    let use_tools = settings.serve_tools && body.get("tools").is_none();
+    if use_tools {
+        let model = body["model"].as_str().unwrap_or_default().to_string();
+        let role = if capabilities::for_model(&model, &settings.model_capabilities).system_role {
+            "system"
+        } else {
+            "user"
+        };
+        let messages = body["messages"].as_array_mut().unwrap();
+        messages.insert(
+            0,
+            json!({ "role": role, "content": agent::UNATTENDED_HELP }),
+        );
+    }
+
+    let mut language_servers = LanguageServers::without_project_code();
+    let mut rounds = 0;
+    loop {
+        let response =
+            post_json(&settings.endpoint, &body, settings).ok_or("The provider request failed")?;
+        if !use_tools || rounds == TOOL_ROUNDS || response.get("error").is_some() {
+            return Ok(response);
+        }
+
+        // Clients get the provider's reply as it came, the tool loop only needs its text
+        let Ok(reply) = response::normalize(&response) else {
+            return Ok(response);
+        };
+        let content = reply.text().to_string();
+        match agent::run_unattended(&content, &mut language_servers, settings) {
+            Some(result) => {
+                let messages = body["messages"].as_array_mut().unwrap();
+                messages.push(json!({ "role": "assistant", "content": content }));
+                messages.push(json!({ "role": "user", "content": result }));
+                rounds += 1;
+            }
+            None => return Ok(response),
+        }
+    }
+}
+
+// Streaming clients get the whole answer as one chunk
+fn as_event_stream(response: &Value) -> String {
+    let created = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let choice = &response["choices"][0];
+    let chunk = json!({
+        "id": response["id"],
+        "object": "chat.completion.chunk",
+        "created": created,
+        "model": response["model"],
+        "choices": [{
+            "index": 0,
+            "delta": {
+                "role": "assistant",
+                "content": choice["message"]["content"],
+            },
+            "finish_reason": choice["finish_reason"],
+        }],
+    });
+    format!("data: {}\n\ndata: [DONE]\n\n", chunk)
+}
+
+// Status, content type and body for a request
+fn route(
+    request: &Request,
+    path: &str,
+    settings: &Settings,
+) -> (&'static str, &'static str, String) {
+    const JSON: &str = "application/json";
+    match (request.method.as_str(), path) {
+        ("GET", "/v1/models") => {
+            let models = json!({
+                "object": "list",
+                "data": [{ "id": settings.model, "object": "model", "owned_by": "ask" }],
+            });
+            ("200 OK", JSON, models.to_string())
+        }
+        ("POST", "/v1/chat/completions") => {
+            let body: Value = match serde_json::from_slice(&request.body) {
+                Ok(body) => body,
+                Err(e) => {
+                    return (
+                        "400 Bad Request",
+                        JSON,
+                        error_body(&format!("Invalid JSON: {}", e)),
+                    )
+                }
+            };
+            let streaming = body["stream"].as_bool().unwrap_or(false);
+            match complete(body, settings) {
+                Ok(response) if response.get("error").is_some() => {
+                    ("502 Bad Gateway", JSON, response.to_string())
+                }
+                Ok(response) if streaming => {
+                    ("200 OK", "text/event-stream", as_event_stream(&response))
+                }
+                Ok(response) => ("200 OK", JSON, response.to_string()),
+                Err(e) => ("502 Bad Gateway", JSON, error_body(&e)),
+            }
+        }
+        _ => ("404 Not Found", JSON, error_body("Not found")),
+    }
+}
+
+fn handle(stream: TcpStream, port: u16, settings: &Settings) {
+    let started = Instant::now();
+    let request = match read_request(&stream) {
+        Ok(request) => request,
+        Err(e) => {
+            write_response(
+                &stream,
+                e.status,
+                "application/json",
+                &error_body(&e.message),
+            );
+            return;
+        }
+    };
+    // Requests spend the user's API key, so other sites open in the browser get nothing
+    if !is_local_host(&request.host, port) || !is_local_origin(request.origin.as_deref(), port) {
+        write_response(
+            &stream,
+            "403 Forbidden",
+            "application/json",
+            &error_body("Only local clients are answered"),
+        );
+        return;
+    }
+    let path = request.path.split('?').next().unwrap_or_default();
+    let (status, content_type, body) = route(&request, path, settings);
+    write_response(&stream, status, content_type, &body);
+    eprintln!(
+        "{} {} {} ({:.1}s)",
+        request.method,
+        path,
+        status,
+        started.elapsed().as_secs_f64()
+    );
+}
+
+// An OpenAI-compatible endpoint on localhost, answered through the configured provider
+pub fn serve(port: u16, settings: &Settings) {
+    let listener = match TcpListener::bind(("127.0.0.1", port)) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!(
+                "{}",
+                epaint(
+                    Style::Error,
+                    &format!("Could not listen on port {}: {}", port, e)
+                )
+            );
+            std::process::exit(1);
+        }
+    };
+    eprintln!(
+        "Serving {} at http://127.0.0.1:{}/v1 (Ctrl-C to stop)",
+        settings.model, port
+    );
+
+    let settings = Settings {
+        show_spinner: false,
+        ..settings.clone()
+    };
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                let settings = settings.clone();
+                thread::spawn(move || handle(stream, port, &settings));
+            }
+            Err(e) => eprintln!(
+                "{}",
+                epaint(Style::Warning, &format!("Connection failed: {}", e))
+            ),
+        }
+    }
+}
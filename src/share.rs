@@ -0,0 +1,270 @@
+use crate::conversation::{ConversationState, Message};
+use crate::outline::language_for;
+use crate::{describe_tool_call, message_id, message_text};
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+use tree_sitter::{Node, Parser};
+
+const STYLE: &str = "
+body { font: 15px/1.5 system-ui, sans-serif; max-width: 52rem; margin: 2rem auto; padding: 0 1rem; color: #1f2328; background: #fff; }
+h1 { font-size: 1.4rem; margin-bottom: 0.2rem; }
+.meta { color: #656d76; margin-top: 0; }
+.message { border: 1px solid #d0d7de; border-radius: 6px; margin: 1rem 0; padding: 0.6rem 1rem; }
+.user { background: #f6f8fa; }
+.role { font-weight: 600; font-size: 0.85rem; color: #656d76; }
+.role .id { font-weight: normal; font-family: monospace; }
+.text { white-space: pre-wrap; overflow-wrap: anywhere; }
+.text code { background: #eff1f3; padding: 0 0.2rem; border-radius: 3px; }
+pre { background: #f6f8fa; border: 1px solid #d0d7de; border-radius: 6px; padding: 0.6rem; overflow-x: auto; }
+img { max-width: 100%; border-radius: 6px; }
+details summary { cursor: pointer; color: #656d76; font-family: monospace; }
+.kw { color: #cf222e; } .str { color: #0a3069; } .com { color: #6e7781; font-style: italic; } .num { color: #0550ae; }
+@media (prefers-color-scheme: dark) {
+  body { color: #e6edf3; background: #0d1117; }
+  .message, pre { border-color: #30363d; } .user, pre { background: #161b22; } .text code { background: #262c36; }
+  .kw { color: #ff7b72; } .str { color: #a5d6ff; } .com { color: #8b949e; } .num { color: #79c0ff; }
+}
+";
+
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// `--share-html FILE`: the conversation as one static page with its images embedded, code
+// highlighted and tool calls folded away, to attach to a ticket or an email
+pub fn share_html(conversation_state: &ConversationState, path: &Path) -> Result<(), String> {
+    fs::write(path, render(conversation_state))
+        .map_err(|e| format!("Could not write {}: {}", path.display(), e))
+}
+
+pub fn render(conversation_state: &ConversationState) -> String {
+    let title = conversation_state
+        .title
+        .as_deref()
+        .unwrap_or("Conversation");
+    let mut meta = vec![escape(&conversation_state.model)];
+    if let Some(metadata) = &conversation_state.metadata {
+        meta.push(escape(&metadata.cwd));
+    }
+
+    let mut body = String::new();
+    for message in &conversation_state.messages {
+        body.push_str(&render_message(message));
+    }
+    format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n<title>{}</title>\n<style>{}</style>\n</head>\n<body>\n<h1>{}</h1>\n<p class=\"meta\">{}</p>\n{}</body>\n</html>\n",
+        escape(title),
+        STYLE,
+        escape(title),
+        meta.join(" · "),
+        body
+    )
+}
+
+fn render_message(message: &Message) -> String {
+    let role = match (&message.name, message.role.as_str()) {
+        (Some(name), "tool") => format!("tool: {}", name),
+        _ => message.role.clone(),
+    };
+    let header = format!(
+        "<div class=\"role\">{} <span class=\"id\">{}</span></div>\n",
+        escape(&role),
+        message_id(message)
+    );
+
+    // The system prompt and tool results are long and rarely what the reader is after
+    let text = message_text(&message.content);
+    if message.role == "system" || message.role == "tool" {
+        let lines = text.lines().count();
+        return format!(
+            "<section class=\"message {}\">\n<details><summary>{} ({} line{})</summary>\n<pre>{}</pre>\n</details>\n</section>\n",
+            escape(&message.role),
+            escape(&role),
+            lines,
+            if lines == 1 { "" } else { "s" },
+            escape(text)
+        );
+    }
+
+    let mut content = String::new();
+    match &message.content {
+        Value::Array(parts) => {
+            for part in parts {
+                if let Some(text) = part["text"].as_str() {
+                    content.push_str(&render_markdown(text));
+                }
+                // Pasted images are data: URLs already, so the page stays self-contained
+                if let Some(url) = part["image_url"]["url"].as_str() {
+                    content.push_str(&format!(
+                        "<p><img src=\"{}\" alt=\"image\"></p>\n",
+                        escape(url)
+                    ));
+                }
+            }
+        }
+        _ => content.push_str(&render_markdown(text)),
+    }
+    for call in message
+        .tool_calls
+        .iter()
+        .flat_map(|calls| calls.as_array())
+        .flatten()
+    {
+        let arguments = match &call["function"]["arguments"] {
+            Value::String(text) => serde_json::from_str::<Value>(text)
+                .map(|parsed| serde_json::to_string_pretty(&parsed).unwrap())
+                .unwrap_or_else(|_| text.clone()),
+            other => serde_json::to_string_pretty(other).unwrap(),
+        };
+        content.push_str(&format!(
+            "<details><summary>⚙ {}</summary>\n<pre>{}</pre>\n</details>\n",
+            escape(&describe_tool_call(call)),
+            escape(&arguments)
+        ));
+    }
+    format!(
+        "<section class=\"message {}\">\n{}{}</section>\n",
+        escape(&message.role),
+        header,
+        content
+    )
+}
+
+// Fenced code becomes highlighted <pre> blocks, the prose keeps its line breaks and gets
+// `code` and **bold** spans. That covers what replies use without a markdown parser.
+fn render_markdown(text: &str) -> String {
+    let mut html = String::new();
+    let mut prose: Vec<&str> = vec![];
+    let mut code: Option<(String, Vec<&str>)> = None;
+
+    let flush = |prose: &mut Vec<&str>, html: &mut String| {
+        let joined = prose.join("\n");
+        if !joined.trim().is_empty() {
+            html.push_str(&format!(
+                "<div class=\"text\">{}</div>\n",
+                inline(joined.trim_matches('\n'))
+            ));
+        }
+        prose.clear();
+    };
+    for line in text.lines() {
+        let fence = line.trim_start().strip_prefix("```");
+        match (code.take(), fence) {
+            (None, Some(info)) => {
+                flush(&mut prose, &mut html);
+                let language = info.split_whitespace().next().unwrap_or("").to_lowercase();
+                code = Some((language, vec![]));
+            }
+            (Some((language, lines)), Some(_)) => {
+                html.push_str(&format!(
+                    "<pre><code>{}</code></pre>\n",
+                    highlight(&lines.join("\n"), &language)
+                ));
+            }
+            (Some((language, mut lines)), None) => {
+                lines.push(line);
+                code = Some((language, lines));
+            }
+            (None, None) => prose.push(line),
+        }
+    }
+    // An unterminated block is still code
+    if let Some((language, lines)) = code {
+        html.push_str(&format!(
+            "<pre><code>{}</code></pre>\n",
+            highlight(&lines.join("\n"), &language)
+        ));
+    }
+    flush(&mut prose, &mut html);
+    html
+}
+
+fn inline(text: &str) -> String {
+    let mut html = String::new();
+    // Backticks split the text into prose and code spans, alternately
+    for (index, piece) in text.split('`').enumerate() {
+        if index % 2 == 1 {
+            html.push_str(&format!("<code>{}</code>", escape(piece)));
+            continue;
+        }
+        for (bold_index, part) in escape(piece).split("**").enumerate() {
+            if bold_index % 2 == 1 {
+                html.push_str(&format!("<strong>{}</strong>", part));
+            } else {
+                html.push_str(part);
+            }
+        }
+    }
+    html
+}
+
+// Syntax highlighting with the tree-sitter grammars ask has for outlines. Other languages
+// are shown plain.
+fn highlight(code: &str, language: &str) -> String {
+    let extension = match language {
+        "rust" | "rs" => "rs",
+        "python" | "py" => "py",
+        "javascript" | "js" | "jsx" | "node" => "js",
+        "go" | "golang" => "go",
+        _ => return escape(code),
+    };
+    let Some((grammar, _)) = language_for(Path::new(&format!("code.{}", extension))) else {
+        return escape(code);
+    };
+    let mut parser = Parser::new();
+    if parser.set_language(&grammar).is_err() {
+        return escape(code);
+    }
+    let Some(tree) = parser.parse(code, None) else {
+        return escape(code);
+    };
+
+    let mut html = String::new();
+    let mut position = 0;
+    spans(tree.root_node(), code, &mut position, &mut html);
+    html.push_str(&escape(&code[position..]));
+    html
+}
+
+fn spans(node: Node, code: &str, position: &mut usize, html: &mut String) {
+    let kind = node.kind();
+    let class = if kind.contains("comment") {
+        Some("com")
+    } else if node.is_named() && kind.contains("string") || kind == "char_literal" {
+        Some("str")
+    } else if node.child_count() == 0
+        && (kind.contains("integer") || kind.contains("float") || kind == "number")
+    {
+        Some("num")
+    } else if !node.is_named()
+        && kind.len() > 1
+        && kind.chars().all(|c| c.is_ascii_lowercase() || c == '_')
+    {
+        Some("kw")
+    } else {
+        None
+    };
+
+    if class.is_none() && node.child_count() > 0 {
+        let mut cursor = node.walk();
+        for child in node.children(&mut cursor) {
+            spans(child, code, position, html);
+        }
+        return;
+    }
+    let (start, end) = (node.start_byte(), node.end_byte());
+    if start < *position || end > code.len() {
+        return;
+    }
+    html.push_str(&escape(&code[*position..start]));
+    let text = escape(&code[start..end]);
+    match class {
+        Some(class) => html.push_str(&format!("<span class=\"{}\">{}</span>", class, text)),
+        None => html.push_str(&text),
+    }
+    *position = end;
+}
@@ -0,0 +1,52 @@
+use crate::conversation::ConversationState;
+use crate::style::{epaint, Style};
+use crate::Settings;
+
+// Leading `/command [argument]` words in a prompt. The first word that isn't one of these ends
+// the list, so prompts that merely start with a path (`/etc/hosts is empty?`) are left alone.
+//   /model <name>, /temp <n>, /tokens <n>   change the conversation, like --set
+//   /plain                                  strip markdown from this reply
+//   /lang <language>                        reply in that language this time
+//   /var NAME=value                         store a variable, like --var
+// Returns what is left of the prompt.
+pub fn apply(
+    prompt: &str,
+    conversation_state: &mut ConversationState,
+    settings: &mut Settings,
+) -> Result<String, String> {
+    let mut rest = prompt.trim_start();
+    loop {
+        let (word, after) = split_word(rest);
+        let (argument, after_argument) = split_word(after);
+        let change = match word {
+            "/model" | "/temp" | "/tokens" | "/lang" | "/var" if argument.is_empty() => {
+                return Err(format!("{} needs an argument", word))
+            }
+            "/model" => conversation_state.apply_override(&format!("model={}", argument))?,
+            "/temp" => conversation_state.apply_override(&format!("temperature={}", argument))?,
+            "/tokens" => conversation_state.apply_override(&format!("max_tokens={}", argument))?,
+            "/var" => conversation_state.set_variable(argument)?,
+            "/lang" => {
+                settings.reply_language = argument.to_string();
+                format!("Replying in {} this time.", argument)
+            }
+            "/plain" => {
+                settings.output_filters.push("strip-markdown".to_string());
+                rest = after;
+                eprintln!("{}", epaint(Style::Dim, "Plain text reply."));
+                continue;
+            }
+            _ => return Ok(rest.to_string()),
+        };
+        eprintln!("{}", epaint(Style::Dim, &change));
+        rest = after_argument;
+    }
+}
+
+fn split_word(text: &str) -> (&str, &str) {
+    let text = text.trim_start();
+    match text.find(char::is_whitespace) {
+        Some(end) => (&text[..end], text[end..].trim_start()),
+        None => (text, ""),
+    }
+}
@@ -0,0 +1,113 @@
+use atty::Stream;
+use dialoguer::theme::{ColorfulTheme, SimpleTheme, Theme as DialogTheme};
+use std::env;
+use std::sync::RwLock;
+
+// All terminal styling goes through here so colors can be themed or turned off in one place.
+
+#[derive(Clone, Copy)]
+pub enum Style {
+    Warning,
+    Error,
+    Success,
+    Prompt,
+    Command,
+    Dim,
+}
+
+struct Theme {
+    warning: &'static str,
+    error: &'static str,
+    success: &'static str,
+    prompt: &'static str,
+    command: &'static str,
+    dim: &'static str,
+}
+
+const DEFAULT_THEME: Theme = Theme {
+    warning: "\x1b[33m",
+    error: "\x1b[31m",
+    success: "\x1b[32m",
+    prompt: "\x1b[33;1m",
+    command: "\x1b[36m",
+    dim: "\x1b[2m",
+};
+
+// Darker tones that stay readable on white backgrounds
+const LIGHT_THEME: Theme = Theme {
+    warning: "\x1b[38;5;130m",
+    error: "\x1b[38;5;124m",
+    success: "\x1b[38;5;28m",
+    prompt: "\x1b[38;5;94;1m",
+    command: "\x1b[38;5;25m",
+    dim: "\x1b[38;5;244m",
+};
+
+// No colors, only weight/decoration
+const MONO_THEME: Theme = Theme {
+    warning: "\x1b[1m",
+    error: "\x1b[1m",
+    success: "\x1b[1m",
+    prompt: "\x1b[1m",
+    command: "\x1b[4m",
+    dim: "\x1b[2m",
+};
+
+const RESET: &str = "\x1b[0m";
+
+static THEME_NAME: RwLock<String> = RwLock::new(String::new());
+
+// Selects the theme, until a config reload picks another one. "none" disables styling altogether.
+pub fn init(theme_name: &str) {
+    *THEME_NAME.write().unwrap() = theme_name.to_string();
+}
+
+fn theme() -> Option<&'static Theme> {
+    match THEME_NAME.read().unwrap().as_str() {
+        "none" => None,
+        "light" => Some(&LIGHT_THEME),
+        "mono" => Some(&MONO_THEME),
+        _ => Some(&DEFAULT_THEME),
+    }
+}
+
+// Honors NO_COLOR (https://no-color.org) and skips styling when the stream isn't a terminal
+pub fn enabled(stream: Stream) -> bool {
+    let no_color = env::var("NO_COLOR").map(|v| !v.is_empty()).unwrap_or(false);
+    !no_color && theme().is_some() && atty::is(stream)
+}
+
+fn apply(style: Style, text: &str, stream: Stream) -> String {
+    let theme = match theme() {
+        Some(theme) if enabled(stream) => theme,
+        _ => return text.to_string(),
+    };
+    let code = match style {
+        Style::Warning => theme.warning,
+        Style::Error => theme.error,
+        Style::Success => theme.success,
+        Style::Prompt => theme.prompt,
+        Style::Command => theme.command,
+        Style::Dim => theme.dim,
+    };
+    format!("{}{}{}", code, text, RESET)
+}
+
+// Styles text headed for stdout
+pub fn paint(style: Style, text: &str) -> String {
+    apply(style, text, Stream::Stdout)
+}
+
+// Styles text headed for stderr
+pub fn epaint(style: Style, text: &str) -> String {
+    apply(style, text, Stream::Stderr)
+}
+
+// Theme for dialoguer prompts, plain when colors are off
+pub fn dialog_theme() -> Box<dyn DialogTheme> {
+    if enabled(Stream::Stderr) {
+        Box::new(ColorfulTheme::default())
+    } else {
+        Box::new(SimpleTheme)
+    }
+}
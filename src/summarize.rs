@@ -0,0 +1,70 @@
+use crate::conversation::{self, ConversationState, Message};
+use crate::style::{epaint, paint, Style};
+use crate::{archive_dir, complete, message_text, Settings};
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Summarizes the current conversation. With `compact`, the history is replaced by the summary
+// and the original transcript is kept in the archive.
+pub fn summarize(state: &mut ConversationState, transcript_path: &Path, compact: bool, settings: &Settings) {
+    // The first message is the startup prompt, sent as "user" to models without a system role
+    let history: Vec<String> = state
+        .messages
+        .iter()
+        .skip(1)
+        .filter(|message| message.role != "system")
+        .map(|message| format!("{}: {}", message.role, message_text(&message.content)))
+        .collect();
+    if history.is_empty() {
+        println!("Nothing to summarize yet.");
+        return;
+    }
+
+    let prompt = format!("{}\n\n{}", settings.summarize_template, history.join("\n\n"));
+    let summary = match complete(prompt, settings) {
+        Some(summary) => summary,
+        None => return,
+    };
+    println!("{}", summary);
+
+    if !compact {
+        return;
+    }
+
+    // Keep the original around before throwing its messages away
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let archive = archive_dir(settings);
+    let file_name = format!(
+        "{}-{}",
+        transcript_path.file_name().unwrap().to_string_lossy(),
+        timestamp
+    );
+    if let Err(e) = fs::create_dir_all(&archive).and_then(|_| fs::copy(transcript_path, archive.join(&file_name))) {
+        eprintln!("{}", epaint(Style::Error, &format!("Could not archive the original conversation: {}", e)));
+        return;
+    }
+
+    let startup_messages: Vec<Message> = state
+        .messages
+        .iter()
+        .enumerate()
+        .take_while(|(index, message)| *index == 0 || message.role == "system")
+        .map(|(_, message)| message.clone())
+        .collect();
+    state.messages = startup_messages;
+    state.messages.push(Message::new(
+        "user",
+        Value::String(format!("Summary of our conversation so far:\n\n{}", summary)),
+    ));
+    conversation::save(state, transcript_path);
+
+    println!(
+        "\n{}",
+        paint(
+            Style::Success,
+            &format!("History replaced by the summary. The original is archived as {}.", file_name)
+        )
+    );
+}
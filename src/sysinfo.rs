@@ -0,0 +1,142 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread;
+
+const PACKAGE_MANAGERS: &[&str] = &[
+    "apt", "dnf", "yum", "pacman", "zypper", "apk", "emerge", "nix", "brew", "port", "snap",
+    "flatpak", "cargo", "npm", "pnpm", "yarn", "pip", "pipx", "uv", "go", "gem",
+];
+
+// Program and the arguments printing its version
+const TOOLCHAINS: &[(&str, &[&str])] = &[
+    ("rustc", &["--version"]),
+    ("cargo", &["--version"]),
+    ("node", &["--version"]),
+    ("python3", &["--version"]),
+    ("go", &["version"]),
+    ("java", &["-version"]),
+    ("gcc", &["--version"]),
+    ("clang", &["--version"]),
+    ("docker", &["--version"]),
+    ("git", &["--version"]),
+];
+
+fn on_path(program: &str) -> bool {
+    env::var_os("PATH")
+        .map(|paths| env::split_paths(&paths).any(|dir| dir.join(program).is_file()))
+        .unwrap_or(false)
+}
+
+fn version_of(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .ok()?;
+    // Some tools (java) print their version on stderr
+    let text = if output.stdout.is_empty() {
+        output.stderr
+    } else {
+        output.stdout
+    };
+    String::from_utf8_lossy(&text)
+        .lines()
+        .next()
+        .map(|line| line.trim().to_string())
+}
+
+fn os_name() -> String {
+    fs::read_to_string("/etc/os-release")
+        .ok()
+        .and_then(|release| {
+            release
+                .lines()
+                .find_map(|line| line.strip_prefix("PRETTY_NAME="))
+                .map(|name| name.trim_matches('"').to_string())
+        })
+        .or_else(|| version_of("sw_vers", &["-productVersion"]).map(|v| format!("macOS {}", v)))
+        .unwrap_or_else(|| env::consts::OS.to_string())
+}
+
+// "MemTotal:  16318508 kB" => "15.6 GiB"
+fn meminfo(field: &str) -> Option<String> {
+    let meminfo = fs::read_to_string("/proc/meminfo").ok()?;
+    let kilobytes: f64 = meminfo
+        .lines()
+        .find_map(|line| line.strip_prefix(field))?
+        .trim_start_matches(':')
+        .split_whitespace()
+        .next()?
+        .parse()
+        .ok()?;
+    Some(format!("{:.1} GiB", kilobytes / 1024.0 / 1024.0))
+}
+
+fn cpu_model() -> Option<String> {
+    fs::read_to_string("/proc/cpuinfo")
+        .ok()?
+        .lines()
+        .find_map(|line| {
+            line.strip_prefix("model name")
+                .map(|rest| rest.trim_start_matches([' ', '\t', ':']).to_string())
+        })
+}
+
+// A one-shot description of the machine, so the agent doesn't have to probe it command by command
+pub fn describe() -> String {
+    let mut lines = vec![
+        format!("OS: {}", os_name()),
+        format!(
+            "Kernel: {} ({})",
+            version_of("uname", &["-sr"]).unwrap_or_else(|| env::consts::OS.to_string()),
+            env::consts::ARCH
+        ),
+        format!(
+            "Shell: {}",
+            env::var("SHELL").unwrap_or_else(|_| "unknown".to_string())
+        ),
+        format!(
+            "CPU: {} ({} threads)",
+            cpu_model().unwrap_or_else(|| "unknown".to_string()),
+            thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(1)
+        ),
+    ];
+    if let (Some(total), Some(available)) = (meminfo("MemTotal"), meminfo("MemAvailable")) {
+        lines.push(format!("Memory: {} total, {} available", total, available));
+    }
+    if let Ok(cwd) = env::current_dir() {
+        lines.push(format!("Working directory: {}", cwd.display()));
+        if Path::new(".git").exists() {
+            lines.push("The working directory is a git repository".to_string());
+        }
+    }
+
+    let managers: Vec<&str> = PACKAGE_MANAGERS
+        .iter()
+        .copied()
+        .filter(|m| on_path(m))
+        .collect();
+    lines.push(format!(
+        "Package managers: {}",
+        if managers.is_empty() {
+            "none found".to_string()
+        } else {
+            managers.join(", ")
+        }
+    ));
+
+    lines.push("Toolchains:".to_string());
+    for (program, args) in TOOLCHAINS {
+        if on_path(program) {
+            if let Some(version) = version_of(program, args) {
+                lines.push(format!("  {}: {}", program, version));
+            }
+        }
+    }
+
+    lines.join("\n")
+}
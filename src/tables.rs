@@ -0,0 +1,192 @@
+use crate::style::{paint, Style};
+use serde_json::Value;
+
+#[derive(Clone, Copy)]
+enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+// Rewrites the markdown tables in a reply as aligned columns that fit in `width`, and a reply
+// that is only a JSON array of objects as a table of them. Everything else is left untouched.
+pub fn render(text: &str, width: usize) -> String {
+    if let Some((header, rows)) = json_table(text) {
+        return layout(&header, &rows, &vec![Align::Left; header.len()], width);
+    }
+
+    let lines: Vec<&str> = text.lines().collect();
+    let mut out: Vec<String> = vec![];
+    let mut index = 0;
+    let mut in_code = false;
+    while index < lines.len() {
+        let line = lines[index];
+        if line.trim_start().starts_with("```") {
+            in_code = !in_code;
+        }
+        let aligns = lines.get(index + 1).and_then(|next| separator(next));
+        match (in_code, aligns) {
+            (false, Some(aligns)) if line.trim_start().starts_with('|') => {
+                let header = cells(line);
+                let mut rows = vec![];
+                index += 2;
+                while index < lines.len() && lines[index].trim_start().starts_with('|') {
+                    rows.push(cells(lines[index]));
+                    index += 1;
+                }
+                out.push(layout(&header, &rows, &aligns, width));
+            }
+            _ => {
+                out.push(line.to_string());
+                index += 1;
+            }
+        }
+    }
+    let mut rendered = out.join("\n");
+    if text.ends_with('\n') {
+        rendered.push('\n');
+    }
+    rendered
+}
+
+fn cells(line: &str) -> Vec<String> {
+    let line = line.trim();
+    let line = line.strip_prefix('|').unwrap_or(line);
+    let line = line.strip_suffix('|').unwrap_or(line);
+    line.split('|')
+        .map(|cell| cell.trim().to_string())
+        .collect()
+}
+
+// The alignment row under a table header (`|---|:---:|--:|`)
+fn separator(line: &str) -> Option<Vec<Align>> {
+    if !line.trim_start().starts_with('|') {
+        return None;
+    }
+    cells(line)
+        .iter()
+        .map(|cell| {
+            let dashes = cell.trim_matches(':');
+            if dashes.is_empty() || !dashes.chars().all(|c| c == '-') {
+                return None;
+            }
+            Some(match (cell.starts_with(':'), cell.ends_with(':')) {
+                (true, true) => Align::Center,
+                (false, true) => Align::Right,
+                _ => Align::Left,
+            })
+        })
+        .collect()
+}
+
+fn json_table(text: &str) -> Option<(Vec<String>, Vec<Vec<String>>)> {
+    let Value::Array(items) = serde_json::from_str(text.trim()).ok()? else {
+        return None;
+    };
+    let mut header: Vec<String> = vec![];
+    for item in &items {
+        for key in item.as_object()?.keys() {
+            if !header.contains(key) {
+                header.push(key.clone());
+            }
+        }
+    }
+    if header.is_empty() {
+        return None;
+    }
+    let rows = items
+        .iter()
+        .map(|item| {
+            header
+                .iter()
+                .map(|key| match &item[key] {
+                    Value::Null => String::new(),
+                    Value::String(text) => text.clone(),
+                    value => value.to_string(),
+                })
+                .collect()
+        })
+        .collect();
+    Some((header, rows))
+}
+
+fn pad(text: &str, width: usize, align: Align) -> String {
+    let count = text.chars().count();
+    let text = if count > width {
+        // Cut cells get an ellipsis so they don't pass for the whole value
+        let mut cut: String = text.chars().take(width.saturating_sub(1)).collect();
+        cut.push('…');
+        cut
+    } else {
+        text.to_string()
+    };
+    let gap = width.saturating_sub(text.chars().count());
+    match align {
+        Align::Left => format!("{}{}", text, " ".repeat(gap)),
+        Align::Right => format!("{}{}", " ".repeat(gap), text),
+        Align::Center => format!(
+            "{}{}{}",
+            " ".repeat(gap / 2),
+            text,
+            " ".repeat(gap - gap / 2)
+        ),
+    }
+}
+
+fn layout(header: &[String], rows: &[Vec<String>], aligns: &[Align], width: usize) -> String {
+    let columns = header.len();
+    let mut widths: Vec<usize> = (0..columns)
+        .map(|column| {
+            std::iter::once(header)
+                .chain(rows.iter().map(|row| row.as_slice()))
+                .filter_map(|row| row.get(column))
+                .map(|cell| cell.chars().count())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+
+    // Narrow the widest column until the table fits, down to a few characters each
+    let separators = 3 * columns.saturating_sub(1);
+    while widths.iter().sum::<usize>() + separators > width {
+        let (widest, &current) = widths
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, width)| **width)
+            .unwrap();
+        if current <= 4 {
+            break;
+        }
+        widths[widest] = current - 1;
+    }
+
+    let line = |row: &[String], bold: bool| {
+        (0..columns)
+            .map(|column| {
+                let align = aligns.get(column).copied().unwrap_or(Align::Left);
+                let cell = pad(
+                    row.get(column).map(String::as_str).unwrap_or(""),
+                    widths[column],
+                    align,
+                );
+                if bold {
+                    paint(Style::Prompt, &cell)
+                } else {
+                    cell
+                }
+            })
+            .collect::<Vec<String>>()
+            .join(&paint(Style::Dim, " │ "))
+    };
+    let rule = widths
+        .iter()
+        .map(|width| "─".repeat(*width))
+        .collect::<Vec<String>>()
+        .join("─┼─");
+
+    std::iter::once(line(header, true))
+        .chain(std::iter::once(paint(Style::Dim, &rule)))
+        .chain(rows.iter().map(|row| line(row, false)))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
@@ -0,0 +1,162 @@
+use serde_json::Value;
+use std::path::Path;
+use std::process::Output;
+
+// Test command detection and result parsing for the agent's TESTS directive. The agent gets a
+// compact summary instead of the full test log.
+
+const EXCERPT_LINES: usize = 30;
+const MAX_EXCERPTS: usize = 5;
+
+pub struct TestProject {
+    pub kind: &'static str,
+    pub command: String,
+}
+
+pub fn detect_project(filter: &str) -> Option<TestProject> {
+    let with_filter = |base: &str| {
+        if filter.is_empty() {
+            base.to_string()
+        } else {
+            format!("{} {}", base, filter)
+        }
+    };
+
+    if Path::new("Cargo.toml").exists() {
+        Some(TestProject {
+            kind: "cargo",
+            command: with_filter("cargo test"),
+        })
+    } else if Path::new("package.json").exists() {
+        let command = if filter.is_empty() {
+            "npm test".to_string()
+        } else {
+            format!("npm test -- {}", filter)
+        };
+        Some(TestProject {
+            kind: "npm",
+            command,
+        })
+    } else if Path::new("go.mod").exists() {
+        Some(TestProject {
+            kind: "go",
+            command: with_filter("go test ./..."),
+        })
+    } else if [
+        "pyproject.toml",
+        "pytest.ini",
+        "setup.py",
+        "setup.cfg",
+        "tox.ini",
+    ]
+    .iter()
+    .any(|file| Path::new(file).exists())
+    {
+        Some(TestProject {
+            kind: "pytest",
+            command: with_filter("python -m pytest"),
+        })
+    } else {
+        None
+    }
+}
+
+// Numbers that precede a word, e.g. "3 passed" in "= 1 failed, 3 passed in 0.1s ="
+fn count_before(line: &str, word: &str) -> u64 {
+    let words: Vec<&str> = line
+        .split(|c: char| c.is_whitespace() || c == ',' || c == ';')
+        .collect();
+    words
+        .windows(2)
+        .filter(|pair| pair[1].trim_end_matches(['.', ':']) == word)
+        .filter_map(|pair| pair[0].parse::<u64>().ok())
+        .sum()
+}
+
+pub fn summarize(project: &TestProject, output: &Output) -> Value {
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let log = format!("{}\n{}", stdout, stderr);
+    let lines: Vec<&str> = log.lines().collect();
+
+    let mut passed = 0;
+    let mut failed = 0;
+    let mut failing_tests: Vec<String> = vec![];
+    let mut excerpts: Vec<String> = vec![];
+
+    for (index, line) in lines.iter().enumerate() {
+        let trimmed = line.trim();
+        match project.kind {
+            "cargo" => {
+                if trimmed.starts_with("test result:") {
+                    passed += count_before(trimmed, "passed");
+                    failed += count_before(trimmed, "failed");
+                } else if let Some(name) = trimmed
+                    .strip_prefix("test ")
+                    .and_then(|rest| rest.strip_suffix(" ... FAILED"))
+                {
+                    failing_tests.push(name.to_string());
+                } else if trimmed.starts_with("---- ") && trimmed.ends_with(" ----") {
+                    excerpts.push(excerpt(&lines, index));
+                }
+            }
+            "npm" => {
+                // jest prints "Tests: 1 failed, 4 passed, 5 total", mocha "4 passing" / "1 failing"
+                if trimmed.starts_with("Tests:") {
+                    passed += count_before(trimmed, "passed");
+                    failed += count_before(trimmed, "failed");
+                } else if trimmed.ends_with("passing") || trimmed.contains(" passing (") {
+                    passed += count_before(trimmed, "passing");
+                } else if trimmed.ends_with("failing") {
+                    failed += count_before(trimmed, "failing");
+                } else if let Some(name) = trimmed.strip_prefix("✕ ") {
+                    failing_tests.push(name.to_string());
+                } else if trimmed.starts_with("● ") {
+                    excerpts.push(excerpt(&lines, index));
+                }
+            }
+            "go" => {
+                if let Some(name) = trimmed.strip_prefix("--- FAIL: ") {
+                    failing_tests.push(name.split_whitespace().next().unwrap_or(name).to_string());
+                    failed += 1;
+                    excerpts.push(excerpt(&lines, index));
+                } else if trimmed.starts_with("--- PASS: ") {
+                    passed += 1;
+                }
+            }
+            _ => {
+                if trimmed.starts_with('=')
+                    && (trimmed.contains(" passed") || trimmed.contains(" failed"))
+                {
+                    passed += count_before(trimmed, "passed");
+                    failed += count_before(trimmed, "failed");
+                } else if let Some(name) = trimmed.strip_prefix("FAILED ") {
+                    failing_tests.push(name.split(" - ").next().unwrap_or(name).to_string());
+                } else if trimmed.starts_with("____") && trimmed.ends_with("____") {
+                    excerpts.push(excerpt(&lines, index));
+                }
+            }
+        }
+    }
+
+    excerpts.truncate(MAX_EXCERPTS);
+
+    // Build failures produce no summary at all, so include the tail of the log instead
+    if passed == 0 && failed == 0 && !output.status.success() {
+        excerpts.push(lines[lines.len().saturating_sub(EXCERPT_LINES)..].join("\n"));
+    }
+
+    serde_json::json!({
+        "project": project.kind,
+        "command": project.command,
+        "exit_code": output.status.code(),
+        "passed": passed,
+        "failed": failed,
+        "failing_tests": failing_tests,
+        "excerpts": excerpts,
+    })
+}
+
+fn excerpt(lines: &[&str], start: usize) -> String {
+    lines[start..(start + EXCERPT_LINES).min(lines.len())].join("\n")
+}
@@ -0,0 +1,90 @@
+use crate::style::{epaint, Style};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs;
+use std::sync::{Mutex, OnceLock};
+
+// One request/response pair as it went over the wire
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Exchange {
+    pub host: String,
+    pub endpoint: String,
+    pub request: Value,
+    pub status: u16,
+    pub response: String, //Raw body, so malformed responses replay as they were received
+}
+
+enum Mode {
+    Record(String, Vec<Exchange>),
+    Replay(String, Vec<Exchange>),
+}
+
+static MODE: OnceLock<Mutex<Mode>> = OnceLock::new();
+
+pub fn record_to(path: &str) {
+    let _ = MODE.set(Mutex::new(Mode::Record(path.to_string(), vec![])));
+}
+
+pub fn replay_from(path: &str) -> Result<(), String> {
+    let contents =
+        fs::read_to_string(path).map_err(|e| format!("Could not read {}: {}", path, e))?;
+    let mut exchanges: Vec<Exchange> =
+        serde_json::from_str(&contents).map_err(|e| format!("Could not parse {}: {}", path, e))?;
+    exchanges.reverse();
+    let _ = MODE.set(Mutex::new(Mode::Replay(path.to_string(), exchanges)));
+    Ok(())
+}
+
+pub fn replaying() -> bool {
+    MODE.get()
+        .is_some_and(|mode| matches!(*mode.lock().unwrap(), Mode::Replay(..)))
+}
+
+// The next recorded exchange, in the order they were recorded
+pub fn next_replayed(endpoint: &str) -> Option<Result<Exchange, String>> {
+    let mut mode = MODE.get()?.lock().unwrap();
+    let Mode::Replay(path, exchanges) = &mut *mode else {
+        return None;
+    };
+    Some(match exchanges.pop() {
+        Some(exchange) if exchange.endpoint == endpoint => Ok(exchange),
+        Some(exchange) => Err(format!(
+            "{} expected a request to {} here, not {}",
+            path, exchange.endpoint, endpoint
+        )),
+        None => Err(format!("{} has no more recorded responses", path)),
+    })
+}
+
+fn redact(text: &str, secrets: &[String]) -> String {
+    secrets
+        .iter()
+        .filter(|secret| secret.len() >= 8)
+        .fold(text.to_string(), |text, secret| {
+            text.replace(secret.as_str(), "[REDACTED]")
+        })
+}
+
+// Appends the exchange and rewrites the file, so it stays valid even if ask is interrupted
+pub fn record(exchange: Exchange, secrets: &[String]) {
+    let Some(mode) = MODE.get() else {
+        return;
+    };
+    let mut mode = mode.lock().unwrap();
+    let Mode::Record(path, exchanges) = &mut *mode else {
+        return;
+    };
+
+    let request = serde_json::to_string(&exchange.request).unwrap();
+    exchanges.push(Exchange {
+        request: serde_json::from_str(&redact(&request, secrets)).unwrap_or(Value::Null),
+        response: redact(&exchange.response, secrets),
+        ..exchange
+    });
+    if let Err(e) = fs::write(&*path, serde_json::to_string_pretty(exchanges).unwrap()) {
+        eprintln!(
+            "{}",
+            epaint(Style::Error, &format!("Could not write {}: {}", path, e))
+        );
+    }
+}
@@ -0,0 +1,70 @@
+use serde_json::Value;
+use std::env;
+use std::fs;
+use std::os::unix::process;
+use std::path::PathBuf;
+use std::process::Command;
+
+// The hook writes the exit code on the first line and the command line after it.
+// Files are keyed by the shell's PID, which is ask's parent PID.
+pub fn last_command_path() -> PathBuf {
+    env::temp_dir().join(format!("ask_last_command-{}", process::parent_id()))
+}
+
+// Prints the hook that records each command and its exit status for --wtf
+pub fn print_shell_hook(shell: &str) {
+    let bash = r#"__ask_record_last() {
+    local code=$?
+    local cmd
+    cmd=$(HISTTIMEFORMAT= history 1 | sed 's/^ *[0-9]* *//')
+    printf '%s\n%s\n' "$code" "$cmd" > "${TMPDIR:-/tmp}/ask_last_command-$$"
+}
+PROMPT_COMMAND="__ask_record_last${PROMPT_COMMAND:+; $PROMPT_COMMAND}""#;
+
+    let zsh = r#"__ask_preexec() { __ask_cmd="$1"; }
+__ask_precmd() {
+    local code=$?
+    [ -n "$__ask_cmd" ] && printf '%s\n%s\n' "$code" "$__ask_cmd" > "${TMPDIR:-/tmp}/ask_last_command-$$"
+    unset __ask_cmd
+}
+autoload -Uz add-zsh-hook
+add-zsh-hook preexec __ask_preexec
+add-zsh-hook precmd __ask_precmd"#;
+
+    match shell {
+        "bash" => println!("{}", bash),
+        "zsh" => println!("{}", zsh),
+        _ => eprintln!("Unsupported shell '{}'. Choose bash or zsh.", shell),
+    }
+}
+
+// Builds the prompt explaining the last recorded command, or None when nothing was recorded
+pub fn build_prompt() -> Option<Value> {
+    let data = match fs::read_to_string(last_command_path()) {
+        Ok(data) => data,
+        Err(_) => {
+            eprintln!("No command recorded for this shell. Install the hook first:");
+            eprintln!("  eval \"$(ask --shell-hook bash)\"   # or zsh");
+            return None;
+        }
+    };
+
+    let mut lines = data.lines();
+    let exit_code = lines.next().unwrap_or("").trim().to_string();
+    let command = lines.collect::<Vec<&str>>().join("\n");
+
+    let os = Command::new("uname")
+        .arg("-sr")
+        .output()
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        .unwrap_or_else(|_| env::consts::OS.to_string());
+    let shell = env::var("SHELL").unwrap_or_default();
+    let cwd = env::current_dir()
+        .map(|dir| dir.display().to_string())
+        .unwrap_or_default();
+
+    Some(Value::String(format!(
+        "My last shell command failed. Explain the most likely cause and suggest a fix.\n\nCommand: {}\nExit code: {}\nOS: {}\nShell: {}\nWorking directory: {}",
+        command, exit_code, os, shell, cwd
+    )))
+}